@@ -1,8 +1,6 @@
 use clap::{Arg, Command};
-use env_logger;
-use plrs_xilinx::StreamFifoValue;
-use std::{process::ExitCode};
-use uio_rs::{self, Device};
+use plrs_xilinx::{Discovery, Error, Selector, StreamFifoValue};
+use std::process::ExitCode;
 
 fn main() -> ExitCode {
     env_logger::init();
@@ -13,6 +11,7 @@ fn main() -> ExitCode {
             Arg::new("device")
                 .short('d')
                 .long("device")
+                .help("UIO name, number, compatible string, base address, /dev/uioN or device tree path")
                 .required(true)
                 .action(clap::ArgAction::Set),
         )
@@ -51,26 +50,31 @@ fn main() -> ExitCode {
     let matches = cmd.get_matches();
     let device_name: &String = matches.get_one("device").unwrap();
 
-    let devices = uio_rs::DeviceDescription::enumerate();
-    let device = devices.iter().find(|d| d.name() == device_name);
-    let uio_number = if let Some(device) = device {
-        device.uio()
-    } else {
-        if let Ok(n) = device_name.parse::<u16>() {
-            n
-        } else {
-            u16::MAX
+    let selector = Selector::parse(device_name);
+    let info = match Discovery::default().find(&selector) {
+        Ok(info) => info,
+        Err(Error::NotFound(candidates)) => {
+            eprintln!("Failed to find UIO device {}, available devices:", device_name);
+            for candidate in candidates {
+                eprintln!("  {}", candidate);
+            }
+            return ExitCode::FAILURE;
+        }
+        Err(Error::Ambiguous(candidates)) => {
+            eprintln!("UIO device {} is ambiguous, matching devices:", device_name);
+            for candidate in candidates {
+                eprintln!("  {}", candidate);
+            }
+            return ExitCode::FAILURE;
+        }
+        Err(error) => {
+            eprintln!("Failed to find UIO device {}, {:?}", device_name, error);
+            return ExitCode::FAILURE;
         }
     };
 
-    if uio_number == u16::MAX {
-        eprintln!("Failed to find UIO device {}", device_name);
-        return ExitCode::FAILURE;
-    }
-
-    let mut device = Device::new(uio_number).expect("Failed to open UIO device");
-
     if *matches.get_one("interrupt").unwrap() {
+        let mut device = info.open().expect("Failed to open UIO device");
         device
             .interrupt_enable()
             .expect("Failed to enable interrupt");
@@ -80,7 +84,7 @@ fn main() -> ExitCode {
         println!("Interrupt {}", value);
     }
 
-    let mut fifo = plrs_xilinx::StreamFifo::try_from(&device, plrs_xilinx::StreamFifoValue::U64)
+    let mut fifo = plrs_xilinx::StreamFifo::try_from(&info, plrs_xilinx::StreamFifoValue::U64)
         .expect("Failed to load FIFO");
     let data_width = fifo.data_width();
 
@@ -138,7 +142,7 @@ fn main() -> ExitCode {
                                 let (_, hex) = text.split_at(2);
                                 u32::from_str_radix(hex, 16).unwrap_or(0)
                             } else {
-                                u32::from_str_radix(text, 10).unwrap_or(0)
+                                text.parse::<u32>().unwrap_or(0)
                             }
                         };
                         let mut write_value = v;
@@ -155,7 +159,7 @@ fn main() -> ExitCode {
                                 let (_, hex) = text.split_at(2);
                                 u64::from_str_radix(hex, 16).unwrap_or(0)
                             } else {
-                                u64::from_str_radix(text, 10).unwrap_or(0)
+                                text.parse::<u64>().unwrap_or(0)
                             }
                         };
                         let mut write_value = v;
@@ -172,7 +176,7 @@ fn main() -> ExitCode {
                                 let (_, hex) = text.split_at(2);
                                 u128::from_str_radix(hex, 16).unwrap_or(0)
                             } else {
-                                u128::from_str_radix(text, 10).unwrap_or(0)
+                                text.parse::<u128>().unwrap_or(0)
                             }
                         };
                         let mut write_value = v;
@@ -0,0 +1,325 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+mod sg;
+
+pub use sg::{CdmaDescriptorRing, MemoryCopy};
+
+/// Time to wait for a reset to complete
+const RESET_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// AXI Central DMA (PG034)
+///
+/// Memory to memory copies between bus addresses, in simple mode or in scatter gather mode when
+/// the core is built with the scatter gather engine.
+pub struct AxiCdma {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    address_width: u32,
+    length_width: u32,
+    keyhole: Control,
+    ring: Option<CdmaDescriptorRing>,
+}
+
+impl AxiCdma {
+    /// Creates a new `AxiCdma` instance from a UIO device.
+    ///
+    /// The address width and buffer length register width are taken from the `xlnx,addrwidth`
+    /// and `xlnx,sg-length-width` device tree properties when available.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiCdma, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut cdma = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node() {
+            if let Ok(width) = devicetree::read_u32(node, "xlnx,addrwidth") {
+                cdma.address_width = width;
+            }
+            if let Ok(width) = devicetree::read_u32(node, "xlnx,sg-length-width") {
+                cdma.set_length_width(width)?;
+            }
+        }
+        Ok(cdma)
+    }
+
+    /// Creates a new `AxiCdma` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt completion is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiCdma {
+        AxiCdma {
+            registers,
+            interrupt,
+            address_width: 32,
+            length_width: 26,
+            keyhole: Control::empty(),
+            ring: None,
+        }
+    }
+
+    /// Sets the address width, `C_M_AXI_ADDR_WIDTH`, in bits.
+    pub fn set_address_width(&mut self, bits: u32) {
+        self.address_width = bits;
+    }
+
+    /// Sets the bytes to transfer register width, in bits.
+    ///
+    /// Fails with [`Error::InvalidArgument`] for widths outside of 8 to 26 bits.
+    pub fn set_length_width(&mut self, bits: u32) -> Result<(), Error> {
+        if !(8..=26).contains(&bits) {
+            return Err(Error::InvalidArgument);
+        }
+        self.length_width = bits;
+        Ok(())
+    }
+
+    /// Returns the largest number of bytes in a single copy.
+    pub fn max_length(&self) -> usize {
+        (1usize << self.length_width) - 1
+    }
+
+    /// Resets the CDMA.
+    ///
+    /// Any copy in progress is abandoned and the core returns to simple mode. The key-hole
+    /// configuration is restored.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::CDMACR, Control::RESET)?;
+        let cdma = &self.registers;
+        wait_for(None, Some(RESET_TIMEOUT), || {
+            Ok((!cdma.read(Registers::CDMACR)?.reset()).then_some(()))
+        })?;
+        if let Some(ref mut ring) = self.ring {
+            ring.clear();
+        }
+        let keyhole = self.keyhole;
+        self.registers.modify(Registers::CDMACR, |control| {
+            control.set_sg_mode(false);
+            *control |= keyhole;
+        })
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<CdmaStatus, Error> {
+        self.registers.read(Registers::CDMASR)
+    }
+
+    /// Checks if the CDMA is idle, the last copy is complete.
+    pub fn is_idle(&self) -> Result<bool, Error> {
+        Ok(self.status()?.idle())
+    }
+
+    /// Configures key-hole reads and writes.
+    ///
+    /// With key-hole read the source address is fixed, with key-hole write the destination
+    /// address is fixed, such as a FIFO data port. The CDMA has to be idle.
+    pub fn set_keyhole(&mut self, read: bool, write: bool) -> Result<(), Error> {
+        if !self.is_idle()? {
+            return Err(Error::Busy);
+        }
+        let mut keyhole = Control::empty();
+        keyhole.set_keyhole_read(read);
+        keyhole.set_keyhole_write(write);
+        self.keyhole = keyhole;
+        self.registers.modify(Registers::CDMACR, |control| {
+            control.set_keyhole_read(read);
+            control.set_keyhole_write(write);
+        })
+    }
+
+    fn check_address(&self, address: u64) -> Result<(), Error> {
+        if self.address_width <= 32 && address > u64::from(u32::MAX) {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Starts a simple mode copy of `length` bytes from bus address `source` to bus address
+    /// `destination`.
+    ///
+    /// Use [`AxiCdma::wait`] for completion. Not available while in scatter gather mode.
+    pub fn copy_start(
+        &mut self,
+        source: u64,
+        destination: u64,
+        length: usize,
+    ) -> Result<(), Error> {
+        if length == 0 || length > self.max_length() {
+            return Err(Error::InvalidArgument);
+        }
+        self.check_address(source)?;
+        self.check_address(destination)?;
+        if self.ring.is_some() {
+            return Err(Error::Unsupported);
+        }
+        if !self.is_idle()? {
+            return Err(Error::Busy);
+        }
+        self.registers
+            .write(Registers::CDMASR, CdmaStatus::IRQ_ALL)?;
+        self.registers.modify(Registers::CDMACR, |control| {
+            *control |= Control::IOC_IRQ_ENABLE | Control::ERROR_IRQ_ENABLE;
+        })?;
+        self.registers.write(Registers::SA, source as u32)?;
+        self.registers.write(Registers::DA, destination as u32)?;
+        if self.address_width > 32 {
+            self.registers
+                .write(Registers::SA_MSB, (source >> 32) as u32)?;
+            self.registers
+                .write(Registers::DA_MSB, (destination >> 32) as u32)?;
+        }
+        log::debug!(
+            "copy {} bytes from {:#x} to {:#x}",
+            length,
+            source,
+            destination
+        );
+        // Writing the number of bytes to transfer starts the copy
+        self.registers.write(Registers::BTT, length as u32)
+    }
+
+    /// Waits for the simple mode copy to complete.
+    ///
+    /// Errors reported in the status register reset the CDMA and are returned.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let cdma = &mut self.registers;
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = cdma.read(Registers::CDMASR)?;
+            if let Some(error) = status.error() {
+                return Err(error);
+            }
+            if status.ioc_irq() || status.idle() {
+                cdma.write(Registers::CDMASR, CdmaStatus::IOC_IRQ)?;
+                return Ok(Some(()));
+            }
+            Ok(None)
+        });
+        if let Err(ref error) = result
+            && *error != Error::Timeout
+        {
+            log::warn!("copy failed, {:?}", error);
+            self.reset()?;
+        }
+        result
+    }
+
+    /// Copies `length` bytes from bus address `source` to bus address `destination` and waits
+    /// for completion.
+    pub fn copy(&mut self, source: u64, destination: u64, length: usize) -> Result<(), Error> {
+        self.copy_start(source, destination, length)?;
+        self.wait(None)
+    }
+}
+
+register_map! {
+    /// AXI CDMA registers
+    struct Registers {
+        /// CDMA control register
+        CDMACR: Control, RW @ 0x00 = 0x0001_0000;
+        /// CDMA status register
+        CDMASR: CdmaStatus, W1C @ 0x04 = 0x0000_0002;
+        /// Current descriptor pointer, lower 32 bits
+        CURDESC: u32, RW @ 0x08;
+        /// Current descriptor pointer, upper 32 bits
+        CURDESC_MSB: u32, RW @ 0x0c;
+        /// Tail descriptor pointer, lower 32 bits, writing starts the fetch
+        TAILDESC: u32, RW @ 0x10;
+        /// Tail descriptor pointer, upper 32 bits
+        TAILDESC_MSB: u32, RW @ 0x14;
+        /// Source address, lower 32 bits
+        SA: u32, RW @ 0x18;
+        /// Source address, upper 32 bits
+        SA_MSB: u32, RW @ 0x1c;
+        /// Destination address, lower 32 bits
+        DA: u32, RW @ 0x20;
+        /// Destination address, upper 32 bits
+        DA_MSB: u32, RW @ 0x24;
+        /// Bytes to transfer, writing starts the copy
+        BTT: u32, WO @ 0x28;
+    }
+}
+
+register_bits! {
+    /// CDMA control register
+    struct Control(u32) {
+        /// Soft reset
+        RESET: reset, set_reset: 2;
+        /// Scatter gather mode, only changed while idle
+        SG_MODE: sg_mode, set_sg_mode: 3;
+        /// Key-hole read, fixed source address
+        KEYHOLE_READ: keyhole_read, set_keyhole_read: 4;
+        /// Key-hole write, fixed destination address
+        KEYHOLE_WRITE: keyhole_write, set_keyhole_write: 5;
+        /// Cyclic buffer descriptor mode
+        CYCLIC_BD_ENABLE: cyclic_bd_enable, set_cyclic_bd_enable: 6;
+        /// Interrupt on complete enable
+        IOC_IRQ_ENABLE: ioc_irq_enable, set_ioc_irq_enable: 12;
+        /// Interrupt on delay timer enable
+        DELAY_IRQ_ENABLE: delay_irq_enable, set_delay_irq_enable: 13;
+        /// Interrupt on error enable
+        ERROR_IRQ_ENABLE: error_irq_enable, set_error_irq_enable: 14;
+        /// Interrupt coalescing threshold
+        IRQ_THRESHOLD: irq_threshold, set_irq_threshold: 23, 16;
+        /// Interrupt delay timeout
+        IRQ_DELAY: irq_delay, set_irq_delay: 31, 24;
+    }
+}
+
+register_bits! {
+    /// CDMA status register
+    pub struct CdmaStatus(u32) {
+        /// CDMA is idle
+        IDLE: idle, set_idle: 1;
+        /// Scatter gather engine is included
+        SG_INCLUDED: sg_included, set_sg_included: 3;
+        /// DMA internal error
+        DMA_INTERNAL_ERROR: dma_internal_error, set_dma_internal_error: 4;
+        /// DMA slave error
+        DMA_SLAVE_ERROR: dma_slave_error, set_dma_slave_error: 5;
+        /// DMA decode error
+        DMA_DECODE_ERROR: dma_decode_error, set_dma_decode_error: 6;
+        /// Scatter gather internal error
+        SG_INTERNAL_ERROR: sg_internal_error, set_sg_internal_error: 8;
+        /// Scatter gather slave error
+        SG_SLAVE_ERROR: sg_slave_error, set_sg_slave_error: 9;
+        /// Scatter gather decode error
+        SG_DECODE_ERROR: sg_decode_error, set_sg_decode_error: 10;
+        /// Interrupt on complete
+        IOC_IRQ: ioc_irq, set_ioc_irq: 12;
+        /// Interrupt on delay timer
+        DELAY_IRQ: delay_irq, set_delay_irq: 13;
+        /// Interrupt on error
+        ERROR_IRQ: error_irq, set_error_irq: 14;
+        /// Interrupt coalescing threshold count
+        IRQ_THRESHOLD: irq_threshold, set_irq_threshold: 23, 16;
+        /// Interrupt delay timer count
+        IRQ_DELAY: irq_delay, set_irq_delay: 31, 24;
+    }
+}
+
+impl CdmaStatus {
+    /// All interrupt bits
+    pub const IRQ_ALL: CdmaStatus =
+        CdmaStatus(CdmaStatus::IOC_IRQ.0 | CdmaStatus::DELAY_IRQ.0 | CdmaStatus::ERROR_IRQ.0);
+
+    /// Returns the first error reported by the status, if any.
+    pub fn error(&self) -> Option<Error> {
+        if self.dma_internal_error() {
+            Some(Error::DmaInternal)
+        } else if self.dma_slave_error() {
+            Some(Error::DmaSlave)
+        } else if self.dma_decode_error() {
+            Some(Error::DmaDecode)
+        } else if self.sg_internal_error() {
+            Some(Error::SgInternal)
+        } else if self.sg_slave_error() {
+            Some(Error::SgSlave)
+        } else if self.sg_decode_error() {
+            Some(Error::SgDecode)
+        } else {
+            None
+        }
+    }
+}
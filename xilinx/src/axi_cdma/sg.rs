@@ -0,0 +1,316 @@
+use super::{AxiCdma, CdmaStatus, Control, Registers};
+use crate::axi_dma::DESCRIPTOR_SIZE;
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use crate::mmio::Mmio;
+use crate::register::{RW, Register};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Bytes to transfer field of the descriptor control
+const LENGTH_MASK: u32 = 0x03ff_ffff;
+/// Largest batch, the interrupt coalescing threshold has 8 bits
+const MAX_BATCH: usize = 255;
+
+/// Memory to memory copy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryCopy {
+    /// Bus address to copy from
+    pub source: u64,
+    /// Bus address to copy to
+    pub destination: u64,
+    /// Length in bytes
+    pub length: usize,
+}
+
+impl MemoryCopy {
+    /// Creates a copy from source and destination bus addresses and length.
+    pub fn new(source: u64, destination: u64, length: usize) -> Self {
+        MemoryCopy {
+            source,
+            destination,
+            length,
+        }
+    }
+}
+
+/// Ring of CDMA buffer descriptors in memory reachable by the CDMA
+///
+/// The memory should be uncached, such as reserved memory mapped through `/dev/mem` or a UIO
+/// map. The CDMA processes one batch of descriptors at a time.
+pub struct CdmaDescriptorRing {
+    memory: Mmio,
+    count: usize,
+    head: usize,
+    first: usize,
+    pending: usize,
+}
+
+impl CdmaDescriptorRing {
+    /// Creates a ring filling the memory region and links the descriptors.
+    pub fn new(memory: Mmio) -> Result<CdmaDescriptorRing, Error> {
+        Self::with_count(memory, usize::MAX)
+    }
+
+    /// Creates a ring of at most `count` descriptors and links the descriptors.
+    pub fn with_count(mut memory: Mmio, count: usize) -> Result<CdmaDescriptorRing, Error> {
+        if !memory.address().is_multiple_of(DESCRIPTOR_SIZE as u64) {
+            return Err(Error::Misaligned);
+        }
+        let count = count.min(memory.size() / DESCRIPTOR_SIZE);
+        if count < 2 {
+            return Err(Error::InvalidArgument);
+        }
+        for index in 0..count {
+            let next = memory.address() + (((index + 1) % count) * DESCRIPTOR_SIZE) as u64;
+            let base = index * DESCRIPTOR_SIZE;
+            memory.write(Descriptor::NXTDESC.shifted(base), next as u32)?;
+            memory.write(Descriptor::NXTDESC_MSB.shifted(base), (next >> 32) as u32)?;
+            memory.write(Descriptor::CONTROL.shifted(base), 0)?;
+            memory.write(Descriptor::STATUS.shifted(base), DescriptorStatus::empty())?;
+        }
+        Ok(CdmaDescriptorRing {
+            memory,
+            count,
+            head: 0,
+            first: 0,
+            pending: 0,
+        })
+    }
+
+    /// Returns the number of descriptors in the ring.
+    pub fn capacity(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the number of descriptors handed to the CDMA and not yet completed.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    fn descriptor_address(&self, index: usize) -> u64 {
+        self.memory.address() + (index * DESCRIPTOR_SIZE) as u64
+    }
+
+    /// Forgets the descriptors in flight.
+    pub(super) fn clear(&mut self) {
+        self.head = 0;
+        self.first = 0;
+        self.pending = 0;
+    }
+
+    /// Writes the copy to the descriptor at `head` and advances `head`.
+    fn push(&mut self, copy: &MemoryCopy) -> Result<usize, Error> {
+        let index = self.head;
+        let base = index * DESCRIPTOR_SIZE;
+        let memory = &mut self.memory;
+        memory.write(Descriptor::SA.shifted(base), copy.source as u32)?;
+        memory.write(Descriptor::SA_MSB.shifted(base), (copy.source >> 32) as u32)?;
+        memory.write(Descriptor::DA.shifted(base), copy.destination as u32)?;
+        memory.write(
+            Descriptor::DA_MSB.shifted(base),
+            (copy.destination >> 32) as u32,
+        )?;
+        memory.write(Descriptor::STATUS.shifted(base), DescriptorStatus::empty())?;
+        memory.write(Descriptor::CONTROL.shifted(base), copy.length as u32)?;
+        self.head = (index + 1) % self.count;
+        self.pending += 1;
+        Ok(index)
+    }
+
+    /// Checks the descriptors of the batch, returns the number of bytes copied when all are
+    /// complete.
+    fn reclaim(&mut self) -> Result<Option<usize>, Error> {
+        let mut copied = 0;
+        for n in 0..self.pending {
+            let base = ((self.first + n) % self.count) * DESCRIPTOR_SIZE;
+            let status = self.memory.read(Descriptor::STATUS.shifted(base))?;
+            if let Some(error) = status.error() {
+                return Err(error);
+            }
+            if !status.complete() {
+                return Ok(None);
+            }
+            let control = self.memory.read(Descriptor::CONTROL.shifted(base))?;
+            copied += (control & LENGTH_MASK) as usize;
+        }
+        self.first = self.head;
+        self.pending = 0;
+        Ok(Some(copied))
+    }
+}
+
+impl AxiCdma {
+    /// Switches the CDMA to scatter gather mode with the descriptor ring.
+    ///
+    /// The CDMA has to be idle. Copies are queued with [`AxiCdma::sg_submit`].
+    pub fn sg_start(&mut self, mut ring: CdmaDescriptorRing) -> Result<(), Error> {
+        let status = self.status()?;
+        if !status.sg_included() {
+            return Err(Error::Unsupported);
+        }
+        if !status.idle() {
+            return Err(Error::Busy);
+        }
+        ring.clear();
+        self.ring = Some(ring);
+        Ok(())
+    }
+
+    /// Resets the CDMA back to simple mode and returns the descriptor ring.
+    ///
+    /// Any copy in flight is abandoned.
+    pub fn sg_stop(&mut self) -> Result<Option<CdmaDescriptorRing>, Error> {
+        self.reset()?;
+        Ok(self.ring.take())
+    }
+
+    /// Queues a batch of copies and starts the CDMA.
+    ///
+    /// The previous batch has to be complete, see [`AxiCdma::sg_wait`]. Fails with
+    /// [`Error::Full`] for more copies than the ring holds or more than 255 copies.
+    pub fn sg_submit(&mut self, copies: &[MemoryCopy]) -> Result<(), Error> {
+        if copies.is_empty() {
+            return Ok(());
+        }
+        for copy in copies {
+            if copy.length == 0 || copy.length > self.max_length() {
+                return Err(Error::InvalidArgument);
+            }
+            self.check_address(copy.source)?;
+            self.check_address(copy.destination)?;
+        }
+        let ring = match self.ring {
+            Some(ref mut ring) => ring,
+            None => return Err(Error::InvalidArgument),
+        };
+        if ring.pending > 0 {
+            return Err(Error::Busy);
+        }
+        if copies.len() > ring.capacity() || copies.len() > MAX_BATCH {
+            return Err(Error::Full);
+        }
+        let first = ring.descriptor_address(ring.head);
+        let mut last = 0;
+        for copy in copies {
+            last = ring.push(copy)?;
+        }
+        let tail = ring.descriptor_address(last);
+        // Toggling scatter gather mode restarts the descriptor fetch at the current descriptor
+        self.registers
+            .modify(Registers::CDMACR, |control| control.set_sg_mode(false))?;
+        self.registers
+            .write(Registers::CDMASR, CdmaStatus::IRQ_ALL)?;
+        // The interrupt fires once the whole batch is complete
+        let threshold = copies.len() as u32;
+        self.registers.modify(Registers::CDMACR, |control| {
+            *control |= Control::SG_MODE | Control::IOC_IRQ_ENABLE | Control::ERROR_IRQ_ENABLE;
+            control.set_irq_threshold(threshold);
+        })?;
+        log::debug!("queue {} copies, tail {:#x}", copies.len(), tail);
+        self.write_descriptor_pointer(Registers::CURDESC, Registers::CURDESC_MSB, first)?;
+        self.write_descriptor_pointer(Registers::TAILDESC, Registers::TAILDESC_MSB, tail)
+    }
+
+    fn write_descriptor_pointer(
+        &mut self,
+        low: Register<u32, RW>,
+        high: Register<u32, RW>,
+        address: u64,
+    ) -> Result<(), Error> {
+        if self.address_width > 32 {
+            self.registers.write(high, (address >> 32) as u32)?;
+        }
+        // Writing the lower half of the tail descriptor pointer starts the fetch
+        self.registers.write(low, address as u32)
+    }
+
+    /// Waits for the batch of copies to complete and returns the number of bytes copied.
+    ///
+    /// Errors reported in the status register or the descriptors reset the CDMA and are
+    /// returned.
+    pub fn sg_wait(&mut self, timeout: Option<Duration>) -> Result<usize, Error> {
+        let cdma = &mut self.registers;
+        let ring = match self.ring {
+            Some(ref mut ring) => ring,
+            None => return Err(Error::InvalidArgument),
+        };
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = cdma.read(Registers::CDMASR)?;
+            if let Some(error) = status.error() {
+                return Err(error);
+            }
+            cdma.write(
+                Registers::CDMASR,
+                CdmaStatus::IOC_IRQ | CdmaStatus::DELAY_IRQ,
+            )?;
+            if !status.idle() {
+                return Ok(None);
+            }
+            ring.reclaim()
+        });
+        if let Err(ref error) = result
+            && *error != Error::Timeout
+        {
+            log::warn!("scatter gather copy failed, {:?}", error);
+            self.reset()?;
+        }
+        result
+    }
+
+    /// Copies a batch of memory regions in scatter gather mode and waits for completion.
+    pub fn sg_copy(&mut self, copies: &[MemoryCopy]) -> Result<usize, Error> {
+        self.sg_submit(copies)?;
+        self.sg_wait(None)
+    }
+}
+
+register_map! {
+    /// Buffer descriptor fields, relative to the descriptor
+    struct Descriptor {
+        /// Next descriptor pointer, lower 32 bits
+        NXTDESC: u32, RW @ 0x00;
+        /// Next descriptor pointer, upper 32 bits
+        NXTDESC_MSB: u32, RW @ 0x04;
+        /// Source address, lower 32 bits
+        SA: u32, RW @ 0x08;
+        /// Source address, upper 32 bits
+        SA_MSB: u32, RW @ 0x0c;
+        /// Destination address, lower 32 bits
+        DA: u32, RW @ 0x10;
+        /// Destination address, upper 32 bits
+        DA_MSB: u32, RW @ 0x14;
+        /// Bytes to transfer
+        CONTROL: u32, RW @ 0x18;
+        /// Status, written by the CDMA
+        STATUS: DescriptorStatus, RW @ 0x1c;
+    }
+}
+
+register_bits! {
+    /// Buffer descriptor status
+    struct DescriptorStatus(u32) {
+        /// DMA internal error
+        INTERNAL_ERROR: internal_error, set_internal_error: 28;
+        /// DMA slave error
+        SLAVE_ERROR: slave_error, set_slave_error: 29;
+        /// DMA decode error
+        DECODE_ERROR: decode_error, set_decode_error: 30;
+        /// Descriptor completed
+        COMPLETE: complete, set_complete: 31;
+    }
+}
+
+impl DescriptorStatus {
+    fn error(&self) -> Option<Error> {
+        if self.internal_error() {
+            Some(Error::DmaInternal)
+        } else if self.slave_error() {
+            Some(Error::DmaSlave)
+        } else if self.decode_error() {
+            Some(Error::DmaDecode)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::register::{RW, Register, W1C};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+mod sg;
+
+pub use sg::{Coalesce, Completion, DESCRIPTOR_SIZE, DescriptorRing, Segment};
+
+/// Time to wait for a channel reset to complete
+const RESET_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// AXI DMA channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaChannel {
+    /// Memory-mapped to stream, transmit
+    Mm2s,
+    /// Stream to memory-mapped, receive
+    S2mm,
+}
+
+impl DmaChannel {
+    fn index(&self) -> usize {
+        match self {
+            DmaChannel::Mm2s => 0,
+            DmaChannel::S2mm => 1,
+        }
+    }
+
+    fn registers(&self) -> &'static ChannelRegisters {
+        match self {
+            DmaChannel::Mm2s => &MM2S,
+            DmaChannel::S2mm => &S2MM,
+        }
+    }
+}
+
+/// AXI DMA (PG021)
+///
+/// Supports direct register (simple) mode, and scatter gather mode when the core is built with
+/// the scatter gather engine. The buffers must be physically contiguous, the transfers are given
+/// as bus addresses.
+pub struct AxiDma {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    address_width: u32,
+    length_width: u32,
+    rings: [Option<DescriptorRing>; 2],
+}
+
+impl AxiDma {
+    /// Creates a new `AxiDma` instance from a UIO device.
+    ///
+    /// The address width and buffer length register width are taken from the `xlnx,addrwidth`
+    /// and `xlnx,sg-length-width` device tree properties when available.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiDma, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut dma = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node() {
+            if let Ok(width) = devicetree::read_u32(node, "xlnx,addrwidth") {
+                dma.address_width = width;
+            }
+            if let Ok(width) = devicetree::read_u32(node, "xlnx,sg-length-width") {
+                dma.set_length_width(width)?;
+            }
+        }
+        Ok(dma)
+    }
+
+    /// Creates a new `AxiDma` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt completion is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiDma {
+        AxiDma {
+            registers,
+            interrupt,
+            address_width: 32,
+            length_width: 26,
+            rings: [None, None],
+        }
+    }
+
+    /// Sets the address width, `C_M_AXI_MM2S_ADDR_WIDTH`, in bits.
+    pub fn set_address_width(&mut self, bits: u32) {
+        self.address_width = bits;
+    }
+
+    /// Sets the buffer length register width, `C_SG_LENGTH_WIDTH`, in bits.
+    ///
+    /// Fails with [`Error::InvalidArgument`] for widths outside of 8 to 26 bits.
+    pub fn set_length_width(&mut self, bits: u32) -> Result<(), Error> {
+        if !(8..=26).contains(&bits) {
+            return Err(Error::InvalidArgument);
+        }
+        self.length_width = bits;
+        Ok(())
+    }
+
+    /// Returns the largest number of bytes in a single transfer.
+    pub fn max_length(&self) -> usize {
+        (1usize << self.length_width) - 1
+    }
+
+    /// Resets the channel.
+    ///
+    /// The AXI DMA resets both channels regardless of which channel requests the reset.
+    pub fn reset(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        let registers = channel.registers();
+        self.registers.write(registers.dmacr, Control::RESET)?;
+        let dma = &self.registers;
+        wait_for(None, Some(RESET_TIMEOUT), || {
+            Ok((!dma.read(registers.dmacr)?.reset()).then_some(()))
+        })
+    }
+
+    /// Starts the channel with the completion and error interrupts enabled.
+    pub fn start(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        self.registers.modify(channel.registers().dmacr, |control| {
+            *control |= Control::RUN | Control::IOC_IRQ_ENABLE | Control::ERROR_IRQ_ENABLE;
+        })
+    }
+
+    /// Halts the channel and waits until the halt is complete.
+    pub fn halt(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        let registers = channel.registers();
+        self.registers
+            .modify(registers.dmacr, |control| control.set_run(false))?;
+        let dma = &self.registers;
+        wait_for(None, Some(RESET_TIMEOUT), || {
+            Ok(dma.read(registers.dmasr)?.halted().then_some(()))
+        })
+    }
+
+    /// Returns the channel status.
+    pub fn status(&self, channel: DmaChannel) -> Result<DmaStatus, Error> {
+        self.registers.read(channel.registers().dmasr)
+    }
+
+    /// Checks if the channel is halted.
+    pub fn is_halted(&self, channel: DmaChannel) -> Result<bool, Error> {
+        Ok(self.status(channel)?.halted())
+    }
+
+    /// Checks if the channel is idle, the last transfer is complete.
+    pub fn is_idle(&self, channel: DmaChannel) -> Result<bool, Error> {
+        Ok(self.status(channel)?.idle())
+    }
+
+    /// Starts a simple transfer of `length` bytes at bus address `address`.
+    ///
+    /// The channel is started if halted. Use [`AxiDma::wait`] for completion. Not available
+    /// when the core includes the scatter gather engine.
+    pub fn transfer(
+        &mut self,
+        channel: DmaChannel,
+        address: u64,
+        length: usize,
+    ) -> Result<(), Error> {
+        if length == 0 || length > self.max_length() {
+            return Err(Error::InvalidArgument);
+        }
+        if self.address_width <= 32 && address > u64::from(u32::MAX) {
+            return Err(Error::InvalidArgument);
+        }
+        let registers = channel.registers();
+        let status = self.status(channel)?;
+        if status.sg_included() {
+            return Err(Error::Unsupported);
+        }
+        if status.halted() {
+            self.start(channel)?;
+        } else if !status.idle() {
+            return Err(Error::Busy);
+        }
+        self.registers.write(registers.dmasr, DmaStatus::IRQ_ALL)?;
+        self.registers.write(registers.address, address as u32)?;
+        if self.address_width > 32 {
+            self.registers
+                .write(registers.address_msb, (address >> 32) as u32)?;
+        }
+        log::debug!("{:?} transfer {} bytes at {:#x}", channel, length, address);
+        // Writing the length starts the transfer
+        self.registers.write(registers.length, length as u32)
+    }
+
+    /// Waits for the transfer on the channel to complete and returns the number of bytes
+    /// transferred.
+    ///
+    /// Errors reported in the status register reset the DMA and are returned.
+    pub fn wait(&mut self, channel: DmaChannel, timeout: Option<Duration>) -> Result<usize, Error> {
+        let registers = channel.registers();
+        let dma = &mut self.registers;
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = dma.read(registers.dmasr)?;
+            if let Some(error) = status.error() {
+                return Err(error);
+            }
+            if status.ioc_irq() || status.idle() {
+                dma.write(registers.dmasr, DmaStatus::IOC_IRQ)?;
+                return Ok(Some(dma.read(registers.length)? as usize));
+            }
+            Ok(None)
+        });
+        if let Err(ref error) = result
+            && *error != Error::Timeout
+        {
+            log::warn!("{:?} transfer failed, {:?}", channel, error);
+            self.reset(channel)?;
+        }
+        result
+    }
+
+    /// Transmits `length` bytes at bus address `address` to the stream and waits for completion.
+    pub fn send(&mut self, address: u64, length: usize) -> Result<usize, Error> {
+        self.transfer(DmaChannel::Mm2s, address, length)?;
+        self.wait(DmaChannel::Mm2s, None)
+    }
+
+    /// Receives at most `length` bytes from the stream to bus address `address` and waits for
+    /// completion. Returns the number of bytes received.
+    pub fn receive(&mut self, address: u64, length: usize) -> Result<usize, Error> {
+        self.transfer(DmaChannel::S2mm, address, length)?;
+        self.wait(DmaChannel::S2mm, None)
+    }
+}
+
+/// Register set of one channel
+struct ChannelRegisters {
+    dmacr: Register<Control, RW>,
+    dmasr: Register<DmaStatus, W1C>,
+    address: Register<u32, RW>,
+    address_msb: Register<u32, RW>,
+    length: Register<u32, RW>,
+    curdesc: Register<u32, RW>,
+    curdesc_msb: Register<u32, RW>,
+    taildesc: Register<u32, RW>,
+    taildesc_msb: Register<u32, RW>,
+}
+
+const MM2S: ChannelRegisters = ChannelRegisters {
+    dmacr: Registers::MM2S_DMACR,
+    dmasr: Registers::MM2S_DMASR,
+    address: Registers::MM2S_SA,
+    address_msb: Registers::MM2S_SA_MSB,
+    length: Registers::MM2S_LENGTH,
+    curdesc: Registers::MM2S_CURDESC,
+    curdesc_msb: Registers::MM2S_CURDESC_MSB,
+    taildesc: Registers::MM2S_TAILDESC,
+    taildesc_msb: Registers::MM2S_TAILDESC_MSB,
+};
+
+const S2MM: ChannelRegisters = ChannelRegisters {
+    dmacr: Registers::S2MM_DMACR,
+    dmasr: Registers::S2MM_DMASR,
+    address: Registers::S2MM_DA,
+    address_msb: Registers::S2MM_DA_MSB,
+    length: Registers::S2MM_LENGTH,
+    curdesc: Registers::S2MM_CURDESC,
+    curdesc_msb: Registers::S2MM_CURDESC_MSB,
+    taildesc: Registers::S2MM_TAILDESC,
+    taildesc_msb: Registers::S2MM_TAILDESC_MSB,
+};
+
+register_map! {
+    /// AXI DMA registers
+    struct Registers {
+        /// MM2S DMA control register
+        MM2S_DMACR: Control, RW @ 0x00 = 0x0001_0000;
+        /// MM2S DMA status register
+        MM2S_DMASR: DmaStatus, W1C @ 0x04 = 0x0000_0001;
+        /// MM2S current descriptor pointer, lower 32 bits
+        MM2S_CURDESC: u32, RW @ 0x08;
+        /// MM2S current descriptor pointer, upper 32 bits
+        MM2S_CURDESC_MSB: u32, RW @ 0x0c;
+        /// MM2S tail descriptor pointer, lower 32 bits, writing starts the fetch
+        MM2S_TAILDESC: u32, RW @ 0x10;
+        /// MM2S tail descriptor pointer, upper 32 bits
+        MM2S_TAILDESC_MSB: u32, RW @ 0x14;
+        /// MM2S source address, lower 32 bits
+        MM2S_SA: u32, RW @ 0x18;
+        /// MM2S source address, upper 32 bits
+        MM2S_SA_MSB: u32, RW @ 0x1c;
+        /// MM2S transfer length in bytes, writing starts the transfer
+        MM2S_LENGTH: u32, RW @ 0x28;
+        /// S2MM DMA control register
+        S2MM_DMACR: Control, RW @ 0x30 = 0x0001_0000;
+        /// S2MM DMA status register
+        S2MM_DMASR: DmaStatus, W1C @ 0x34 = 0x0000_0001;
+        /// S2MM current descriptor pointer, lower 32 bits
+        S2MM_CURDESC: u32, RW @ 0x38;
+        /// S2MM current descriptor pointer, upper 32 bits
+        S2MM_CURDESC_MSB: u32, RW @ 0x3c;
+        /// S2MM tail descriptor pointer, lower 32 bits, writing starts the fetch
+        S2MM_TAILDESC: u32, RW @ 0x40;
+        /// S2MM tail descriptor pointer, upper 32 bits
+        S2MM_TAILDESC_MSB: u32, RW @ 0x44;
+        /// S2MM destination address, lower 32 bits
+        S2MM_DA: u32, RW @ 0x48;
+        /// S2MM destination address, upper 32 bits
+        S2MM_DA_MSB: u32, RW @ 0x4c;
+        /// S2MM buffer length, writing starts the transfer, reads back the received bytes
+        S2MM_LENGTH: u32, RW @ 0x58;
+    }
+}
+
+register_bits! {
+    /// DMA control register
+    struct Control(u32) {
+        /// Run, clear to halt
+        RUN: run, set_run: 0;
+        /// Soft reset of the whole AXI DMA
+        RESET: reset, set_reset: 2;
+        /// Keyhole, fixed address
+        KEYHOLE: keyhole, set_keyhole: 3;
+        /// Cyclic buffer descriptor mode
+        CYCLIC_BD_ENABLE: cyclic_bd_enable, set_cyclic_bd_enable: 4;
+        /// Interrupt on complete enable
+        IOC_IRQ_ENABLE: ioc_irq_enable, set_ioc_irq_enable: 12;
+        /// Interrupt on delay timer enable
+        DELAY_IRQ_ENABLE: delay_irq_enable, set_delay_irq_enable: 13;
+        /// Interrupt on error enable
+        ERROR_IRQ_ENABLE: error_irq_enable, set_error_irq_enable: 14;
+        /// Interrupt coalescing threshold
+        IRQ_THRESHOLD: irq_threshold, set_irq_threshold: 23, 16;
+        /// Interrupt delay timeout
+        IRQ_DELAY: irq_delay, set_irq_delay: 31, 24;
+    }
+}
+
+register_bits! {
+    /// DMA status register
+    pub struct DmaStatus(u32) {
+        /// Channel is halted
+        HALTED: halted, set_halted: 0;
+        /// Channel is idle
+        IDLE: idle, set_idle: 1;
+        /// Scatter gather engine is included
+        SG_INCLUDED: sg_included, set_sg_included: 3;
+        /// DMA internal error
+        DMA_INTERNAL_ERROR: dma_internal_error, set_dma_internal_error: 4;
+        /// DMA slave error
+        DMA_SLAVE_ERROR: dma_slave_error, set_dma_slave_error: 5;
+        /// DMA decode error
+        DMA_DECODE_ERROR: dma_decode_error, set_dma_decode_error: 6;
+        /// Scatter gather internal error
+        SG_INTERNAL_ERROR: sg_internal_error, set_sg_internal_error: 8;
+        /// Scatter gather slave error
+        SG_SLAVE_ERROR: sg_slave_error, set_sg_slave_error: 9;
+        /// Scatter gather decode error
+        SG_DECODE_ERROR: sg_decode_error, set_sg_decode_error: 10;
+        /// Interrupt on complete
+        IOC_IRQ: ioc_irq, set_ioc_irq: 12;
+        /// Interrupt on delay timer
+        DELAY_IRQ: delay_irq, set_delay_irq: 13;
+        /// Interrupt on error
+        ERROR_IRQ: error_irq, set_error_irq: 14;
+        /// Interrupt coalescing threshold count
+        IRQ_THRESHOLD: irq_threshold, set_irq_threshold: 23, 16;
+        /// Interrupt delay timer count
+        IRQ_DELAY: irq_delay, set_irq_delay: 31, 24;
+    }
+}
+
+impl DmaStatus {
+    /// All interrupt bits
+    pub const IRQ_ALL: DmaStatus =
+        DmaStatus(DmaStatus::IOC_IRQ.0 | DmaStatus::DELAY_IRQ.0 | DmaStatus::ERROR_IRQ.0);
+
+    /// Returns the first error reported by the status, if any.
+    pub fn error(&self) -> Option<Error> {
+        if self.dma_internal_error() {
+            Some(Error::DmaInternal)
+        } else if self.dma_slave_error() {
+            Some(Error::DmaSlave)
+        } else if self.dma_decode_error() {
+            Some(Error::DmaDecode)
+        } else if self.sg_internal_error() {
+            Some(Error::SgInternal)
+        } else if self.sg_slave_error() {
+            Some(Error::SgSlave)
+        } else if self.sg_decode_error() {
+            Some(Error::SgDecode)
+        } else {
+            None
+        }
+    }
+}
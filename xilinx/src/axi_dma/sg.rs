@@ -0,0 +1,554 @@
+use super::{AxiDma, Control, DmaChannel, DmaStatus};
+use crate::dma_buffer::{DmaBuffer, DmaDirection};
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use crate::mmio::Mmio;
+use crate::register::{RW, Register};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Size of a buffer descriptor, descriptors are aligned to this size
+pub const DESCRIPTOR_SIZE: usize = 0x40;
+
+/// Buffer segment to transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Bus address of the buffer
+    pub address: u64,
+    /// Length in bytes
+    pub length: usize,
+}
+
+impl Segment {
+    /// Creates a segment from bus address and length.
+    pub fn new(address: u64, length: usize) -> Self {
+        Segment { address, length }
+    }
+}
+
+/// Completed buffer descriptor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Completion {
+    /// Bus address of the buffer
+    pub address: u64,
+    /// Number of bytes transferred
+    pub length: usize,
+    /// First buffer of a packet
+    pub start_of_frame: bool,
+    /// Last buffer of a packet
+    pub end_of_frame: bool,
+}
+
+/// Interrupt coalescing configuration
+///
+/// A threshold above 1 requires the delay timer, otherwise fewer outstanding descriptors than
+/// the threshold never raise an interrupt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coalesce {
+    /// Number of completed descriptors before an interrupt, 1 to 255
+    pub threshold: u8,
+    /// Delay timer in units of 125 scatter gather clock cycles, 0 disables the timer
+    pub delay: u8,
+}
+
+impl Default for Coalesce {
+    fn default() -> Self {
+        Coalesce {
+            threshold: 1,
+            delay: 0,
+        }
+    }
+}
+
+/// Ring of buffer descriptors in memory reachable by the DMA
+///
+/// The memory should be uncached, such as reserved memory mapped through `/dev/mem` or a UIO
+/// map. Descriptors are owned by software from `head` and by the DMA from `tail`.
+pub struct DescriptorRing {
+    memory: Mmio,
+    count: usize,
+    head: usize,
+    tail: usize,
+    pending: usize,
+    cyclic: bool,
+}
+
+impl DescriptorRing {
+    /// Creates a ring filling the memory region and links the descriptors.
+    pub fn new(memory: Mmio) -> Result<DescriptorRing, Error> {
+        Self::with_count(memory, usize::MAX)
+    }
+
+    /// Creates a ring of at most `count` descriptors and links the descriptors.
+    pub fn with_count(mut memory: Mmio, count: usize) -> Result<DescriptorRing, Error> {
+        if !memory.address().is_multiple_of(DESCRIPTOR_SIZE as u64) {
+            return Err(Error::Misaligned);
+        }
+        let count = count.min(memory.size() / DESCRIPTOR_SIZE);
+        if count < 2 {
+            return Err(Error::InvalidArgument);
+        }
+        for index in 0..count {
+            let next = memory.address() + (((index + 1) % count) * DESCRIPTOR_SIZE) as u64;
+            let base = index * DESCRIPTOR_SIZE;
+            memory.write(Descriptor::NXTDESC.shifted(base), next as u32)?;
+            memory.write(Descriptor::NXTDESC_MSB.shifted(base), (next >> 32) as u32)?;
+            memory.write(
+                Descriptor::CONTROL.shifted(base),
+                DescriptorControl::empty(),
+            )?;
+            memory.write(Descriptor::STATUS.shifted(base), DescriptorStatus::empty())?;
+        }
+        Ok(DescriptorRing {
+            memory,
+            count,
+            head: 0,
+            tail: 0,
+            pending: 0,
+            cyclic: false,
+        })
+    }
+
+    /// Returns the number of descriptors in the ring.
+    pub fn capacity(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the number of descriptors available for new buffers.
+    pub fn free(&self) -> usize {
+        if self.cyclic {
+            0
+        } else {
+            self.count - self.pending
+        }
+    }
+
+    /// Returns the number of descriptors handed to the DMA and not yet reclaimed.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    fn descriptor_address(&self, index: usize) -> u64 {
+        self.memory.address() + (index * DESCRIPTOR_SIZE) as u64
+    }
+
+    /// Writes the buffer of the descriptor at `head` and advances `head`.
+    fn push(&mut self, segment: &Segment, control: DescriptorControl) -> Result<usize, Error> {
+        let index = self.head;
+        let base = index * DESCRIPTOR_SIZE;
+        let memory = &mut self.memory;
+        memory.write(
+            Descriptor::BUFFER_ADDRESS.shifted(base),
+            segment.address as u32,
+        )?;
+        memory.write(
+            Descriptor::BUFFER_ADDRESS_MSB.shifted(base),
+            (segment.address >> 32) as u32,
+        )?;
+        memory.write(Descriptor::STATUS.shifted(base), DescriptorStatus::empty())?;
+        memory.write(Descriptor::CONTROL.shifted(base), control)?;
+        self.head = (index + 1) % self.count;
+        self.pending += 1;
+        Ok(index)
+    }
+
+    /// Collects the completed descriptors from `tail`.
+    ///
+    /// A descriptor that completed with an error is returned as the error once the completions
+    /// before it have been collected. In cyclic mode the descriptors are handed back to the DMA after the status is cleared.
+    pub fn reclaim(&mut self) -> Result<Vec<Completion>, Error> {
+        let mut completions = Vec::new();
+        while self.pending > 0 {
+            let base = self.tail * DESCRIPTOR_SIZE;
+            let status = self.memory.read(Descriptor::STATUS.shifted(base))?;
+            if !status.complete() {
+                break;
+            }
+            if let Some(error) = status.error() {
+                // The failed descriptor stays at `tail` and is reported by the next call
+                if completions.is_empty() {
+                    return Err(error);
+                }
+                break;
+            }
+            let low = self.memory.read(Descriptor::BUFFER_ADDRESS.shifted(base))?;
+            let high = self
+                .memory
+                .read(Descriptor::BUFFER_ADDRESS_MSB.shifted(base))?;
+            completions.push(Completion {
+                address: (u64::from(high) << 32) | u64::from(low),
+                length: status.transferred() as usize,
+                start_of_frame: status.rx_sof(),
+                end_of_frame: status.rx_eof(),
+            });
+            self.tail = (self.tail + 1) % self.count;
+            if self.cyclic {
+                self.memory
+                    .write(Descriptor::STATUS.shifted(base), DescriptorStatus::empty())?;
+            } else {
+                self.pending -= 1;
+            }
+        }
+        Ok(completions)
+    }
+}
+
+impl AxiDma {
+    /// Starts the channel in scatter gather mode with the descriptor ring.
+    ///
+    /// The channel has to be halted, see [`AxiDma::reset`]. Buffers are queued with
+    /// [`AxiDma::sg_submit`].
+    ///
+    /// Fails with [`Error::InvalidArgument`] for a coalesce threshold above 1 without a delay.
+    pub fn sg_start(
+        &mut self,
+        channel: DmaChannel,
+        ring: DescriptorRing,
+        coalesce: Coalesce,
+    ) -> Result<(), Error> {
+        self.sg_run(channel, ring, coalesce, false)
+    }
+
+    /// Starts the channel in cyclic scatter gather mode.
+    ///
+    /// Every descriptor of the ring is filled with a segment and the DMA loops over them until
+    /// halted, typically for continuous capture on S2MM. Completed descriptors are collected
+    /// with [`AxiDma::sg_wait`] or [`AxiDma::sg_reclaim`].
+    pub fn sg_start_cyclic(
+        &mut self,
+        channel: DmaChannel,
+        mut ring: DescriptorRing,
+        segments: &[Segment],
+        coalesce: Coalesce,
+    ) -> Result<(), Error> {
+        if segments.len() != ring.capacity() {
+            return Err(Error::InvalidArgument);
+        }
+        for segment in segments {
+            ring.push(segment, self.descriptor_control(segment, true, true)?)?;
+        }
+        ring.cyclic = true;
+        self.sg_run(channel, ring, coalesce, true)?;
+        // Any address outside of the ring keeps the DMA running
+        let registers = channel.registers();
+        let ring = self.rings[channel.index()].as_ref().unwrap();
+        let tail = ring.descriptor_address(ring.count);
+        self.write_descriptor_pointer(registers.taildesc, registers.taildesc_msb, tail)
+    }
+
+    fn sg_run(
+        &mut self,
+        channel: DmaChannel,
+        ring: DescriptorRing,
+        coalesce: Coalesce,
+        cyclic: bool,
+    ) -> Result<(), Error> {
+        let registers = channel.registers();
+        let status = self.status(channel)?;
+        if !status.sg_included() {
+            return Err(Error::Unsupported);
+        }
+        if !status.halted() {
+            return Err(Error::Busy);
+        }
+        if coalesce.threshold == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        // Without the delay timer only a cyclic ring is sure to reach the threshold
+        if coalesce.threshold > 1
+            && coalesce.delay == 0
+            && (!cyclic || usize::from(coalesce.threshold) > ring.capacity())
+        {
+            return Err(Error::InvalidArgument);
+        }
+        let current = ring.descriptor_address(ring.tail);
+        self.write_descriptor_pointer(registers.curdesc, registers.curdesc_msb, current)?;
+        self.registers.write(registers.dmasr, DmaStatus::IRQ_ALL)?;
+        let mut control = Control::RUN | Control::IOC_IRQ_ENABLE | Control::ERROR_IRQ_ENABLE;
+        control.set_irq_threshold(u32::from(coalesce.threshold));
+        control.set_irq_delay(u32::from(coalesce.delay));
+        control.set_delay_irq_enable(coalesce.delay > 0);
+        control.set_cyclic_bd_enable(cyclic);
+        self.registers.write(registers.dmacr, control)?;
+        self.rings[channel.index()] = Some(ring);
+        Ok(())
+    }
+
+    /// Halts the channel and returns its descriptor ring.
+    ///
+    /// The DMA is reset, so any descriptor in flight is abandoned.
+    pub fn sg_stop(&mut self, channel: DmaChannel) -> Result<Option<DescriptorRing>, Error> {
+        self.reset(channel)?;
+        Ok(self.rings[channel.index()].take().map(|mut ring| {
+            ring.head = 0;
+            ring.tail = 0;
+            ring.pending = 0;
+            ring.cyclic = false;
+            ring
+        }))
+    }
+
+    fn descriptor_control(
+        &self,
+        segment: &Segment,
+        first: bool,
+        last: bool,
+    ) -> Result<DescriptorControl, Error> {
+        if segment.length == 0 || segment.length > self.max_length() {
+            return Err(Error::InvalidArgument);
+        }
+        if self.address_width <= 32 && segment.address > u64::from(u32::MAX) {
+            return Err(Error::InvalidArgument);
+        }
+        let mut control = DescriptorControl::empty();
+        control.set_length(segment.length as u32);
+        control.set_sof(first);
+        control.set_eof(last);
+        Ok(control)
+    }
+
+    fn write_descriptor_pointer(
+        &mut self,
+        low: Register<u32, RW>,
+        high: Register<u32, RW>,
+        address: u64,
+    ) -> Result<(), Error> {
+        if self.address_width > 32 {
+            self.registers.write(high, (address >> 32) as u32)?;
+        }
+        // Writing the lower half of the tail descriptor pointer starts the fetch
+        self.registers.write(low, address as u32)
+    }
+
+    /// Queues buffers on the channel.
+    ///
+    /// On MM2S the segments form one packet, the first descriptor is marked start of frame and
+    /// the last end of frame. On S2MM every segment is a receive buffer, a received packet may
+    /// span several buffers.
+    pub fn sg_submit(&mut self, channel: DmaChannel, segments: &[Segment]) -> Result<(), Error> {
+        if segments.is_empty() {
+            return Ok(());
+        }
+        let free = match self.rings[channel.index()] {
+            Some(ref ring) => ring.free(),
+            None => return Err(Error::InvalidArgument),
+        };
+        if segments.len() > free {
+            return Err(Error::Full);
+        }
+        let mut controls = Vec::with_capacity(segments.len());
+        for (n, segment) in segments.iter().enumerate() {
+            let (first, last) = match channel {
+                DmaChannel::Mm2s => (n == 0, n + 1 == segments.len()),
+                DmaChannel::S2mm => (false, false),
+            };
+            controls.push(self.descriptor_control(segment, first, last)?);
+        }
+        let ring = self.rings[channel.index()].as_mut().unwrap();
+        let mut last = 0;
+        for (segment, control) in segments.iter().zip(controls) {
+            last = ring.push(segment, control)?;
+        }
+        let tail = ring.descriptor_address(last);
+        let registers = channel.registers();
+        log::debug!(
+            "{:?} queue {} descriptors, tail {:#x}",
+            channel,
+            segments.len(),
+            tail
+        );
+        self.write_descriptor_pointer(registers.taildesc, registers.taildesc_msb, tail)
+    }
+
+    /// Collects completed descriptors of the channel without waiting.
+    ///
+    /// Descriptors completed before an error are returned first, the error is returned by the
+    /// next call.
+    pub fn sg_reclaim(&mut self, channel: DmaChannel) -> Result<Vec<Completion>, Error> {
+        let status = self.status(channel)?;
+        self.registers.write(
+            channel.registers().dmasr,
+            DmaStatus::IOC_IRQ | DmaStatus::DELAY_IRQ,
+        )?;
+        let completions = match self.rings[channel.index()] {
+            Some(ref mut ring) => ring.reclaim(),
+            None => return Err(Error::InvalidArgument),
+        };
+        let error = match completions {
+            Ok(ref completions) if !completions.is_empty() => None,
+            Ok(_) => status.error(),
+            Err(ref error) => Some(error.clone()),
+        };
+        if let Some(error) = error {
+            log::warn!("{:?} scatter gather failed, {:?}", channel, error);
+            self.sg_stop(channel)?;
+            return Err(error);
+        }
+        completions
+    }
+
+    /// Waits until at least one descriptor of the channel completes and collects the completed
+    /// descriptors.
+    pub fn sg_wait(
+        &mut self,
+        channel: DmaChannel,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Completion>, Error> {
+        let registers = channel.registers();
+        let dma = &mut self.registers;
+        let ring = match self.rings[channel.index()] {
+            Some(ref mut ring) => ring,
+            None => return Err(Error::InvalidArgument),
+        };
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = dma.read(registers.dmasr)?;
+            dma.write(registers.dmasr, DmaStatus::IOC_IRQ | DmaStatus::DELAY_IRQ)?;
+            let completions = ring.reclaim()?;
+            if !completions.is_empty() {
+                return Ok(Some(completions));
+            }
+            match status.error() {
+                Some(error) => Err(error),
+                None => Ok(None),
+            }
+        });
+        if let Err(ref error) = result
+            && *error != Error::Timeout
+        {
+            log::warn!("{:?} scatter gather failed, {:?}", channel, error);
+            self.sg_stop(channel)?;
+        }
+        result
+    }
+
+    /// Transmits `data` as one packet through `buffer` and waits until it is sent. Returns the
+    /// number of bytes transmitted.
+    ///
+    /// The data is copied to the start of the buffer and queued in segments of at most
+    /// [`AxiDma::max_length`] bytes. Fails with [`Error::Busy`] while other MM2S descriptors
+    /// are outstanding.
+    pub fn sg_write_bytes(
+        &mut self,
+        buffer: &mut DmaBuffer,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<usize, Error> {
+        self.sg_check_idle(DmaChannel::Mm2s)?;
+        if data.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+        buffer.write(0, data)?;
+        buffer.sync_for_device(0, data.len(), DmaDirection::ToDevice)?;
+        let max_length = self.max_length();
+        let segments = (0..data.len())
+            .step_by(max_length)
+            .map(|offset| buffer.segment(offset, max_length.min(data.len() - offset)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.sg_submit(DmaChannel::Mm2s, &segments)?;
+        let mut remaining = segments.len();
+        let mut sent = 0;
+        while remaining > 0 {
+            let completions = self.sg_wait(DmaChannel::Mm2s, timeout)?;
+            remaining -= completions.len();
+            sent += completions.iter().map(|c| c.length).sum::<usize>();
+        }
+        Ok(sent)
+    }
+
+    /// Receives at most `data.len()` bytes from the stream through `buffer` and waits for them.
+    /// Returns the number of bytes received and whether the packet ended.
+    ///
+    /// One descriptor of at most [`AxiDma::max_length`] bytes at the start of the buffer is
+    /// queued, the rest of a longer packet is returned by the following calls. Fails with
+    /// [`Error::Busy`] while other S2MM descriptors are outstanding, such as after a timeout.
+    pub fn sg_read_bytes(
+        &mut self,
+        buffer: &mut DmaBuffer,
+        data: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<(usize, bool), Error> {
+        self.sg_check_idle(DmaChannel::S2mm)?;
+        let length = data.len().min(self.max_length());
+        let segment = buffer.segment(0, length)?;
+        buffer.sync_for_device(0, length, DmaDirection::FromDevice)?;
+        self.sg_submit(DmaChannel::S2mm, &[segment])?;
+        let completion = self.sg_wait(DmaChannel::S2mm, timeout)?[0];
+        let length = completion.length.min(length);
+        buffer.sync_for_cpu(0, length, DmaDirection::FromDevice)?;
+        buffer.read(0, &mut data[..length])?;
+        Ok((length, completion.end_of_frame))
+    }
+
+    /// Checks that the channel runs in scatter gather mode without outstanding descriptors.
+    fn sg_check_idle(&self, channel: DmaChannel) -> Result<(), Error> {
+        match self.rings[channel.index()] {
+            Some(ref ring) if ring.pending() == 0 => Ok(()),
+            Some(_) => Err(Error::Busy),
+            None => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+register_map! {
+    /// Buffer descriptor fields, relative to the descriptor
+    struct Descriptor {
+        /// Next descriptor pointer, lower 32 bits
+        NXTDESC: u32, RW @ 0x00;
+        /// Next descriptor pointer, upper 32 bits
+        NXTDESC_MSB: u32, RW @ 0x04;
+        /// Buffer address, lower 32 bits
+        BUFFER_ADDRESS: u32, RW @ 0x08;
+        /// Buffer address, upper 32 bits
+        BUFFER_ADDRESS_MSB: u32, RW @ 0x0c;
+        /// Control
+        CONTROL: DescriptorControl, RW @ 0x18;
+        /// Status, written by the DMA
+        STATUS: DescriptorStatus, RW @ 0x1c;
+    }
+}
+
+register_bits! {
+    /// Buffer descriptor control
+    struct DescriptorControl(u32) {
+        /// Buffer length in bytes
+        LENGTH: length, set_length: 25, 0;
+        /// End of frame, last buffer of a transmitted packet
+        EOF: eof, set_eof: 26;
+        /// Start of frame, first buffer of a transmitted packet
+        SOF: sof, set_sof: 27;
+    }
+}
+
+register_bits! {
+    /// Buffer descriptor status
+    struct DescriptorStatus(u32) {
+        /// Number of bytes transferred
+        TRANSFERRED: transferred, set_transferred: 25, 0;
+        /// Last buffer of a received packet
+        RX_EOF: rx_eof, set_rx_eof: 26;
+        /// First buffer of a received packet
+        RX_SOF: rx_sof, set_rx_sof: 27;
+        /// DMA internal error
+        INTERNAL_ERROR: internal_error, set_internal_error: 28;
+        /// DMA slave error
+        SLAVE_ERROR: slave_error, set_slave_error: 29;
+        /// DMA decode error
+        DECODE_ERROR: decode_error, set_decode_error: 30;
+        /// Descriptor completed
+        COMPLETE: complete, set_complete: 31;
+    }
+}
+
+impl DescriptorStatus {
+    fn error(&self) -> Option<Error> {
+        if self.internal_error() {
+            Some(Error::DmaInternal)
+        } else if self.slave_error() {
+            Some(Error::DmaSlave)
+        } else if self.decode_error() {
+            Some(Error::DmaDecode)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::register::{RW, Register};
+use crate::{register_bits, register_map};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// AXI GPIO channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpioChannel {
+    /// Channel 1, `GPIO_DATA` and `GPIO_TRI`
+    One,
+    /// Channel 2, `GPIO2_DATA` and `GPIO2_TRI`, only in dual channel configurations
+    Two,
+}
+
+impl GpioChannel {
+    fn index(&self) -> usize {
+        match self {
+            GpioChannel::One => 0,
+            GpioChannel::Two => 1,
+        }
+    }
+
+    fn data(&self) -> Register<u32, RW> {
+        match self {
+            GpioChannel::One => Registers::GPIO_DATA,
+            GpioChannel::Two => Registers::GPIO2_DATA,
+        }
+    }
+
+    fn tri(&self) -> Register<u32, RW> {
+        match self {
+            GpioChannel::One => Registers::GPIO_TRI,
+            GpioChannel::Two => Registers::GPIO2_TRI,
+        }
+    }
+
+    fn interrupt(&self) -> GpioInterrupt {
+        match self {
+            GpioChannel::One => GpioInterrupt::CHANNEL1,
+            GpioChannel::Two => GpioInterrupt::CHANNEL2,
+        }
+    }
+}
+
+/// Registers and the last written output and direction values
+struct State {
+    registers: Mmio,
+    data: [u32; 2],
+    tri: [u32; 2],
+}
+
+/// AXI GPIO (PG144)
+///
+/// Output values and directions are kept in shadow registers, which are read from the data and
+/// tri-state registers once when the driver is created. Bit updates are read/modify/write
+/// operations on the shadow under a lock, so an `AxiGpio` can be shared between threads. Other
+/// processes mapping the same device are not synchronised, their changes are overwritten by the
+/// next update.
+pub struct AxiGpio {
+    state: Mutex<State>,
+    interrupt: Option<Interrupt>,
+    width: [u32; 2],
+}
+
+impl AxiGpio {
+    /// Creates a new `AxiGpio` instance from a UIO device.
+    ///
+    /// The channel widths and dual channel configuration are taken from the `xlnx,gpio-width`,
+    /// `xlnx,gpio2-width` and `xlnx,is-dual` device tree properties when available. The output
+    /// shadow is read from the data registers, so outputs set before keep their value.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiGpio, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut gpio = Self::from_mmio(registers, Some(interrupt))?;
+        if let Some(node) = device.of_node() {
+            let read = |name: &str| devicetree::read_u32(node, name).ok();
+            gpio.set_width(GpioChannel::One, read("xlnx,gpio-width").unwrap_or(32))?;
+            if read("xlnx,is-dual").unwrap_or(0) != 0 {
+                gpio.set_width(GpioChannel::Two, read("xlnx,gpio2-width").unwrap_or(32))?;
+            }
+        }
+        Ok(gpio)
+    }
+
+    /// Creates a new `AxiGpio` instance from a memory mapped register region.
+    ///
+    /// The instance is configured as a single 32-bit channel, see [`AxiGpio::set_width`]. The
+    /// shadow registers are initialised from the hardware.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> Result<AxiGpio, Error> {
+        let data = [registers.read(Registers::GPIO_DATA)?, 0];
+        let tri = [
+            registers.read(Registers::GPIO_TRI)?,
+            Registers::GPIO2_TRI.reset(),
+        ];
+        Ok(AxiGpio {
+            state: Mutex::new(State {
+                registers,
+                data,
+                tri,
+            }),
+            interrupt,
+            width: [32, 0],
+        })
+    }
+
+    /// Sets the width of the channel in bits, 1 to 32. A width of 0 for channel 2 marks a
+    /// single channel configuration.
+    pub fn set_width(&mut self, channel: GpioChannel, bits: u32) -> Result<(), Error> {
+        if bits > 32 || (bits == 0 && channel == GpioChannel::One) {
+            return Err(Error::InvalidArgument);
+        }
+        self.width[channel.index()] = bits;
+        if channel == GpioChannel::Two && bits > 0 {
+            let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+            state.data[1] = state.registers.read(Registers::GPIO2_DATA)?;
+            state.tri[1] = state.registers.read(Registers::GPIO2_TRI)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the width of the channel in bits, 0 for a missing channel 2.
+    pub fn width(&self, channel: GpioChannel) -> u32 {
+        self.width[channel.index()]
+    }
+
+    /// Checks if the core is configured with two channels.
+    pub fn is_dual(&self) -> bool {
+        self.width[1] > 0
+    }
+
+    fn mask(&self, channel: GpioChannel) -> Result<u32, Error> {
+        match self.width[channel.index()] {
+            0 => Err(Error::Unsupported),
+            32 => Ok(u32::MAX),
+            bits => Ok((1 << bits) - 1),
+        }
+    }
+
+    fn check_bit(&self, channel: GpioChannel, bit: u32) -> Result<(), Error> {
+        if bit >= self.width[channel.index()] {
+            return Err(Error::OutOfRange);
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads the pins of the channel.
+    pub fn read(&self, channel: GpioChannel) -> Result<u32, Error> {
+        let mask = self.mask(channel)?;
+        Ok(self.lock().registers.read(channel.data())? & mask)
+    }
+
+    /// Reads one pin of the channel.
+    pub fn read_bit(&self, channel: GpioChannel, bit: u32) -> Result<bool, Error> {
+        self.check_bit(channel, bit)?;
+        Ok(self.read(channel)? & (1 << bit) != 0)
+    }
+
+    /// Returns the last written output value of the channel.
+    pub fn output(&self, channel: GpioChannel) -> Result<u32, Error> {
+        let mask = self.mask(channel)?;
+        Ok(self.lock().data[channel.index()] & mask)
+    }
+
+    /// Writes the outputs of the channel.
+    pub fn write(&self, channel: GpioChannel, value: u32) -> Result<(), Error> {
+        self.modify(channel, u32::MAX, value)
+    }
+
+    /// Writes the outputs selected by `mask` to `value`, leaving the other outputs unchanged.
+    pub fn modify(&self, channel: GpioChannel, mask: u32, value: u32) -> Result<(), Error> {
+        let mask = mask & self.mask(channel)?;
+        let mut state = self.lock();
+        let index = channel.index();
+        let data = (state.data[index] & !mask) | (value & mask);
+        state.registers.write(channel.data(), data)?;
+        state.data[index] = data;
+        Ok(())
+    }
+
+    /// Sets the outputs selected by `mask`.
+    pub fn set_bits(&self, channel: GpioChannel, mask: u32) -> Result<(), Error> {
+        self.modify(channel, mask, u32::MAX)
+    }
+
+    /// Clears the outputs selected by `mask`.
+    pub fn clear_bits(&self, channel: GpioChannel, mask: u32) -> Result<(), Error> {
+        self.modify(channel, mask, 0)
+    }
+
+    /// Inverts the outputs selected by `mask`.
+    pub fn toggle_bits(&self, channel: GpioChannel, mask: u32) -> Result<(), Error> {
+        let mask = mask & self.mask(channel)?;
+        let mut state = self.lock();
+        let index = channel.index();
+        let data = state.data[index] ^ mask;
+        state.registers.write(channel.data(), data)?;
+        state.data[index] = data;
+        Ok(())
+    }
+
+    /// Writes one output of the channel.
+    pub fn write_bit(&self, channel: GpioChannel, bit: u32, value: bool) -> Result<(), Error> {
+        self.check_bit(channel, bit)?;
+        self.modify(channel, 1 << bit, if value { u32::MAX } else { 0 })
+    }
+
+    /// Returns the tri-state control of the channel, set bits are inputs.
+    pub fn tristate(&self, channel: GpioChannel) -> Result<u32, Error> {
+        let mask = self.mask(channel)?;
+        Ok(self.lock().tri[channel.index()] & mask)
+    }
+
+    /// Writes the tri-state control of the channel, set bits are inputs.
+    pub fn set_tristate(&self, channel: GpioChannel, tri: u32) -> Result<(), Error> {
+        self.modify_tristate(channel, u32::MAX, tri)
+    }
+
+    fn modify_tristate(&self, channel: GpioChannel, mask: u32, value: u32) -> Result<(), Error> {
+        let mask = mask & self.mask(channel)?;
+        let mut state = self.lock();
+        let index = channel.index();
+        let tri = (state.tri[index] & !mask) | (value & mask);
+        state.registers.write(channel.tri(), tri)?;
+        state.tri[index] = tri;
+        Ok(())
+    }
+
+    /// Configures the pins selected by `mask` as outputs.
+    pub fn set_outputs(&self, channel: GpioChannel, mask: u32) -> Result<(), Error> {
+        self.modify_tristate(channel, mask, 0)
+    }
+
+    /// Configures the pins selected by `mask` as inputs.
+    pub fn set_inputs(&self, channel: GpioChannel, mask: u32) -> Result<(), Error> {
+        self.modify_tristate(channel, mask, u32::MAX)
+    }
+
+    /// Enables the interrupt of the channel and the global interrupt.
+    ///
+    /// The interrupt fires on any change of the channel inputs.
+    pub fn enable_interrupt(&self, channel: GpioChannel) -> Result<(), Error> {
+        self.mask(channel)?;
+        let mut state = self.lock();
+        state
+            .registers
+            .modify(Registers::IER, |enable| *enable |= channel.interrupt())?;
+        state
+            .registers
+            .write(Registers::GIER, GlobalInterrupt::ENABLE)
+    }
+
+    /// Disables the interrupt of the channel, the global interrupt is disabled with the last
+    /// channel.
+    pub fn disable_interrupt(&self, channel: GpioChannel) -> Result<(), Error> {
+        let mut state = self.lock();
+        let mut enable = state.registers.read(Registers::IER)?;
+        enable &= !channel.interrupt();
+        state.registers.write(Registers::IER, enable)?;
+        if enable == GpioInterrupt::empty() {
+            state
+                .registers
+                .write(Registers::GIER, GlobalInterrupt::empty())?;
+        }
+        Ok(())
+    }
+
+    /// Returns and clears the pending channel interrupts.
+    pub fn interrupt_status(&self) -> Result<GpioInterrupt, Error> {
+        self.lock().registers.acknowledge(Registers::ISR)
+    }
+
+    /// Waits for a channel interrupt and returns the pending channel interrupts.
+    ///
+    /// Without a UIO interrupt the status register is polled.
+    pub fn wait_interrupt(&self, timeout: Option<Duration>) -> Result<GpioInterrupt, Error> {
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = self.interrupt_status()?;
+            Ok((status != GpioInterrupt::empty()).then_some(status))
+        })
+    }
+}
+
+register_map! {
+    /// AXI GPIO registers
+    struct Registers {
+        /// Channel 1 data
+        GPIO_DATA: u32, RW @ 0x000;
+        /// Channel 1 tri-state control, set bits are inputs
+        GPIO_TRI: u32, RW @ 0x004 = 0xffff_ffff;
+        /// Channel 2 data
+        GPIO2_DATA: u32, RW @ 0x008;
+        /// Channel 2 tri-state control, set bits are inputs
+        GPIO2_TRI: u32, RW @ 0x00c = 0xffff_ffff;
+        /// Global interrupt enable
+        GIER: GlobalInterrupt, RW @ 0x11c;
+        /// Interrupt status, bits toggle on write
+        ISR: GpioInterrupt, TOW @ 0x120;
+        /// Interrupt enable
+        IER: GpioInterrupt, RW @ 0x128;
+    }
+}
+
+register_bits! {
+    /// Global interrupt enable register
+    struct GlobalInterrupt(u32) {
+        /// Enable the interrupt output
+        ENABLE: enable, set_enable: 31;
+    }
+}
+
+register_bits! {
+    /// Channel interrupts
+    pub struct GpioInterrupt(u32) {
+        /// Channel 1 input changed
+        CHANNEL1: channel1, set_channel1: 0;
+        /// Channel 2 input changed
+        CHANNEL2: channel2, set_channel2: 1;
+    }
+}
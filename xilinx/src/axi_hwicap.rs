@@ -0,0 +1,360 @@
+use crate::bitstream::{Bitstream, SYNC_WORD};
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Time to wait for a FIFO transfer to the configuration interface
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Words read from the read FIFO per transfer
+const READ_CHUNK: usize = 256;
+
+/// Dummy word before the synchronisation word
+const DUMMY_WORD: u32 = 0xffff_ffff;
+/// No operation packet
+const NOOP: u32 = 0x2000_0000;
+
+/// Configuration packet operations
+const OP_READ: u32 = 1;
+const OP_WRITE: u32 = 2;
+
+/// Configuration command register commands
+const CMD_RCFG: u32 = 0x04;
+const CMD_RCRC: u32 = 0x07;
+const CMD_DESYNC: u32 = 0x0d;
+
+/// Device family, selects the configuration frame size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IcapFamily {
+    /// 7 series and Zynq-7000
+    SevenSeries,
+    /// UltraScale
+    UltraScale,
+    /// UltraScale+ and Zynq UltraScale+
+    UltraScalePlus,
+}
+
+impl IcapFamily {
+    /// Returns the number of words in a configuration frame.
+    pub fn frame_words(&self) -> usize {
+        match self {
+            IcapFamily::SevenSeries => 101,
+            IcapFamily::UltraScale => 123,
+            IcapFamily::UltraScalePlus => 93,
+        }
+    }
+}
+
+/// Configuration register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigRegister {
+    /// CRC
+    Crc,
+    /// Frame address
+    Far,
+    /// Command
+    Cmd,
+    /// Control 0
+    Ctl0,
+    /// Status
+    Stat,
+    /// Configuration options 0
+    Cor0,
+    /// Device identifier
+    IdCode,
+    /// Configuration options 1
+    Cor1,
+    /// Warm boot start address
+    WbStar,
+    /// Boot history status
+    BootSts,
+    /// Control 1
+    Ctl1,
+}
+
+impl ConfigRegister {
+    fn address(&self) -> u32 {
+        match self {
+            ConfigRegister::Crc => 0x00,
+            ConfigRegister::Far => 0x01,
+            ConfigRegister::Cmd => 0x04,
+            ConfigRegister::Ctl0 => 0x05,
+            ConfigRegister::Stat => 0x07,
+            ConfigRegister::Cor0 => 0x09,
+            ConfigRegister::IdCode => 0x0c,
+            ConfigRegister::Cor1 => 0x0e,
+            ConfigRegister::WbStar => 0x10,
+            ConfigRegister::BootSts => 0x16,
+            ConfigRegister::Ctl1 => 0x18,
+        }
+    }
+}
+
+/// Frame data output register address
+const FDRO: u32 = 0x03;
+
+/// Returns a type 1 packet header.
+fn type1(operation: u32, register: u32, words: u32) -> u32 {
+    1 << 29 | operation << 27 | register << 13 | (words & 0x7ff)
+}
+
+/// Returns a type 2 packet header.
+fn type2(operation: u32, words: u32) -> u32 {
+    2 << 29 | operation << 27 | (words & 0x07ff_ffff)
+}
+
+/// AXI HWICAP (PG134)
+///
+/// Writes bitstreams, such as partial bitstreams for dynamic function exchange, through the
+/// internal configuration access port, and reads back configuration registers and frames.
+pub struct AxiHwicap {
+    registers: Mmio,
+    family: IcapFamily,
+}
+
+impl AxiHwicap {
+    /// Creates a new `AxiHwicap` instance from a UIO device.
+    pub fn try_from(device: &DeviceInfo, family: IcapFamily) -> Result<AxiHwicap, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        Ok(Self::from_mmio(registers, family))
+    }
+
+    /// Creates a new `AxiHwicap` instance from a memory mapped register region.
+    pub fn from_mmio(registers: Mmio, family: IcapFamily) -> AxiHwicap {
+        AxiHwicap { registers, family }
+    }
+
+    /// Returns the device family.
+    pub fn family(&self) -> IcapFamily {
+        self.family
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<IcapStatus, Error> {
+        self.registers.read(Registers::SR)
+    }
+
+    /// Resets the core and clears both FIFOs.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers
+            .write(Registers::CR, Control::SOFTWARE_RESET)?;
+        self.registers.write(Registers::CR, Control::FIFO_CLEAR)?;
+        self.registers.write(Registers::CR, Control::empty())
+    }
+
+    /// Aborts the current configuration access, then resets the core.
+    pub fn abort(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::CR, Control::ABORT)?;
+        let registers = &self.registers;
+        let result = wait_for(None, Some(TRANSFER_TIMEOUT), || {
+            Ok((!registers.read(Registers::CR)?.abort()).then_some(()))
+        });
+        let abort_status = self.registers.read(Registers::ASR)?;
+        log::debug!("abort status {:08x}", abort_status);
+        self.reset()?;
+        result
+    }
+
+    /// Waits until the control register bits of the started transfer clear.
+    fn wait_transfer(&mut self, transfer: Control) -> Result<(), Error> {
+        let registers = &self.registers;
+        let result = wait_for(None, Some(TRANSFER_TIMEOUT), || {
+            Ok((!registers.read(Registers::CR)?.intersects(transfer)).then_some(()))
+        });
+        if let Err(ref error) = result {
+            log::warn!("configuration access failed, {:?}", error);
+            self.abort()?;
+        }
+        result
+    }
+
+    /// Writes configuration words, calls `progress` with the words written and the total.
+    fn write_words<I, F>(&mut self, words: I, total: usize, mut progress: F) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = u32>,
+        F: FnMut(usize, usize),
+    {
+        let mut words = words.into_iter().peekable();
+        let mut written = 0;
+        while words.peek().is_some() {
+            let vacancy = self.registers.read(Registers::WFV)? as usize;
+            if vacancy == 0 {
+                return Err(Error::Full);
+            }
+            for word in words.by_ref().take(vacancy) {
+                self.registers.write(Registers::WF, word)?;
+                written += 1;
+            }
+            self.registers.write(Registers::CR, Control::WRITE)?;
+            self.wait_transfer(Control::WRITE)?;
+            progress(written, total);
+        }
+        if !self.status()?.no_configuration_error() {
+            log::warn!("configuration error");
+            return Err(Error::Configuration);
+        }
+        Ok(())
+    }
+
+    /// Reads `count` words from the configuration interface.
+    fn read_words(&mut self, count: usize) -> Result<Vec<u32>, Error> {
+        let mut words = Vec::with_capacity(count);
+        while words.len() < count {
+            let chunk = (count - words.len()).min(READ_CHUNK);
+            self.registers.write(Registers::SZ, chunk as u32)?;
+            self.registers.write(Registers::CR, Control::READ)?;
+            self.wait_transfer(Control::READ)?;
+            let available = self.registers.read(Registers::RFO)? as usize;
+            if available < chunk {
+                return Err(Error::UnderRun);
+            }
+            for _ in 0..chunk {
+                words.push(self.registers.read(Registers::RF)?);
+            }
+        }
+        Ok(words)
+    }
+
+    /// Writes a bitstream.
+    pub fn write_bitstream(&mut self, bitstream: &Bitstream) -> Result<(), Error> {
+        self.write_bitstream_with_progress(bitstream, |_, _| {})
+    }
+
+    /// Writes a bitstream, calls `progress` with the words written and the total after each
+    /// FIFO transfer.
+    pub fn write_bitstream_with_progress<F>(
+        &mut self,
+        bitstream: &Bitstream,
+        progress: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize, usize),
+    {
+        let total = bitstream.data().len() / 4;
+        log::debug!("write bitstream, {} words", total);
+        self.write_words(bitstream.words(), total, progress)
+    }
+
+    fn desynchronise(&mut self) -> Result<(), Error> {
+        let words = [
+            type1(OP_WRITE, ConfigRegister::Cmd.address(), 1),
+            CMD_DESYNC,
+            NOOP,
+            NOOP,
+        ];
+        self.write_words(words, words.len(), |_, _| {})
+    }
+
+    /// Reads a configuration register.
+    pub fn read_register(&mut self, register: ConfigRegister) -> Result<u32, Error> {
+        let words = [
+            DUMMY_WORD,
+            SYNC_WORD,
+            NOOP,
+            NOOP,
+            type1(OP_READ, register.address(), 1),
+            NOOP,
+            NOOP,
+        ];
+        self.write_words(words, words.len(), |_, _| {})?;
+        let value = self.read_words(1);
+        self.desynchronise()?;
+        Ok(value?[0])
+    }
+
+    /// Reads the device identifier.
+    pub fn id_code(&mut self) -> Result<u32, Error> {
+        self.read_register(ConfigRegister::IdCode)
+    }
+
+    /// Reads the configuration status register.
+    pub fn configuration_status(&mut self) -> Result<u32, Error> {
+        self.read_register(ConfigRegister::Stat)
+    }
+
+    /// Reads `frames` configuration frames starting at the frame address.
+    pub fn read_frames(&mut self, address: u32, frames: usize) -> Result<Vec<u32>, Error> {
+        let frame_words = self.family.frame_words();
+        // The frame data is preceded by a pad frame
+        let count = (frames + 1) * frame_words;
+        let words = [
+            DUMMY_WORD,
+            SYNC_WORD,
+            NOOP,
+            type1(OP_WRITE, ConfigRegister::Cmd.address(), 1),
+            CMD_RCRC,
+            NOOP,
+            NOOP,
+            type1(OP_WRITE, ConfigRegister::Cmd.address(), 1),
+            CMD_RCFG,
+            NOOP,
+            type1(OP_WRITE, ConfigRegister::Far.address(), 1),
+            address,
+            type1(OP_READ, FDRO, 0),
+            type2(OP_READ, count as u32),
+            NOOP,
+            NOOP,
+        ];
+        self.write_words(words, words.len(), |_, _| {})?;
+        let data = self.read_words(count);
+        self.desynchronise()?;
+        let mut data = data?;
+        data.drain(..frame_words);
+        Ok(data)
+    }
+}
+
+register_map! {
+    /// AXI HWICAP registers
+    struct Registers {
+        /// Write FIFO keyhole
+        WF: u32, WO @ 0x100;
+        /// Read FIFO keyhole
+        RF: u32, RO @ 0x104;
+        /// Number of words to read
+        SZ: u32, WO @ 0x108;
+        /// Control
+        CR: Control, RW @ 0x10c;
+        /// Status
+        SR: IcapStatus, RO @ 0x110;
+        /// Write FIFO vacancy
+        WFV: u32, RO @ 0x114;
+        /// Read FIFO occupancy
+        RFO: u32, RO @ 0x118;
+        /// Abort status
+        ASR: u32, RO @ 0x11c;
+    }
+}
+
+register_bits! {
+    /// Control register
+    struct Control(u32) {
+        /// Write the write FIFO to the configuration interface
+        WRITE: write, set_write: 0;
+        /// Read from the configuration interface to the read FIFO
+        READ: read, set_read: 1;
+        /// Clear the FIFOs
+        FIFO_CLEAR: fifo_clear, set_fifo_clear: 2;
+        /// Software reset
+        SOFTWARE_RESET: software_reset, set_software_reset: 3;
+        /// Abort the current access
+        ABORT: abort, set_abort: 4;
+    }
+}
+
+register_bits! {
+    /// HWICAP status register
+    pub struct IcapStatus(u32) {
+        /// The configuration interface is ready
+        DONE: done, set_done: 0;
+        /// End of startup
+        END_OF_STARTUP: end_of_startup, set_end_of_startup: 2;
+        /// The configuration is synchronised
+        SYNCHRONISED: synchronised, set_synchronised: 7;
+        /// No configuration error, low on error
+        NO_CONFIGURATION_ERROR: no_configuration_error, set_no_configuration_error: 8;
+    }
+}
@@ -0,0 +1,427 @@
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use embedded_hal::i2c::{
+    ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress,
+};
+use std::time::Duration;
+
+/// Default time to wait for each step of a transfer
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Soft reset key
+const SOFT_RESET_KEY: u32 = 0xa;
+/// Largest number of bytes of a read in dynamic controller mode
+const MAX_READ_LENGTH: usize = 255;
+
+/// Target address on the IIC bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IicAddress {
+    /// 7-bit address
+    Seven(u8),
+    /// 10-bit address
+    Ten(u16),
+}
+
+/// AXI IIC (PG090)
+///
+/// Transfers use the dynamic controller logic, start, stop and the read byte count are queued
+/// in the transmit FIFO together with the data. Consecutive operations of a transaction are
+/// joined with repeated starts. Implements the `embedded-hal` [`I2c`] trait for 7-bit and
+/// 10-bit addresses.
+pub struct AxiIic {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    timeout: Duration,
+}
+
+impl AxiIic {
+    /// Creates a new `AxiIic` instance from a UIO device and resets the controller.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiIic, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut iic = Self::from_mmio(registers, Some(interrupt));
+        iic.reset()?;
+        Ok(iic)
+    }
+
+    /// Creates a new `AxiIic` instance from a memory mapped register region.
+    ///
+    /// The controller has to be initialised with [`AxiIic::reset`] before use. Without an
+    /// interrupt the status register is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiIic {
+        AxiIic {
+            registers,
+            interrupt,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the time to wait for bus access and for each step of a transfer.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Resets the controller and enables it as bus controller.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::SOFTR, SOFT_RESET_KEY)?;
+        // Interrupt as soon as one byte is received
+        self.registers.write(Registers::RX_FIFO_PIRQ, 0)?;
+        self.registers
+            .write(Registers::CR, Control::TX_FIFO_RESET)?;
+        self.registers.write(Registers::CR, Control::ENABLE)?;
+        self.registers.acknowledge(Registers::ISR)?;
+        self.registers
+            .write(Registers::IER, IicInterrupt::empty())?;
+        self.registers
+            .write(Registers::GIE, GlobalInterrupt::ENABLE)
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<IicStatus, Error> {
+        self.registers.read(Registers::SR)
+    }
+
+    /// Checks if the bus is busy, between a start and a stop condition.
+    pub fn is_bus_busy(&self) -> Result<bool, Error> {
+        Ok(self.status()?.bus_busy())
+    }
+
+    /// Performs the operations as one transaction with the target.
+    ///
+    /// Operations of the same kind are joined, between a write and a read a repeated start is
+    /// issued. Reads are limited to 255 bytes per group of joined read operations. The
+    /// transaction cannot end with an empty write. The controller is reset after a failed
+    /// transaction.
+    pub fn transaction(
+        &mut self,
+        address: IicAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        match address {
+            IicAddress::Seven(address) if address > 0x7f => return Err(Error::InvalidArgument),
+            IicAddress::Ten(address) if address > 0x3ff => return Err(Error::InvalidArgument),
+            _ => {}
+        }
+        if operations.is_empty() {
+            return Ok(());
+        }
+        if let Some(Operation::Write(data)) = operations.last()
+            && data.is_empty()
+        {
+            return Err(Error::Unsupported);
+        }
+        match self.wait(IicInterrupt::BUS_NOT_BUSY, |status| !status.bus_busy()) {
+            Err(Error::Timeout) => return Err(Error::Busy),
+            result => result?,
+        }
+        self.registers.modify(Registers::CR, |control| {
+            control.set_tx_fifo_reset(true);
+        })?;
+        self.registers.modify(Registers::CR, |control| {
+            control.set_tx_fifo_reset(false);
+        })?;
+        self.registers.acknowledge(Registers::ISR)?;
+        let result = self.run(address, operations);
+        if let Err(ref error) = result {
+            log::warn!("transaction with {:?} failed, {:?}", address, error);
+            self.reset()?;
+        }
+        result
+    }
+
+    fn run(&mut self, address: IicAddress, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        let mut start = 0;
+        while start < operations.len() {
+            let read = matches!(operations[start], Operation::Read(_));
+            let end = operations[start..]
+                .iter()
+                .position(|operation| matches!(operation, Operation::Read(_)) != read)
+                .map_or(operations.len(), |n| start + n);
+            let last = end == operations.len();
+            let group = &mut operations[start..end];
+            if read {
+                self.read_group(address, group, last)?;
+            } else {
+                self.write_group(address, group, last)?;
+            }
+            start = end;
+        }
+        self.wait(IicInterrupt::BUS_NOT_BUSY, |status| !status.bus_busy())
+    }
+
+    fn write_group(
+        &mut self,
+        address: IicAddress,
+        group: &[Operation<'_>],
+        last: bool,
+    ) -> Result<(), Error> {
+        match address {
+            IicAddress::Seven(address) => {
+                self.push(TxWord::START | TxWord::data(address << 1))?;
+            }
+            IicAddress::Ten(address) => {
+                self.push(TxWord::START | TxWord::data(ten_bit_header(address)))?;
+                self.push(TxWord::data(address as u8))?;
+            }
+        }
+        let total: usize = group.iter().map(operation_length).sum();
+        let bytes = group.iter().flat_map(|operation| match operation {
+            Operation::Write(data) => data.iter(),
+            Operation::Read(_) => [].iter(),
+        });
+        for (n, byte) in bytes.enumerate() {
+            let mut word = TxWord::data(*byte);
+            word.set_stop(last && n + 1 == total);
+            self.push(word)?;
+        }
+        Ok(())
+    }
+
+    fn read_group(
+        &mut self,
+        address: IicAddress,
+        group: &mut [Operation<'_>],
+        last: bool,
+    ) -> Result<(), Error> {
+        let total: usize = group.iter().map(operation_length).sum();
+        if total == 0 || total > MAX_READ_LENGTH {
+            return Err(Error::InvalidArgument);
+        }
+        match address {
+            IicAddress::Seven(address) => {
+                self.push(TxWord::START | TxWord::data((address << 1) | 1))?;
+            }
+            IicAddress::Ten(address) => {
+                // The full address is written, then the header is repeated for the read
+                let header = ten_bit_header(address);
+                self.push(TxWord::START | TxWord::data(header))?;
+                self.push(TxWord::data(address as u8))?;
+                self.push(TxWord::START | TxWord::data(header | 1))?;
+            }
+        }
+        let mut count = TxWord::data(total as u8);
+        count.set_stop(last);
+        self.push(count)?;
+        for operation in group.iter_mut() {
+            if let Operation::Read(buffer) = operation {
+                for byte in buffer.iter_mut() {
+                    self.wait(IicInterrupt::RX_FULL, |status| !status.rx_fifo_empty())?;
+                    *byte = self.registers.read(Registers::RX_FIFO)? as u8;
+                }
+            }
+        }
+        // The controller does not acknowledge the last byte, which raises the transmit error
+        let pending = self.registers.read(Registers::ISR)?;
+        self.registers
+            .acknowledge_bits(Registers::ISR, pending & IicInterrupt::TX_ERROR)
+    }
+
+    /// Queues a word in the transmit FIFO, waiting for space.
+    fn push(&mut self, word: TxWord) -> Result<(), Error> {
+        self.wait(IicInterrupt::TX_HALF_EMPTY, |status| !status.tx_fifo_full())?;
+        self.registers.write(Registers::TX_FIFO, word)
+    }
+
+    /// Waits until `condition` holds for the status, sleeping on `events`.
+    ///
+    /// Arbitration loss and missing acknowledge are reported as errors. While waiting for
+    /// received bytes, a transmit error with received data pending is the missing acknowledge
+    /// of the controller after the last byte and is not an error.
+    fn wait<F>(&mut self, events: IicInterrupt, mut condition: F) -> Result<(), Error>
+    where
+        F: FnMut(IicStatus) -> bool,
+    {
+        let errors = IicInterrupt::ARBITRATION_LOST | IicInterrupt::TX_ERROR;
+        let registers = &mut self.registers;
+        registers.write(Registers::IER, errors | events)?;
+        let result = wait_for(self.interrupt.as_ref(), Some(self.timeout), || {
+            let pending = registers.read(Registers::ISR)?;
+            if pending.arbitration_lost() {
+                return Err(Error::ArbitrationLost);
+            }
+            let status = registers.read(Registers::SR)?;
+            let received = pending.rx_full() || !status.rx_fifo_empty();
+            if pending.tx_error() && !(events.rx_full() && received) {
+                return Err(Error::NoAcknowledge);
+            }
+            registers.acknowledge_bits(Registers::ISR, pending & events)?;
+            Ok(condition(status).then_some(()))
+        });
+        registers.write(Registers::IER, IicInterrupt::empty())?;
+        result
+    }
+}
+
+/// Returns the first byte of a 10-bit address, `11110` followed by the two high address bits.
+fn ten_bit_header(address: u16) -> u8 {
+    0xf0 | ((address >> 7) as u8 & 0x06)
+}
+
+fn operation_length(operation: &Operation<'_>) -> usize {
+    match operation {
+        Operation::Read(buffer) => buffer.len(),
+        Operation::Write(data) => data.len(),
+    }
+}
+
+impl embedded_hal::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            Error::NoAcknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Busy => ErrorKind::Bus,
+            Error::OverRun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for AxiIic {
+    type Error = Error;
+}
+
+impl I2c<SevenBitAddress> for AxiIic {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        AxiIic::transaction(self, IicAddress::Seven(address), operations)
+    }
+}
+
+impl I2c<TenBitAddress> for AxiIic {
+    fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        AxiIic::transaction(self, IicAddress::Ten(address), operations)
+    }
+}
+
+register_map! {
+    /// AXI IIC registers
+    struct Registers {
+        /// Global interrupt enable
+        GIE: GlobalInterrupt, RW @ 0x01c;
+        /// Interrupt status, bits toggle on write
+        ISR: IicInterrupt, TOW @ 0x020 = 0x0000_00d0;
+        /// Interrupt enable
+        IER: IicInterrupt, RW @ 0x028;
+        /// Soft reset, write the reset key
+        SOFTR: u32, WO @ 0x040;
+        /// Control register
+        CR: Control, RW @ 0x100;
+        /// Status register
+        SR: IicStatus, RO @ 0x104 = 0x0000_00c0;
+        /// Transmit FIFO, data with start and stop flags in dynamic mode
+        TX_FIFO: TxWord, WO @ 0x108;
+        /// Receive FIFO
+        RX_FIFO: u32, RO @ 0x10c;
+        /// Target mode address
+        ADR: u32, RW @ 0x110;
+        /// Transmit FIFO occupancy minus one
+        TX_FIFO_OCY: u32, RO @ 0x114;
+        /// Receive FIFO occupancy minus one
+        RX_FIFO_OCY: u32, RO @ 0x118;
+        /// Target mode 10-bit address
+        TEN_ADR: u32, RW @ 0x11c;
+        /// Receive FIFO level for the receive FIFO full interrupt, minus one
+        RX_FIFO_PIRQ: u32, RW @ 0x120;
+        /// General purpose outputs
+        GPO: u32, RW @ 0x124;
+    }
+}
+
+register_bits! {
+    /// Global interrupt enable register
+    struct GlobalInterrupt(u32) {
+        /// Enable the interrupt output
+        ENABLE: enable, set_enable: 31;
+    }
+}
+
+register_bits! {
+    /// Interrupt status and enable bits
+    struct IicInterrupt(u32) {
+        /// Arbitration lost
+        ARBITRATION_LOST: arbitration_lost, set_arbitration_lost: 0;
+        /// Transmit error, no acknowledge from the target
+        TX_ERROR: tx_error, set_tx_error: 1;
+        /// Transmit FIFO empty
+        TX_EMPTY: tx_empty, set_tx_empty: 2;
+        /// Receive FIFO reached the programmed level
+        RX_FULL: rx_full, set_rx_full: 3;
+        /// Bus not busy
+        BUS_NOT_BUSY: bus_not_busy, set_bus_not_busy: 4;
+        /// Addressed as target
+        ADDRESSED: addressed, set_addressed: 5;
+        /// Not addressed as target
+        NOT_ADDRESSED: not_addressed, set_not_addressed: 6;
+        /// Transmit FIFO half empty
+        TX_HALF_EMPTY: tx_half_empty, set_tx_half_empty: 7;
+    }
+}
+
+register_bits! {
+    /// Control register
+    struct Control(u32) {
+        /// Enable the controller
+        ENABLE: enable, set_enable: 0;
+        /// Reset the transmit FIFO
+        TX_FIFO_RESET: tx_fifo_reset, set_tx_fifo_reset: 1;
+        /// Controller mode, a rising edge generates a start, a falling edge a stop
+        MSMS: msms, set_msms: 2;
+        /// Transmit direction
+        TX: tx, set_tx: 3;
+        /// Transmit acknowledge disable
+        TXAK: txak, set_txak: 4;
+        /// Repeated start
+        RSTA: rsta, set_rsta: 5;
+        /// General call enable
+        GC_ENABLE: gc_enable, set_gc_enable: 6;
+    }
+}
+
+register_bits! {
+    /// IIC status register
+    pub struct IicStatus(u32) {
+        /// Addressed by a general call
+        GENERAL_CALL: general_call, set_general_call: 0;
+        /// Addressed as target
+        ADDRESSED: addressed, set_addressed: 1;
+        /// Bus busy, between start and stop
+        BUS_BUSY: bus_busy, set_bus_busy: 2;
+        /// Target read/write direction
+        TARGET_READ: target_read, set_target_read: 3;
+        /// Transmit FIFO full
+        TX_FIFO_FULL: tx_fifo_full, set_tx_fifo_full: 4;
+        /// Receive FIFO full
+        RX_FIFO_FULL: rx_fifo_full, set_rx_fifo_full: 5;
+        /// Receive FIFO empty
+        RX_FIFO_EMPTY: rx_fifo_empty, set_rx_fifo_empty: 6;
+        /// Transmit FIFO empty
+        TX_FIFO_EMPTY: tx_fifo_empty, set_tx_fifo_empty: 7;
+    }
+}
+
+register_bits! {
+    /// Transmit FIFO word in dynamic controller mode
+    struct TxWord(u32) {
+        /// Data, address or read byte count
+        DATA: data_bits, set_data_bits: 7, 0;
+        /// Generate a start, or a repeated start, before the byte
+        START: start, set_start: 8;
+        /// Generate a stop after the byte, or after the read count is received
+        STOP: stop, set_stop: 9;
+    }
+}
+
+impl TxWord {
+    fn data(byte: u8) -> TxWord {
+        TxWord(u32::from(byte))
+    }
+}
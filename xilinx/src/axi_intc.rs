@@ -0,0 +1,412 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Maximum number of interrupt inputs
+const MAX_SOURCES: u32 = 32;
+
+/// Interval at which a dispatcher thread checks if it has been stopped
+const DISPATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handler called by the dispatcher
+type Handler = Box<dyn FnMut() + Send>;
+
+/// What the dispatcher does with an interrupt source
+enum Slot {
+    /// Not in use, the source is masked when it fires
+    Free,
+    /// Called on every interrupt, the source stays enabled
+    Handler(Handler),
+    /// Wakes an [`Interrupt`], the source is masked until the waiter enables it again
+    Waiter { event: File, count: Arc<AtomicU32> },
+}
+
+/// Controller state shared with the dispatcher and the source interrupts
+struct Controller {
+    registers: Mutex<Mmio>,
+    interrupt: Option<Interrupt>,
+    slots: Mutex<Vec<Slot>>,
+}
+
+impl Controller {
+    fn registers(&self) -> MutexGuard<'_, Mmio> {
+        self.registers
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn slots(&self) -> MutexGuard<'_, Vec<Slot>> {
+        self.slots.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn enable(&self, source: u32) -> Result<(), Error> {
+        self.registers().write(Registers::SIE, 1 << source)
+    }
+
+    fn disable(&self, source: u32) -> Result<(), Error> {
+        self.registers().write(Registers::CIE, 1 << source)
+    }
+}
+
+/// Interrupt source of an [`AxiIntc`] waited for through an [`Interrupt`]
+pub(crate) struct IntcSource {
+    controller: Arc<Controller>,
+    source: u32,
+    count: Arc<AtomicU32>,
+}
+
+impl IntcSource {
+    /// Unmasks the source.
+    pub(crate) fn enable(&self) -> Result<(), Error> {
+        self.controller.enable(self.source)
+    }
+
+    /// Masks the source.
+    pub(crate) fn disable(&self) -> Result<(), Error> {
+        self.controller.disable(self.source)
+    }
+
+    /// Returns the number of dispatched interrupts.
+    pub(crate) fn count(&self) -> u32 {
+        self.count.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for IntcSource {
+    fn drop(&mut self) {
+        if let Err(error) = self.controller.disable(self.source) {
+            log::warn!(
+                "failed to disable interrupt source {}, {:?}",
+                self.source,
+                error
+            );
+        }
+        self.controller.slots()[self.source as usize] = Slot::Free;
+    }
+}
+
+/// AXI Interrupt Controller (PG099)
+///
+/// Collects the interrupt sources of a design into the single UIO interrupt. The dispatcher
+/// waits on the UIO interrupt and for each pending source either calls the registered handler
+/// or wakes the [`Interrupt`] returned by [`AxiIntc::interrupt`]. Such an interrupt behaves as a
+/// UIO interrupt, drivers waiting on it are unaware of the controller.
+pub struct AxiIntc {
+    controller: Arc<Controller>,
+    sources: u32,
+}
+
+impl AxiIntc {
+    /// Creates a new `AxiIntc` instance from a UIO device.
+    ///
+    /// The number of sources is taken from `xlnx,num-intr-inputs` when available. The
+    /// controller has to be started with [`AxiIntc::start`].
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiIntc, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut intc = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node()
+            && let Ok(sources) = devicetree::read_u32(node, "xlnx,num-intr-inputs")
+        {
+            intc.set_source_count(sources)?;
+        }
+        Ok(intc)
+    }
+
+    /// Creates a new `AxiIntc` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt the dispatcher polls the status register. The controller has to be
+    /// started with [`AxiIntc::start`].
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiIntc {
+        let slots = (0..MAX_SOURCES).map(|_| Slot::Free).collect();
+        AxiIntc {
+            controller: Arc::new(Controller {
+                registers: Mutex::new(registers),
+                interrupt,
+                slots: Mutex::new(slots),
+            }),
+            sources: MAX_SOURCES,
+        }
+    }
+
+    /// Sets the number of interrupt inputs, `C_NUM_INTR_INPUTS`, 1 to 32.
+    pub fn set_source_count(&mut self, sources: u32) -> Result<(), Error> {
+        if !(1..=MAX_SOURCES).contains(&sources) {
+            return Err(Error::InvalidArgument);
+        }
+        self.sources = sources;
+        Ok(())
+    }
+
+    /// Returns the number of interrupt inputs.
+    pub fn source_count(&self) -> u32 {
+        self.sources
+    }
+
+    fn check_source(&self, source: u32) -> Result<(), Error> {
+        if source >= self.sources {
+            return Err(Error::OutOfRange);
+        }
+        Ok(())
+    }
+
+    /// Disables all sources, acknowledges pending interrupts and disables the controller
+    /// output.
+    ///
+    /// The hardware interrupt enable can not be cleared once set.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        let mut registers = self.controller.registers();
+        registers.write(Registers::MER, MasterEnable::empty())?;
+        registers.write(Registers::IER, 0)?;
+        registers.write(Registers::IAR, u32::MAX)
+    }
+
+    /// Resets the controller, then enables the controller output and hardware interrupts.
+    ///
+    /// Sources with a handler or a waiter are enabled again.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.reset()?;
+        let enabled = self
+            .controller
+            .slots()
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| matches!(slot, Slot::Handler(_) | Slot::Waiter { .. }))
+            .fold(0u32, |mask, (source, _)| mask | 1 << source);
+        let mut registers = self.controller.registers();
+        registers.write(Registers::IER, enabled)?;
+        registers.write(
+            Registers::MER,
+            MasterEnable::MASTER_ENABLE | MasterEnable::HARDWARE_INTERRUPT_ENABLE,
+        )
+    }
+
+    /// Enables the source.
+    pub fn enable(&self, source: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        self.controller.enable(source)
+    }
+
+    /// Disables the source.
+    pub fn disable(&self, source: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        self.controller.disable(source)
+    }
+
+    /// Acknowledges the interrupts in `mask`.
+    pub fn acknowledge(&self, mask: u32) -> Result<(), Error> {
+        self.controller.registers().write(Registers::IAR, mask)
+    }
+
+    /// Returns the active sources, enabled or not.
+    pub fn status(&self) -> Result<u32, Error> {
+        self.controller.registers().read(Registers::ISR)
+    }
+
+    /// Returns the enabled sources.
+    pub fn enabled(&self) -> Result<u32, Error> {
+        self.controller.registers().read(Registers::IER)
+    }
+
+    /// Returns the active and enabled sources.
+    pub fn pending(&self) -> Result<u32, Error> {
+        let registers = self.controller.registers();
+        Ok(registers.read(Registers::ISR)? & registers.read(Registers::IER)?)
+    }
+
+    /// Calls `handler` from the dispatcher on every interrupt of the source, and enables the
+    /// source.
+    ///
+    /// The handler has to clear the interrupt condition of the device, it is acknowledged in the
+    /// controller afterwards. The handler must not use the controller.
+    pub fn set_handler<F>(&self, source: u32, handler: F) -> Result<(), Error>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.check_source(source)?;
+        {
+            let mut slots = self.controller.slots();
+            if matches!(slots[source as usize], Slot::Waiter { .. }) {
+                return Err(Error::Busy);
+            }
+            slots[source as usize] = Slot::Handler(Box::new(handler));
+        }
+        self.controller.enable(source)
+    }
+
+    /// Removes the handler of the source, and disables the source.
+    pub fn remove_handler(&self, source: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        self.controller.disable(source)?;
+        let mut slots = self.controller.slots();
+        if matches!(slots[source as usize], Slot::Handler(_)) {
+            slots[source as usize] = Slot::Free;
+        }
+        Ok(())
+    }
+
+    /// Returns an interrupt woken by the dispatcher when the source fires.
+    ///
+    /// As with a UIO interrupt the source is masked when it fires and unmasked by
+    /// [`Interrupt::enable`], so it can be handed to any driver taking an interrupt. Dropping
+    /// the interrupt releases the source.
+    pub fn interrupt(&self, source: u32) -> Result<Interrupt, Error> {
+        self.check_source(source)?;
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        let count = Arc::new(AtomicU32::new(0));
+        {
+            let mut slots = self.controller.slots();
+            if !matches!(slots[source as usize], Slot::Free) {
+                return Err(Error::Busy);
+            }
+            slots[source as usize] = Slot::Waiter {
+                event: file.try_clone()?,
+                count: count.clone(),
+            };
+        }
+        let source = IntcSource {
+            controller: self.controller.clone(),
+            source,
+            count,
+        };
+        Ok(Interrupt::from_intc(file, source))
+    }
+
+    /// Waits for interrupts and dispatches the pending sources, returns the dispatched sources.
+    ///
+    /// Fails with [`Error::Timeout`] if no source became pending in time.
+    pub fn dispatch(&self, timeout: Option<Duration>) -> Result<u32, Error> {
+        dispatch(&self.controller, timeout)
+    }
+
+    /// Dispatches interrupts on a thread until the returned dispatcher is stopped or dropped.
+    pub fn spawn(&self) -> Result<IntcDispatcher, Error> {
+        let controller = self.controller.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name("axi-intc".to_string())
+            .spawn(move || {
+                while !stopped.load(Ordering::Acquire) {
+                    match dispatch(&controller, Some(DISPATCH_INTERVAL)) {
+                        Ok(_) | Err(Error::Timeout) => {}
+                        Err(error) => {
+                            log::warn!("interrupt dispatch failed, {:?}", error);
+                            return Err(error);
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+        Ok(IntcDispatcher {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn dispatch(controller: &Controller, timeout: Option<Duration>) -> Result<u32, Error> {
+    let pending = wait_for(controller.interrupt.as_ref(), timeout, || {
+        let registers = controller.registers();
+        let pending = registers.read(Registers::ISR)? & registers.read(Registers::IER)?;
+        Ok((pending != 0).then_some(pending))
+    })?;
+    let mut slots = controller.slots();
+    for source in (0..MAX_SOURCES).filter(|source| pending & (1 << source) != 0) {
+        match &mut slots[source as usize] {
+            Slot::Handler(handler) => handler(),
+            Slot::Waiter { event, count } => {
+                // Masked until the waiter has served the device, as UIO does
+                controller.disable(source)?;
+                count.fetch_add(1, Ordering::AcqRel);
+                event.write_all(&1u64.to_ne_bytes())?;
+            }
+            Slot::Free => {
+                log::warn!("interrupt source {} without handler, disabled", source);
+                controller.disable(source)?;
+            }
+        }
+    }
+    controller.registers().write(Registers::IAR, pending)?;
+    Ok(pending)
+}
+
+/// Thread dispatching the interrupts of an [`AxiIntc`]
+pub struct IntcDispatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl IntcDispatcher {
+    /// Checks if the dispatcher thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stops the dispatcher thread, returns the error that ended the thread, if any.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), Error> {
+        self.stop.store(true, Ordering::Release);
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or(Err(Error::Unsupported)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for IntcDispatcher {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+register_map! {
+    /// AXI INTC registers
+    struct Registers {
+        /// Interrupt status, active sources
+        ISR: u32, RW @ 0x00;
+        /// Interrupt pending, active and enabled sources
+        IPR: u32, RO @ 0x04;
+        /// Interrupt enable
+        IER: u32, RW @ 0x08;
+        /// Interrupt acknowledge
+        IAR: u32, WO @ 0x0c;
+        /// Set interrupt enables
+        SIE: u32, WO @ 0x10;
+        /// Clear interrupt enables
+        CIE: u32, WO @ 0x14;
+        /// Interrupt vector, lowest pending source
+        IVR: u32, RO @ 0x18;
+        /// Master enable
+        MER: MasterEnable, RW @ 0x1c;
+    }
+}
+
+register_bits! {
+    /// Master enable register
+    struct MasterEnable(u32) {
+        /// Enable the interrupt output
+        MASTER_ENABLE: master_enable, set_master_enable: 0;
+        /// Enable hardware interrupts, software interrupts only when clear, write once
+        HARDWARE_INTERRUPT_ENABLE: hardware_interrupt_enable, set_hardware_interrupt_enable: 1;
+    }
+}
@@ -0,0 +1,275 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Default depth of the mailbox FIFOs
+const DEFAULT_DEPTH: u32 = 16;
+
+/// AXI Mailbox (PG114)
+///
+/// One side of a mailbox, sends words to and receives words from the processor on the other
+/// interface. Blocking sends and receives sleep on the threshold interrupts when there is a UIO
+/// interrupt.
+pub struct AxiMailbox {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    depth: u32,
+}
+
+impl AxiMailbox {
+    /// Creates a new `AxiMailbox` instance from a UIO device.
+    ///
+    /// The FIFO depth is taken from `xlnx,mailbox-depth` when available.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiMailbox, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut mailbox = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node()
+            && let Ok(depth) = devicetree::read_u32(node, "xlnx,mailbox-depth")
+        {
+            mailbox.set_depth(depth)?;
+        }
+        Ok(mailbox)
+    }
+
+    /// Creates a new `AxiMailbox` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt the status register is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiMailbox {
+        AxiMailbox {
+            registers,
+            interrupt,
+            depth: DEFAULT_DEPTH,
+        }
+    }
+
+    /// Sets the FIFO depth, `C_MAILBOX_DEPTH`.
+    pub fn set_depth(&mut self, depth: u32) -> Result<(), Error> {
+        if !depth.is_power_of_two() || depth < 16 {
+            return Err(Error::InvalidArgument);
+        }
+        self.depth = depth;
+        Ok(())
+    }
+
+    /// Returns the FIFO depth.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Discards the contents of both FIFOs, pending errors and interrupts.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(
+            Registers::CTRL,
+            Control::RESET_SEND_FIFO | Control::RESET_RECEIVE_FIFO,
+        )?;
+        self.registers.read(Registers::ERROR)?;
+        self.registers
+            .write(Registers::IE, MailboxInterrupt::empty())?;
+        self.registers.acknowledge(Registers::IS)?;
+        Ok(())
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<MailboxStatus, Error> {
+        self.registers.read(Registers::STATUS)
+    }
+
+    /// Returns and clears the error flags, a read of an empty or a write to a full FIFO.
+    ///
+    /// Reported as [`Error::Empty`] and [`Error::Full`].
+    pub fn take_error(&mut self) -> Result<Option<Error>, Error> {
+        let error = self.registers.read(Registers::ERROR)?;
+        Ok(if error.read_empty() {
+            Some(Error::Empty)
+        } else if error.write_full() {
+            Some(Error::Full)
+        } else {
+            None
+        })
+    }
+
+    /// Sets the send threshold, the send interrupt is raised when the send FIFO holds this
+    /// number of words or fewer.
+    pub fn set_send_threshold(&mut self, words: u32) -> Result<(), Error> {
+        if words >= self.depth {
+            return Err(Error::OutOfRange);
+        }
+        self.registers.write(Registers::SIT, words)
+    }
+
+    /// Sets the receive threshold, the receive interrupt is raised when the receive FIFO holds
+    /// more than this number of words.
+    pub fn set_receive_threshold(&mut self, words: u32) -> Result<(), Error> {
+        if words >= self.depth {
+            return Err(Error::OutOfRange);
+        }
+        self.registers.write(Registers::RIT, words)
+    }
+
+    /// Enables the interrupts, other interrupts are disabled.
+    pub fn enable_interrupts(&mut self, interrupts: MailboxInterrupt) -> Result<(), Error> {
+        self.registers.write(Registers::IE, interrupts)
+    }
+
+    /// Returns and acknowledges the pending interrupts.
+    pub fn interrupt_status(&mut self) -> Result<MailboxInterrupt, Error> {
+        self.registers.acknowledge(Registers::IS)
+    }
+
+    /// Writes as many words as fit in the send FIFO and returns the number of words written.
+    pub fn try_send(&mut self, words: &[u32]) -> Result<usize, Error> {
+        send(&mut self.registers, words)
+    }
+
+    /// Waits until all words are written to the send FIFO.
+    pub fn send(&mut self, words: &[u32], timeout: Option<Duration>) -> Result<(), Error> {
+        // Wake when the send FIFO has drained below half
+        self.set_send_threshold(self.depth / 2)?;
+        let registers = &mut self.registers;
+        let mut written = 0;
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            acknowledge(registers, MailboxInterrupt::SEND_THRESHOLD)?;
+            written += send(registers, &words[written..])?;
+            if written == words.len() {
+                return Ok(Some(()));
+            }
+            registers.write(Registers::IE, MailboxInterrupt::SEND_THRESHOLD)?;
+            Ok(None)
+        });
+        self.registers
+            .write(Registers::IE, MailboxInterrupt::empty())?;
+        result
+    }
+
+    /// Reads the received words without waiting and returns the number of words read.
+    pub fn try_receive(&mut self, words: &mut [u32]) -> Result<usize, Error> {
+        receive(&mut self.registers, words)
+    }
+
+    /// Waits until words are received and returns the number of words read.
+    pub fn receive(
+        &mut self,
+        words: &mut [u32],
+        timeout: Option<Duration>,
+    ) -> Result<usize, Error> {
+        if words.is_empty() {
+            return Ok(0);
+        }
+        // Wake when the receive FIFO holds any word
+        self.set_receive_threshold(0)?;
+        let registers = &mut self.registers;
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            acknowledge(registers, MailboxInterrupt::RECEIVE_THRESHOLD)?;
+            let count = receive(registers, words)?;
+            if count > 0 {
+                return Ok(Some(count));
+            }
+            registers.write(Registers::IE, MailboxInterrupt::RECEIVE_THRESHOLD)?;
+            Ok(None)
+        });
+        self.registers
+            .write(Registers::IE, MailboxInterrupt::empty())?;
+        result
+    }
+}
+
+fn send(registers: &mut Mmio, words: &[u32]) -> Result<usize, Error> {
+    let mut count = 0;
+    while count < words.len() && !registers.read(Registers::STATUS)?.tx_full() {
+        registers.write(Registers::WRDATA, words[count])?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn receive(registers: &mut Mmio, words: &mut [u32]) -> Result<usize, Error> {
+    let mut count = 0;
+    while count < words.len() && !registers.read(Registers::STATUS)?.rx_empty() {
+        words[count] = registers.read(Registers::RDDATA)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Acknowledges the interrupt if it is pending.
+fn acknowledge(registers: &mut Mmio, interrupt: MailboxInterrupt) -> Result<(), Error> {
+    let pending = registers.read(Registers::IS)? & interrupt;
+    registers.acknowledge_bits(Registers::IS, pending)
+}
+
+register_map! {
+    /// AXI Mailbox registers
+    struct Registers {
+        /// Write data, to the send FIFO
+        WRDATA: u32, WO @ 0x00;
+        /// Read data, from the receive FIFO
+        RDDATA: u32, RO @ 0x08;
+        /// Status
+        STATUS: MailboxStatus, RO @ 0x10 = 0x0000_0005;
+        /// Error, cleared when read
+        ERROR: MailboxError, RO @ 0x14;
+        /// Send interrupt threshold
+        SIT: u32, RW @ 0x18;
+        /// Receive interrupt threshold
+        RIT: u32, RW @ 0x1c;
+        /// Interrupt status, toggle on write
+        IS: MailboxInterrupt, TOW @ 0x20;
+        /// Interrupt enable
+        IE: MailboxInterrupt, RW @ 0x24;
+        /// Interrupt pending
+        IP: MailboxInterrupt, RO @ 0x28;
+        /// Control
+        CTRL: Control, WO @ 0x2c;
+    }
+}
+
+register_bits! {
+    /// Mailbox status register
+    pub struct MailboxStatus(u32) {
+        /// The receive FIFO is empty
+        RX_EMPTY: rx_empty, set_rx_empty: 0;
+        /// The send FIFO is full
+        TX_FULL: tx_full, set_tx_full: 1;
+        /// The send FIFO level is at or below the send threshold
+        SEND_THRESHOLD: send_threshold, set_send_threshold: 2;
+        /// The receive FIFO level is above the receive threshold
+        RECEIVE_THRESHOLD: receive_threshold, set_receive_threshold: 3;
+    }
+}
+
+register_bits! {
+    /// Mailbox error register
+    struct MailboxError(u32) {
+        /// Read of the empty receive FIFO
+        READ_EMPTY: read_empty, set_read_empty: 0;
+        /// Write to the full send FIFO
+        WRITE_FULL: write_full, set_write_full: 1;
+    }
+}
+
+register_bits! {
+    /// Mailbox interrupts
+    pub struct MailboxInterrupt(u32) {
+        /// The send FIFO level is at or below the send threshold
+        SEND_THRESHOLD: send_threshold, set_send_threshold: 0;
+        /// The receive FIFO level is above the receive threshold
+        RECEIVE_THRESHOLD: receive_threshold, set_receive_threshold: 1;
+        /// An error flag was set
+        ERROR: error, set_error: 2;
+    }
+}
+
+register_bits! {
+    /// Control register
+    struct Control(u32) {
+        /// Reset the send FIFO
+        RESET_SEND_FIFO: reset_send_fifo, set_reset_send_fifo: 0;
+        /// Reset the receive FIFO
+        RESET_RECEIVE_FIFO: reset_receive_fifo, set_reset_receive_fifo: 1;
+    }
+}
@@ -0,0 +1,202 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Distance between the registers of consecutive mutexes
+const MUTEX_STRIDE: usize = 0x100;
+
+/// Maximum number of mutexes
+const MAX_MUTEXES: u32 = 32;
+
+/// Registers and the mutexes locked through this instance
+struct State {
+    registers: Mmio,
+    held: u32,
+}
+
+/// AXI Mutex (PG111)
+///
+/// Hardware mutexes shared between processors. A mutex is locked with the CPU ID of this
+/// processor and unlocked when the returned guard is dropped. Each mutex has an optional user
+/// register, written by the owner.
+pub struct AxiMutex {
+    state: Mutex<State>,
+    cpu_id: u8,
+    count: u32,
+}
+
+impl AxiMutex {
+    /// Creates a new `AxiMutex` instance from a UIO device, locking with `cpu_id`.
+    ///
+    /// The number of mutexes is taken from `xlnx,num-mutex` when available.
+    pub fn try_from(device: &DeviceInfo, cpu_id: u8) -> Result<AxiMutex, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let mut mutex = Self::from_mmio(registers, cpu_id);
+        if let Some(node) = device.of_node()
+            && let Ok(count) = devicetree::read_u32(node, "xlnx,num-mutex")
+        {
+            mutex.set_count(count)?;
+        }
+        Ok(mutex)
+    }
+
+    /// Creates a new `AxiMutex` instance from a memory mapped register region, locking with
+    /// `cpu_id`.
+    ///
+    /// The CPU ID has to differ from the IDs used by the other processors.
+    pub fn from_mmio(registers: Mmio, cpu_id: u8) -> AxiMutex {
+        AxiMutex {
+            state: Mutex::new(State { registers, held: 0 }),
+            cpu_id,
+            count: MAX_MUTEXES,
+        }
+    }
+
+    /// Sets the number of mutexes, `C_NUM_MUTEX`.
+    pub fn set_count(&mut self, count: u32) -> Result<(), Error> {
+        if count == 0 || count > MAX_MUTEXES {
+            return Err(Error::InvalidArgument);
+        }
+        self.count = count;
+        Ok(())
+    }
+
+    /// Returns the number of mutexes.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the CPU ID used to lock.
+    pub fn cpu_id(&self) -> u8 {
+        self.cpu_id
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn check_mutex(&self, mutex: u32) -> Result<(), Error> {
+        if mutex >= self.count {
+            return Err(Error::OutOfRange);
+        }
+        Ok(())
+    }
+
+    fn lock_value(&self, locked: bool) -> MutexValue {
+        let mut value = MutexValue::empty();
+        value.set_locked(locked);
+        value.set_cpu_id(u32::from(self.cpu_id));
+        value
+    }
+
+    /// Tries to lock the mutex, returns `None` if it is locked by another processor or through
+    /// another guard.
+    pub fn try_lock(&self, mutex: u32) -> Result<Option<AxiMutexGuard<'_>>, Error> {
+        self.check_mutex(mutex)?;
+        let mut state = self.lock_state();
+        if state.held & (1 << mutex) != 0 {
+            return Ok(None);
+        }
+        let register = Registers::MUTEX.shifted(mutex as usize * MUTEX_STRIDE);
+        let value = self.lock_value(true);
+        // The write is ignored while another processor holds the mutex
+        state.registers.write(register, value)?;
+        if state.registers.read(register)? != value {
+            return Ok(None);
+        }
+        state.held |= 1 << mutex;
+        Ok(Some(AxiMutexGuard { owner: self, mutex }))
+    }
+
+    /// Waits until the mutex is locked.
+    pub fn lock(&self, mutex: u32, timeout: Option<Duration>) -> Result<AxiMutexGuard<'_>, Error> {
+        wait_for(None, timeout, || self.try_lock(mutex))
+    }
+
+    /// Returns the CPU ID of the processor holding the mutex, if it is locked.
+    pub fn owner(&self, mutex: u32) -> Result<Option<u8>, Error> {
+        self.check_mutex(mutex)?;
+        let register = Registers::MUTEX.shifted(mutex as usize * MUTEX_STRIDE);
+        let value = self.lock_state().registers.read(register)?;
+        Ok(value.locked().then_some(value.cpu_id() as u8))
+    }
+
+    /// Returns the user register of the mutex.
+    pub fn user(&self, mutex: u32) -> Result<u32, Error> {
+        self.check_mutex(mutex)?;
+        let register = Registers::USER.shifted(mutex as usize * MUTEX_STRIDE);
+        self.lock_state().registers.read(register)
+    }
+
+    fn unlock(&self, mutex: u32) -> Result<(), Error> {
+        let mut state = self.lock_state();
+        let register = Registers::MUTEX.shifted(mutex as usize * MUTEX_STRIDE);
+        state.registers.write(register, self.lock_value(false))?;
+        state.held &= !(1 << mutex);
+        Ok(())
+    }
+}
+
+/// Locked mutex of an [`AxiMutex`], unlocked when dropped
+pub struct AxiMutexGuard<'a> {
+    owner: &'a AxiMutex,
+    mutex: u32,
+}
+
+impl AxiMutexGuard<'_> {
+    /// Returns the mutex number.
+    pub fn mutex(&self) -> u32 {
+        self.mutex
+    }
+
+    /// Returns the user register.
+    pub fn user(&self) -> Result<u32, Error> {
+        self.owner.user(self.mutex)
+    }
+
+    /// Writes the user register, only the owner of the mutex can write it.
+    pub fn set_user(&self, value: u32) -> Result<(), Error> {
+        let register = Registers::USER.shifted(self.mutex as usize * MUTEX_STRIDE);
+        self.owner.lock_state().registers.write(register, value)
+    }
+
+    /// Unlocks the mutex.
+    pub fn unlock(self) -> Result<(), Error> {
+        let result = self.owner.unlock(self.mutex);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for AxiMutexGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(error) = self.owner.unlock(self.mutex) {
+            log::warn!("failed to unlock mutex {}, {:?}", self.mutex, error);
+        }
+    }
+}
+
+register_map! {
+    /// AXI Mutex registers of mutex 0, followed by the other mutexes
+    struct Registers {
+        /// Lock state and owner
+        MUTEX: MutexValue, RW @ 0x00;
+        /// User value
+        USER: u32, RW @ 0x04;
+    }
+}
+
+register_bits! {
+    /// Mutex register
+    struct MutexValue(u32) {
+        /// The mutex is locked
+        LOCKED: locked, set_locked: 0;
+        /// CPU ID of the owner
+        CPU_ID: cpu_id, set_cpu_id: 8, 1;
+    }
+}
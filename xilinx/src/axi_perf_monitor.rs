@@ -0,0 +1,474 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Maximum number of metric counters
+pub const APM_MAX_COUNTERS: usize = 10;
+
+/// Maximum number of monitor slots
+const MAX_SLOTS: u8 = 8;
+
+/// Distance between the registers of consecutive metric counters
+const COUNTER_STRIDE: usize = 0x10;
+
+/// Metric counted by a metric counter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApmMetric {
+    /// Write transactions
+    WriteTransactions,
+    /// Read transactions
+    ReadTransactions,
+    /// Bytes written
+    WriteBytes,
+    /// Bytes read
+    ReadBytes,
+    /// Write beats
+    WriteBeats,
+    /// Sum of the read latencies in clock cycles
+    ReadLatency,
+    /// Sum of the write latencies in clock cycles
+    WriteLatency,
+    /// Cycles the slave was idle during writes
+    WriteIdle,
+    /// Cycles the master was idle during reads
+    ReadIdle,
+    /// Write responses
+    WriteResponses,
+    /// Last write beats
+    WriteLasts,
+    /// Last read beats
+    ReadLasts,
+    /// Minimum write latency in clock cycles
+    MinWriteLatency,
+    /// Maximum write latency in clock cycles
+    MaxWriteLatency,
+    /// Minimum read latency in clock cycles
+    MinReadLatency,
+    /// Maximum read latency in clock cycles
+    MaxReadLatency,
+    /// AXI4-Stream transfer cycles
+    StreamTransfers,
+    /// AXI4-Stream packets
+    StreamPackets,
+    /// AXI4-Stream data bytes
+    StreamBytes,
+    /// External event count
+    ExternalEvents,
+}
+
+impl ApmMetric {
+    /// Returns the metric selector code.
+    fn code(&self) -> u32 {
+        match self {
+            ApmMetric::WriteTransactions => 0,
+            ApmMetric::ReadTransactions => 1,
+            ApmMetric::WriteBytes => 2,
+            ApmMetric::ReadBytes => 3,
+            ApmMetric::WriteBeats => 4,
+            ApmMetric::ReadLatency => 5,
+            ApmMetric::WriteLatency => 6,
+            ApmMetric::WriteIdle => 7,
+            ApmMetric::ReadIdle => 8,
+            ApmMetric::WriteResponses => 9,
+            ApmMetric::WriteLasts => 10,
+            ApmMetric::ReadLasts => 11,
+            ApmMetric::MinWriteLatency => 12,
+            ApmMetric::MaxWriteLatency => 13,
+            ApmMetric::MinReadLatency => 14,
+            ApmMetric::MaxReadLatency => 15,
+            ApmMetric::StreamTransfers => 16,
+            ApmMetric::StreamPackets => 17,
+            ApmMetric::StreamBytes => 18,
+            ApmMetric::ExternalEvents => 30,
+        }
+    }
+}
+
+/// Sampled metric counters
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApmSample {
+    /// Clock cycles covered by the sample
+    pub cycles: u64,
+    /// Slot, metric and value of the configured counters
+    pub counters: Vec<(u8, ApmMetric, u64)>,
+}
+
+impl ApmSample {
+    /// Returns the value of the metric of the slot, if it is counted.
+    pub fn value(&self, slot: u8, metric: ApmMetric) -> Option<u64> {
+        self.counters
+            .iter()
+            .find(|(s, m, _)| *s == slot && *m == metric)
+            .map(|(_, _, value)| *value)
+    }
+}
+
+/// Bandwidth and latency of a monitor slot over a sample
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApmSlotStatistics {
+    /// Monitor slot
+    pub slot: u8,
+    /// Read bandwidth in bytes per second
+    pub read_bandwidth: Option<f64>,
+    /// Write bandwidth in bytes per second
+    pub write_bandwidth: Option<f64>,
+    /// Average read latency
+    pub read_latency: Option<Duration>,
+    /// Average write latency
+    pub write_latency: Option<Duration>,
+}
+
+/// AXI Performance Monitor (PG037)
+///
+/// Counts metrics of the monitored AXI slots in up to ten metric counters, sampled on demand or
+/// every sample interval. Samples are decoded to bandwidth and average latency per slot with
+/// the AXI clock frequency. Requires the advanced mode of the core.
+pub struct AxiPerformanceMonitor {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    clock_frequency: u64,
+    counters: [Option<(u8, ApmMetric)>; APM_MAX_COUNTERS],
+    counter_count: usize,
+    interval: Option<u64>,
+    last_clock: u64,
+}
+
+impl AxiPerformanceMonitor {
+    /// Creates a new `AxiPerformanceMonitor` instance from a UIO device.
+    ///
+    /// Without `clock_frequency` the AXI clock frequency is taken from the `clock-frequency`
+    /// device tree property. The number of counters is taken from `xlnx,num-of-counters` when
+    /// available.
+    pub fn try_from(
+        device: &DeviceInfo,
+        clock_frequency: Option<u64>,
+    ) -> Result<AxiPerformanceMonitor, Error> {
+        let node = device.of_node();
+        let clock_frequency = match (clock_frequency, node) {
+            (Some(frequency), _) => frequency,
+            (None, Some(node)) => u64::from(devicetree::read_u32(node, "clock-frequency")?),
+            (None, None) => return Err(Error::InvalidArgument),
+        };
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut monitor = Self::from_mmio(registers, Some(interrupt), clock_frequency)?;
+        if let Some(node) = node
+            && let Ok(counters) = devicetree::read_u32(node, "xlnx,num-of-counters")
+        {
+            monitor.set_counter_count(counters as usize)?;
+        }
+        Ok(monitor)
+    }
+
+    /// Creates a new `AxiPerformanceMonitor` instance from a memory mapped register region and
+    /// the AXI clock frequency in Hz.
+    ///
+    /// Without an interrupt the end of a sample interval is polled.
+    pub fn from_mmio(
+        registers: Mmio,
+        interrupt: Option<Interrupt>,
+        clock_frequency: u64,
+    ) -> Result<AxiPerformanceMonitor, Error> {
+        if clock_frequency == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(AxiPerformanceMonitor {
+            registers,
+            interrupt,
+            clock_frequency,
+            counters: [None; APM_MAX_COUNTERS],
+            counter_count: APM_MAX_COUNTERS,
+            interval: None,
+            last_clock: 0,
+        })
+    }
+
+    /// Sets the number of metric counters, `C_NUM_OF_COUNTERS`.
+    pub fn set_counter_count(&mut self, counters: usize) -> Result<(), Error> {
+        if counters == 0 || counters > APM_MAX_COUNTERS {
+            return Err(Error::InvalidArgument);
+        }
+        self.counter_count = counters;
+        Ok(())
+    }
+
+    /// Returns the number of metric counters.
+    pub fn counter_count(&self) -> usize {
+        self.counter_count
+    }
+
+    /// Returns the AXI clock frequency in Hz.
+    pub fn clock_frequency(&self) -> u64 {
+        self.clock_frequency
+    }
+
+    /// Selects the metric and slot counted by the metric counter.
+    pub fn set_metric(&mut self, counter: usize, slot: u8, metric: ApmMetric) -> Result<(), Error> {
+        if counter >= self.counter_count || slot >= MAX_SLOTS {
+            return Err(Error::OutOfRange);
+        }
+        // Four counters per selector register, a byte each
+        let register = Registers::MSR0.shifted(counter / 4 * 4);
+        let shift = (counter % 4) * 8;
+        let mut selector = self.registers.read(register)?;
+        selector &= !(0xff << shift);
+        selector |= (metric.code() | u32::from(slot) << 5) << shift;
+        self.registers.write(register, selector)?;
+        self.counters[counter] = Some((slot, metric));
+        Ok(())
+    }
+
+    /// Counts bytes, transactions and total latency of reads and writes of the slot, using six
+    /// counters starting at `first_counter`.
+    pub fn monitor_slot(&mut self, slot: u8, first_counter: usize) -> Result<(), Error> {
+        if first_counter + SLOT_METRICS.len() > self.counter_count {
+            return Err(Error::OutOfRange);
+        }
+        for (n, metric) in SLOT_METRICS.iter().enumerate() {
+            self.set_metric(first_counter + n, slot, *metric)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the sample interval, `None` samples on demand only.
+    ///
+    /// Takes effect with the next start.
+    pub fn set_sample_interval(&mut self, interval: Option<Duration>) -> Result<(), Error> {
+        self.interval = match interval {
+            Some(interval) => {
+                let cycles =
+                    (interval.as_nanos() * u128::from(self.clock_frequency) / 1_000_000_000) as u64;
+                if cycles == 0 || cycles > u64::from(u32::MAX) {
+                    return Err(Error::OutOfRange);
+                }
+                Some(cycles)
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Resets and starts the metric counters and the global clock counter.
+    ///
+    /// The metric counters are reset with every sample, so that each sample covers the time
+    /// since the previous one.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.registers.write(
+            Registers::CR,
+            Control::RESET_METRIC_COUNTERS | Control::RESET_GLOBAL_CLOCK,
+        )?;
+        self.registers
+            .write(Registers::SICR, SampleControl::RESET_ON_SAMPLE)?;
+        let pending = self.registers.read(Registers::ISR)?;
+        self.registers.write(Registers::ISR, pending)?;
+        if let Some(cycles) = self.interval {
+            self.registers.write(Registers::SIR, cycles as u32)?;
+            self.registers.write(
+                Registers::SICR,
+                SampleControl::RESET_ON_SAMPLE | SampleControl::LOAD,
+            )?;
+            self.registers.write(
+                Registers::SICR,
+                SampleControl::RESET_ON_SAMPLE | SampleControl::ENABLE,
+            )?;
+            self.registers
+                .write(Registers::IER, ApmInterrupt::SAMPLE_INTERVAL)?;
+            self.registers
+                .write(Registers::GIER, GlobalInterrupt::ENABLE)?;
+        }
+        self.registers.write(
+            Registers::CR,
+            Control::ENABLE_METRIC_COUNTERS | Control::ENABLE_GLOBAL_CLOCK,
+        )?;
+        self.last_clock = 0;
+        Ok(())
+    }
+
+    /// Stops the counters and the sample interval.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::CR, Control::empty())?;
+        self.registers
+            .write(Registers::SICR, SampleControl::empty())?;
+        self.registers.write(Registers::IER, ApmInterrupt::empty())
+    }
+
+    /// Returns the global clock counter, the clock cycles since start.
+    pub fn global_clock(&self) -> Result<u64, Error> {
+        // Read the high half again if the low half wrapped in between
+        loop {
+            let high = self.registers.read(Registers::GCC_HIGH)?;
+            let low = self.registers.read(Registers::GCC_LOW)?;
+            if self.registers.read(Registers::GCC_HIGH)? == high {
+                return Ok(u64::from(high) << 32 | u64::from(low));
+            }
+        }
+    }
+
+    /// Returns the running value of the metric counter.
+    pub fn counter(&self, counter: usize) -> Result<u32, Error> {
+        if counter >= self.counter_count {
+            return Err(Error::OutOfRange);
+        }
+        self.registers
+            .read(Registers::MC.shifted(counter * COUNTER_STRIDE))
+    }
+
+    fn read_sample(&self, cycles: u64) -> Result<ApmSample, Error> {
+        let mut counters = Vec::new();
+        for (counter, selection) in self.counters[..self.counter_count].iter().enumerate() {
+            if let Some((slot, metric)) = selection {
+                let value = self
+                    .registers
+                    .read(Registers::SMC.shifted(counter * COUNTER_STRIDE))?;
+                counters.push((*slot, *metric, u64::from(value)));
+            }
+        }
+        Ok(ApmSample { cycles, counters })
+    }
+
+    /// Samples the metric counters now, the sample covers the time since the previous sample or
+    /// the start.
+    pub fn sample(&mut self) -> Result<ApmSample, Error> {
+        // Reading the sample register latches the metric counters
+        self.registers.read(Registers::SR)?;
+        let clock = self.global_clock()?;
+        let cycles = clock.saturating_sub(self.last_clock);
+        self.last_clock = clock;
+        self.read_sample(cycles)
+    }
+
+    /// Waits for the end of the sample interval and returns the sample.
+    pub fn wait_sample(&mut self, timeout: Option<Duration>) -> Result<ApmSample, Error> {
+        let cycles = self.interval.ok_or(Error::InvalidArgument)?;
+        let registers = &mut self.registers;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            let pending = registers.read(Registers::ISR)?;
+            if !pending.sample_interval() {
+                return Ok(None);
+            }
+            registers.write(Registers::ISR, ApmInterrupt::SAMPLE_INTERVAL)?;
+            Ok(Some(()))
+        })?;
+        self.last_clock = self.global_clock()?;
+        self.read_sample(cycles)
+    }
+
+    /// Decodes the sample to bandwidth and average latency of each counted slot.
+    pub fn statistics(&self, sample: &ApmSample) -> Vec<ApmSlotStatistics> {
+        let mut slots: Vec<u8> = sample.counters.iter().map(|(slot, _, _)| *slot).collect();
+        slots.sort_unstable();
+        slots.dedup();
+        let seconds = sample.cycles as f64 / self.clock_frequency as f64;
+        let bandwidth = |slot, metric| {
+            sample
+                .value(slot, metric)
+                .filter(|_| sample.cycles > 0)
+                .map(|bytes| bytes as f64 / seconds)
+        };
+        let latency = |slot, total, transactions| {
+            let total = sample.value(slot, total)?;
+            let transactions = sample.value(slot, transactions).filter(|t| *t > 0)?;
+            let nanos = u128::from(total) * 1_000_000_000
+                / (u128::from(transactions) * u128::from(self.clock_frequency));
+            Some(Duration::from_nanos(nanos as u64))
+        };
+        slots
+            .into_iter()
+            .map(|slot| ApmSlotStatistics {
+                slot,
+                read_bandwidth: bandwidth(slot, ApmMetric::ReadBytes),
+                write_bandwidth: bandwidth(slot, ApmMetric::WriteBytes),
+                read_latency: latency(slot, ApmMetric::ReadLatency, ApmMetric::ReadTransactions),
+                write_latency: latency(slot, ApmMetric::WriteLatency, ApmMetric::WriteTransactions),
+            })
+            .collect()
+    }
+}
+
+/// Metrics counted by [`AxiPerformanceMonitor::monitor_slot`]
+const SLOT_METRICS: [ApmMetric; 6] = [
+    ApmMetric::ReadBytes,
+    ApmMetric::WriteBytes,
+    ApmMetric::ReadTransactions,
+    ApmMetric::WriteTransactions,
+    ApmMetric::ReadLatency,
+    ApmMetric::WriteLatency,
+];
+
+register_map! {
+    /// AXI Performance Monitor registers
+    struct Registers {
+        /// Global clock counter, upper half
+        GCC_HIGH: u32, RO @ 0x0000;
+        /// Global clock counter, lower half
+        GCC_LOW: u32, RO @ 0x0004;
+        /// Sample interval in clock cycles
+        SIR: u32, RW @ 0x0024;
+        /// Sample interval control
+        SICR: SampleControl, RW @ 0x0028;
+        /// Sample register, reading samples the metric counters
+        SR: u32, RO @ 0x002c;
+        /// Global interrupt enable
+        GIER: GlobalInterrupt, RW @ 0x0030;
+        /// Interrupt enable
+        IER: ApmInterrupt, RW @ 0x0034;
+        /// Interrupt status, toggle on write
+        ISR: ApmInterrupt, W1C @ 0x0038;
+        /// Metric selector for counters 0 to 3, followed by counters 4 to 7 and 8 to 9
+        MSR0: u32, RW @ 0x0044;
+        /// Metric counter 0, followed by the other counters
+        MC: u32, RO @ 0x0100;
+        /// Sampled metric counter 0, followed by the other counters
+        SMC: u32, RO @ 0x0200;
+        /// Control register
+        CR: Control, RW @ 0x0300;
+    }
+}
+
+register_bits! {
+    /// Global interrupt enable register
+    struct GlobalInterrupt(u32) {
+        /// Global interrupt enable
+        ENABLE: enable, set_enable: 0;
+    }
+}
+
+register_bits! {
+    /// Interrupt status and enable registers
+    struct ApmInterrupt(u32) {
+        /// Global clock counter overflow
+        GLOBAL_CLOCK_OVERFLOW: global_clock_overflow, set_global_clock_overflow: 0;
+        /// Sample interval expired
+        SAMPLE_INTERVAL: sample_interval, set_sample_interval: 1;
+    }
+}
+
+register_bits! {
+    /// Sample interval control register
+    struct SampleControl(u32) {
+        /// Enable the sample interval counter
+        ENABLE: enable, set_enable: 0;
+        /// Load the sample interval counter
+        LOAD: load, set_load: 1;
+        /// Reset the metric counters when sampled
+        RESET_ON_SAMPLE: reset_on_sample, set_reset_on_sample: 8;
+    }
+}
+
+register_bits! {
+    /// Control register
+    struct Control(u32) {
+        /// Enable the metric counters
+        ENABLE_METRIC_COUNTERS: enable_metric_counters, set_enable_metric_counters: 0;
+        /// Reset the metric counters
+        RESET_METRIC_COUNTERS: reset_metric_counters, set_reset_metric_counters: 1;
+        /// Enable the global clock counter
+        ENABLE_GLOBAL_CLOCK: enable_global_clock, set_enable_global_clock: 16;
+        /// Reset the global clock counter
+        RESET_GLOBAL_CLOCK: reset_global_clock, set_reset_global_clock: 17;
+    }
+}
@@ -0,0 +1,480 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use embedded_hal::spi::{
+    ErrorKind, ErrorType, Mode, Operation, Phase, Polarity, SpiBus, SpiDevice,
+};
+use std::time::Duration;
+
+/// Default time to wait for a FIFO worth of data
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Soft reset key
+const SOFT_RESET_KEY: u32 = 0xa;
+
+/// SPI protocol of the core, `C_SPI_MODE`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiMode {
+    /// One data line in each direction
+    Standard,
+    /// Dual I/O flash commands
+    Dual,
+    /// Dual and quad I/O flash commands
+    Quad,
+}
+
+/// AXI Quad SPI (PG153)
+///
+/// Controller mode transfers of 8-bit words through the AXI4-Lite register interface. Data is
+/// exchanged a FIFO at a time, the transfer is inhibited while the transmit FIFO is filled.
+/// With manual slave select the chip select stays asserted across FIFO refills.
+///
+/// In dual and quad mode the core decodes the first byte of a transaction as a flash command
+/// and switches the address and data phases to the command's lines, see
+/// [`SpiNor::set_read_mode`](crate::SpiNor::set_read_mode). Only SPI modes 0 and 3 and most
+/// significant bit first are supported then.
+///
+/// Implements the `embedded-hal` [`SpiBus`] trait on the selected slave, see
+/// [`AxiQuadSpi::select`], and [`SpiDevice`] through [`AxiQuadSpi::device`].
+pub struct AxiQuadSpi {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    fifo_depth: usize,
+    slaves: u32,
+    spi_mode: SpiMode,
+    control: Control,
+    timeout: Duration,
+}
+
+impl AxiQuadSpi {
+    /// Creates a new `AxiQuadSpi` instance from a UIO device and resets the controller.
+    ///
+    /// The number of slave selects and the SPI protocol are taken from the `xlnx,num-ss-bits`
+    /// and `xlnx,spi-mode` device tree properties. Only 8-bit transfers,
+    /// `xlnx,num-transfer-bits`, are supported.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiQuadSpi, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut spi = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node() {
+            if let Ok(bits) = devicetree::read_u32(node, "xlnx,num-transfer-bits")
+                && bits != 8
+            {
+                return Err(Error::Unsupported);
+            }
+            if let Ok(slaves) = devicetree::read_u32(node, "xlnx,num-ss-bits") {
+                spi.slaves = slaves.clamp(1, 32);
+            }
+            if let Ok(mode) = devicetree::read_u32(node, "xlnx,spi-mode") {
+                spi.spi_mode = match mode {
+                    0 => SpiMode::Standard,
+                    1 => SpiMode::Dual,
+                    2 => SpiMode::Quad,
+                    _ => return Err(Error::Unsupported),
+                };
+            }
+        }
+        spi.reset()?;
+        Ok(spi)
+    }
+
+    /// Creates a new `AxiQuadSpi` instance from a memory mapped register region.
+    ///
+    /// The controller has to be initialised with [`AxiQuadSpi::reset`] before use. Without an
+    /// interrupt completion is polled. The core is assumed to be in standard mode, see
+    /// [`AxiQuadSpi::set_spi_mode`].
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiQuadSpi {
+        AxiQuadSpi {
+            registers,
+            interrupt,
+            fifo_depth: 1,
+            slaves: 1,
+            spi_mode: SpiMode::Standard,
+            control: Control::MASTER | Control::MANUAL_SLAVE_SELECT,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the number of slave select outputs, `C_NUM_SS_BITS`.
+    pub fn set_slaves(&mut self, slaves: u32) {
+        self.slaves = slaves.clamp(1, 32);
+    }
+
+    /// Sets the SPI protocol of the core, `C_SPI_MODE`.
+    pub fn set_spi_mode(&mut self, spi_mode: SpiMode) {
+        self.spi_mode = spi_mode;
+    }
+
+    /// Returns the SPI protocol of the core.
+    pub fn spi_mode(&self) -> SpiMode {
+        self.spi_mode
+    }
+
+    /// Sets the time to wait for each FIFO worth of data.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the FIFO depth, detected by [`AxiQuadSpi::reset`].
+    pub fn fifo_depth(&self) -> usize {
+        self.fifo_depth
+    }
+
+    /// Resets the controller, deselects all slaves and detects the FIFO depth.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::SRR, SOFT_RESET_KEY)?;
+        self.registers.write(Registers::SPISSR, u32::MAX)?;
+        self.registers.write(
+            Registers::SPICR,
+            self.control
+                | Control::ENABLE
+                | Control::MASTER_INHIBIT
+                | Control::TX_FIFO_RESET
+                | Control::RX_FIFO_RESET,
+        )?;
+        // Fill the inhibited transmit FIFO to find its depth
+        let mut depth = 0;
+        while !self.status()?.tx_full() && depth < 0x10000 {
+            self.registers.write(Registers::SPI_DTR, 0)?;
+            depth += 1;
+        }
+        self.fifo_depth = depth.max(1);
+        log::debug!("FIFO depth {}", self.fifo_depth);
+        self.registers.write(
+            Registers::SPICR,
+            self.control
+                | Control::ENABLE
+                | Control::MASTER_INHIBIT
+                | Control::TX_FIFO_RESET
+                | Control::RX_FIFO_RESET,
+        )?;
+        self.registers.acknowledge(Registers::IPISR)?;
+        self.registers
+            .write(Registers::IPIER, SpiInterrupt::empty())?;
+        self.registers
+            .write(Registers::DGIER, GlobalInterrupt::ENABLE)
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<SpiStatus, Error> {
+        self.registers.read(Registers::SPISR)
+    }
+
+    /// Sets the clock polarity and phase.
+    ///
+    /// Fails with [`Error::Unsupported`] for modes 1 and 2 in dual or quad mode.
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), Error> {
+        let cpol = mode.polarity == Polarity::IdleHigh;
+        let cpha = mode.phase == Phase::CaptureOnSecondTransition;
+        if self.spi_mode != SpiMode::Standard && cpol != cpha {
+            return Err(Error::Unsupported);
+        }
+        self.control.set_cpol(cpol);
+        self.control.set_cpha(cpha);
+        self.update_control()
+    }
+
+    /// Transfers the least significant bit first when set.
+    ///
+    /// Fails with [`Error::Unsupported`] in dual or quad mode.
+    pub fn set_lsb_first(&mut self, lsb_first: bool) -> Result<(), Error> {
+        if self.spi_mode != SpiMode::Standard && lsb_first {
+            return Err(Error::Unsupported);
+        }
+        self.control.set_lsb_first(lsb_first);
+        self.update_control()
+    }
+
+    /// Drives the slave selects from [`AxiQuadSpi::select`] when set. Otherwise the controller
+    /// asserts the selected slave only while data is shifted, deasserting it between FIFO
+    /// refills.
+    pub fn set_manual_slave_select(&mut self, manual: bool) -> Result<(), Error> {
+        self.control.set_manual_slave_select(manual);
+        self.update_control()
+    }
+
+    fn update_control(&mut self) -> Result<(), Error> {
+        let control = self.control;
+        self.registers.modify(Registers::SPICR, |value| {
+            let inhibit = value.master_inhibit();
+            *value = control | Control::ENABLE;
+            value.set_master_inhibit(inhibit);
+        })
+    }
+
+    /// Selects slave `slave`, asserting its slave select in manual mode.
+    pub fn select(&mut self, slave: u32) -> Result<(), Error> {
+        if slave >= self.slaves {
+            return Err(Error::InvalidArgument);
+        }
+        self.registers.write(Registers::SPISSR, !(1 << slave))
+    }
+
+    /// Deselects all slaves.
+    pub fn deselect(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::SPISSR, u32::MAX)
+    }
+
+    /// Returns a device on slave select `slave`, implementing [`SpiDevice`].
+    pub fn device(&mut self, slave: u32) -> Result<QuadSpiDevice<'_>, Error> {
+        if slave >= self.slaves {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(QuadSpiDevice { spi: self, slave })
+    }
+
+    /// Exchanges bytes with the selected slave, `write` and `read` have the same length.
+    pub fn exchange(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        if write.len() != read.len() {
+            return Err(Error::LengthMismatch);
+        }
+        for (write, read) in write
+            .chunks(self.fifo_depth)
+            .zip(read.chunks_mut(self.fifo_depth))
+        {
+            self.exchange_fifo(write, read)?;
+        }
+        Ok(())
+    }
+
+    /// Exchanges at most a FIFO of bytes.
+    fn exchange_fifo(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        for byte in write {
+            self.registers.write(Registers::SPI_DTR, u32::from(*byte))?;
+        }
+        self.registers.acknowledge(Registers::IPISR)?;
+        self.registers.write(
+            Registers::IPIER,
+            SpiInterrupt::TX_EMPTY | SpiInterrupt::MODE_FAULT,
+        )?;
+        self.registers.modify(Registers::SPICR, |control| {
+            control.set_master_inhibit(false)
+        })?;
+        let result = self.receive(read);
+        self.registers
+            .modify(Registers::SPICR, |control| control.set_master_inhibit(true))?;
+        self.registers
+            .write(Registers::IPIER, SpiInterrupt::empty())?;
+        if let Err(ref error) = result {
+            log::warn!("transfer failed, {:?}", error);
+            self.registers.modify(Registers::SPICR, |control| {
+                *control |= Control::TX_FIFO_RESET | Control::RX_FIFO_RESET;
+            })?;
+        }
+        result
+    }
+
+    fn receive(&mut self, read: &mut [u8]) -> Result<(), Error> {
+        let registers = &mut self.registers;
+        let mut count = 0;
+        wait_for(self.interrupt.as_ref(), Some(self.timeout), || {
+            let pending = registers.read(Registers::IPISR)?;
+            if pending.mode_fault() {
+                return Err(Error::ModeFault);
+            }
+            if pending.rx_overrun() {
+                return Err(Error::OverRun);
+            }
+            // Dual and quad mode reject commands unknown to the core
+            if registers.read(Registers::SPISR)?.command_error() {
+                return Err(Error::Unsupported);
+            }
+            registers.acknowledge_bits(Registers::IPISR, pending & SpiInterrupt::TX_EMPTY)?;
+            while count < read.len() && !registers.read(Registers::SPISR)?.rx_empty() {
+                read[count] = registers.read(Registers::SPI_DRR)? as u8;
+                count += 1;
+            }
+            Ok((count == read.len()).then_some(()))
+        })
+    }
+}
+
+/// Device on one slave select of an [`AxiQuadSpi`]
+///
+/// The slave is selected for the duration of each transaction.
+pub struct QuadSpiDevice<'a> {
+    spi: &'a mut AxiQuadSpi,
+    slave: u32,
+}
+
+impl embedded_hal::spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::OverRun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for AxiQuadSpi {
+    type Error = Error;
+}
+
+impl SpiBus for AxiQuadSpi {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let write = vec![0; words.len()];
+        self.exchange(&write, words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        let mut read = vec![0; words.len()];
+        self.exchange(words, &mut read)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let length = read.len().max(write.len());
+        let mut tx = write.to_vec();
+        tx.resize(length, 0);
+        let mut rx = vec![0; length];
+        self.exchange(&tx, &mut rx)?;
+        read.copy_from_slice(&rx[..read.len()]);
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let write = words.to_vec();
+        self.exchange(&write, words)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        // Transfers complete before returning
+        Ok(())
+    }
+}
+
+impl ErrorType for QuadSpiDevice<'_> {
+    type Error = Error;
+}
+
+impl SpiDevice for QuadSpiDevice<'_> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.spi.select(self.slave)?;
+        let result = operations
+            .iter_mut()
+            .try_for_each(|operation| match operation {
+                Operation::Read(words) => self.spi.read(words),
+                Operation::Write(words) => self.spi.write(words),
+                Operation::Transfer(read, write) => self.spi.transfer(read, write),
+                Operation::TransferInPlace(words) => self.spi.transfer_in_place(words),
+                Operation::DelayNs(ns) => {
+                    std::thread::sleep(Duration::from_nanos(u64::from(*ns)));
+                    Ok(())
+                }
+            });
+        self.spi.deselect()?;
+        result
+    }
+}
+
+register_map! {
+    /// AXI Quad SPI registers
+    struct Registers {
+        /// Global interrupt enable
+        DGIER: GlobalInterrupt, RW @ 0x1c;
+        /// Interrupt status, bits toggle on write
+        IPISR: SpiInterrupt, TOW @ 0x20;
+        /// Interrupt enable
+        IPIER: SpiInterrupt, RW @ 0x28;
+        /// Soft reset, write the reset key
+        SRR: u32, WO @ 0x40;
+        /// Control register
+        SPICR: Control, RW @ 0x60 = 0x0000_0180;
+        /// Status register
+        SPISR: SpiStatus, RO @ 0x64 = 0x0000_00a5;
+        /// Transmit data
+        SPI_DTR: u32, WO @ 0x68;
+        /// Receive data
+        SPI_DRR: u32, RO @ 0x6c;
+        /// Slave select, active low
+        SPISSR: u32, RW @ 0x70 = 0xffff_ffff;
+        /// Transmit FIFO occupancy minus one
+        TX_FIFO_OCY: u32, RO @ 0x74;
+        /// Receive FIFO occupancy minus one
+        RX_FIFO_OCY: u32, RO @ 0x78;
+    }
+}
+
+register_bits! {
+    /// Global interrupt enable register
+    struct GlobalInterrupt(u32) {
+        /// Enable the interrupt output
+        ENABLE: enable, set_enable: 31;
+    }
+}
+
+register_bits! {
+    /// Interrupt status and enable bits
+    struct SpiInterrupt(u32) {
+        /// Mode fault
+        MODE_FAULT: mode_fault, set_mode_fault: 0;
+        /// Slave mode fault
+        SLAVE_MODE_FAULT: slave_mode_fault, set_slave_mode_fault: 1;
+        /// Transmit register or FIFO empty
+        TX_EMPTY: tx_empty, set_tx_empty: 2;
+        /// Transmit underrun, slave mode
+        TX_UNDERRUN: tx_underrun, set_tx_underrun: 3;
+        /// Receive register or FIFO full
+        RX_FULL: rx_full, set_rx_full: 4;
+        /// Receive overrun
+        RX_OVERRUN: rx_overrun, set_rx_overrun: 5;
+        /// Transmit FIFO half empty
+        TX_HALF_EMPTY: tx_half_empty, set_tx_half_empty: 6;
+    }
+}
+
+register_bits! {
+    /// SPI control register
+    struct Control(u32) {
+        /// Local loopback
+        LOOPBACK: loopback, set_loopback: 0;
+        /// SPI system enable
+        ENABLE: enable, set_enable: 1;
+        /// Controller mode
+        MASTER: master, set_master: 2;
+        /// Clock polarity, idle high when set
+        CPOL: cpol, set_cpol: 3;
+        /// Clock phase, capture on the second edge when set
+        CPHA: cpha, set_cpha: 4;
+        /// Reset the transmit FIFO
+        TX_FIFO_RESET: tx_fifo_reset, set_tx_fifo_reset: 5;
+        /// Reset the receive FIFO
+        RX_FIFO_RESET: rx_fifo_reset, set_rx_fifo_reset: 6;
+        /// Slave select follows the slave select register
+        MANUAL_SLAVE_SELECT: manual_slave_select, set_manual_slave_select: 7;
+        /// Inhibit transfers
+        MASTER_INHIBIT: master_inhibit, set_master_inhibit: 8;
+        /// Least significant bit first
+        LSB_FIRST: lsb_first, set_lsb_first: 9;
+    }
+}
+
+register_bits! {
+    /// SPI status register
+    pub struct SpiStatus(u32) {
+        /// Receive FIFO empty
+        RX_EMPTY: rx_empty, set_rx_empty: 0;
+        /// Receive FIFO full
+        RX_FULL: rx_full, set_rx_full: 1;
+        /// Transmit FIFO empty
+        TX_EMPTY: tx_empty, set_tx_empty: 2;
+        /// Transmit FIFO full
+        TX_FULL: tx_full, set_tx_full: 3;
+        /// Mode fault
+        MODE_FAULT: mode_fault, set_mode_fault: 4;
+        /// Slave mode select input asserted
+        SLAVE_MODE_SELECT: slave_mode_select, set_slave_mode_select: 5;
+        /// Unsupported clock polarity and phase for the configured mode
+        CPOL_CPHA_ERROR: cpol_cpha_error, set_cpol_cpha_error: 6;
+        /// Slave mode not supported in dual or quad mode
+        SLAVE_MODE_ERROR: slave_mode_error, set_slave_mode_error: 7;
+        /// LSB first not supported in dual or quad mode
+        MSB_ERROR: msb_error, set_msb_error: 8;
+        /// Loopback not supported in dual or quad mode
+        LOOPBACK_ERROR: loopback_error, set_loopback_error: 9;
+        /// Unsupported command in dual or quad mode
+        COMMAND_ERROR: command_error, set_command_error: 10;
+    }
+}
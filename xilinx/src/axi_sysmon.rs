@@ -0,0 +1,593 @@
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::register::{RW, Register};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Software reset key
+const RESET_KEY: u32 = 0x0000_000a;
+
+/// Full scale of the supply sensors in volts
+const SUPPLY_FULL_SCALE: f64 = 3.0;
+
+/// Analog to digital converter family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysmonFamily {
+    /// 7 series and Zynq-7000 XADC
+    Xadc,
+    /// UltraScale SYSMONE1
+    UltraScale,
+    /// UltraScale+ SYSMONE4
+    UltraScalePlus,
+}
+
+impl SysmonFamily {
+    /// Returns the gain and offset of the temperature transfer function.
+    fn temperature_transfer(&self) -> (f64, f64) {
+        match self {
+            SysmonFamily::Xadc => (503.975, 273.15),
+            SysmonFamily::UltraScale => (502.9098, 273.8195),
+            SysmonFamily::UltraScalePlus => (509.314, 280.2309),
+        }
+    }
+}
+
+/// Channel of the system monitor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysmonChannel {
+    /// Die temperature
+    Temperature,
+    /// Internal supply
+    VccInt,
+    /// Auxiliary supply
+    VccAux,
+    /// Dedicated analog input
+    VpVn,
+    /// Positive reference
+    VrefP,
+    /// Negative reference
+    VrefN,
+    /// Block RAM supply
+    VccBram,
+    /// Processing system internal supply
+    VccPInt,
+    /// Processing system auxiliary supply
+    VccPAux,
+    /// Processing system DDR I/O supply
+    VccODdr,
+    /// Auxiliary analog input, 0 to 15
+    Aux(u8),
+}
+
+impl SysmonChannel {
+    /// Returns the channel number, also the index of the status register.
+    fn number(&self) -> Result<usize, Error> {
+        Ok(match self {
+            SysmonChannel::Temperature => 0,
+            SysmonChannel::VccInt => 1,
+            SysmonChannel::VccAux => 2,
+            SysmonChannel::VpVn => 3,
+            SysmonChannel::VrefP => 4,
+            SysmonChannel::VrefN => 5,
+            SysmonChannel::VccBram => 6,
+            SysmonChannel::VccPInt => 13,
+            SysmonChannel::VccPAux => 14,
+            SysmonChannel::VccODdr => 15,
+            SysmonChannel::Aux(n) if *n < 16 => 16 + usize::from(*n),
+            SysmonChannel::Aux(_) => return Err(Error::OutOfRange),
+        })
+    }
+
+    /// Returns the bit of the channel in the sequencer registers, the auxiliary inputs are in
+    /// the upper half.
+    fn sequence_bit(&self) -> Result<u32, Error> {
+        Ok(match self {
+            SysmonChannel::VccPInt => 5,
+            SysmonChannel::VccPAux => 6,
+            SysmonChannel::VccODdr => 7,
+            SysmonChannel::Temperature => 8,
+            SysmonChannel::VccInt => 9,
+            SysmonChannel::VccAux => 10,
+            SysmonChannel::VpVn => 11,
+            SysmonChannel::VrefP => 12,
+            SysmonChannel::VrefN => 13,
+            SysmonChannel::VccBram => 14,
+            SysmonChannel::Aux(n) if *n < 16 => 16 + u32::from(*n),
+            SysmonChannel::Aux(_) => return Err(Error::OutOfRange),
+        })
+    }
+
+    /// Returns the sequencer mask of the channels.
+    fn mask(channels: &[SysmonChannel]) -> Result<u32, Error> {
+        channels
+            .iter()
+            .try_fold(0, |mask, channel| Ok(mask | 1 << channel.sequence_bit()?))
+    }
+}
+
+/// Sample averaging
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Averaging {
+    /// No averaging
+    None,
+    /// Average 16 samples
+    Samples16,
+    /// Average 64 samples
+    Samples64,
+    /// Average 256 samples
+    Samples256,
+}
+
+/// Channel sequencer mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequencerMode {
+    /// Default mode, monitors the on-chip sensors only
+    Default,
+    /// Converts the selected channels once
+    SinglePass,
+    /// Converts the selected channels continuously
+    Continuous,
+}
+
+impl SequencerMode {
+    fn bits(&self) -> u32 {
+        match self {
+            SequencerMode::Default => 0,
+            SequencerMode::SinglePass => 1,
+            SequencerMode::Continuous => 2,
+        }
+    }
+}
+
+/// Alarm with upper and lower thresholds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysmonAlarm {
+    /// Over temperature, the lower threshold resets the alarm
+    OverTemperature,
+    /// Temperature
+    Temperature,
+    /// Internal supply
+    VccInt,
+    /// Auxiliary supply
+    VccAux,
+    /// Block RAM supply
+    VccBram,
+}
+
+impl SysmonAlarm {
+    /// Returns the upper and lower threshold registers.
+    fn thresholds(&self) -> (Register<u32, RW>, Register<u32, RW>) {
+        let (upper, lower) = match self {
+            SysmonAlarm::Temperature => (0, 4),
+            SysmonAlarm::VccInt => (1, 5),
+            SysmonAlarm::VccAux => (2, 6),
+            SysmonAlarm::OverTemperature => (3, 7),
+            SysmonAlarm::VccBram => (8, 12),
+        };
+        (
+            Registers::THRESHOLD.shifted(upper * 4),
+            Registers::THRESHOLD.shifted(lower * 4),
+        )
+    }
+
+    /// Returns the channel compared against the thresholds.
+    fn channel(&self) -> SysmonChannel {
+        match self {
+            SysmonAlarm::OverTemperature | SysmonAlarm::Temperature => SysmonChannel::Temperature,
+            SysmonAlarm::VccInt => SysmonChannel::VccInt,
+            SysmonAlarm::VccAux => SysmonChannel::VccAux,
+            SysmonAlarm::VccBram => SysmonChannel::VccBram,
+        }
+    }
+
+    /// Returns the alarm disable bit of configuration register 1.
+    fn disable_bit(&self) -> Config1 {
+        match self {
+            SysmonAlarm::OverTemperature => Config1::DISABLE_OVER_TEMPERATURE,
+            SysmonAlarm::Temperature => Config1::DISABLE_TEMPERATURE,
+            SysmonAlarm::VccInt => Config1::DISABLE_VCC_INT,
+            SysmonAlarm::VccAux => Config1::DISABLE_VCC_AUX,
+            SysmonAlarm::VccBram => Config1::DISABLE_VCC_BRAM,
+        }
+    }
+
+    /// Returns the interrupt of the alarm.
+    fn interrupt(&self) -> SysmonInterrupt {
+        match self {
+            SysmonAlarm::OverTemperature => SysmonInterrupt::OVER_TEMPERATURE,
+            SysmonAlarm::Temperature => SysmonInterrupt::TEMPERATURE,
+            SysmonAlarm::VccInt => SysmonInterrupt::VCC_INT,
+            SysmonAlarm::VccAux => SysmonInterrupt::VCC_AUX,
+            SysmonAlarm::VccBram => SysmonInterrupt::VCC_BRAM,
+        }
+    }
+}
+
+/// AXI XADC (PG019) and System Management Wizard (PG185)
+///
+/// Reads the on-chip sensors and auxiliary analog inputs converted to degrees Celsius and
+/// volts, configures the channel sequencer, averaging and alarm thresholds. Alarms are reported
+/// through the UIO interrupt.
+pub struct AxiSysmon {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    family: SysmonFamily,
+    bipolar: u32,
+}
+
+impl AxiSysmon {
+    /// Creates a new `AxiSysmon` instance from a UIO device of the converter family.
+    ///
+    /// The System Management Wizard has the same `compatible` string on UltraScale and
+    /// UltraScale+, so the family is not taken from the device tree.
+    pub fn try_from(device: &DeviceInfo, family: SysmonFamily) -> Result<AxiSysmon, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut sysmon = Self::from_mmio(registers, Some(interrupt), family);
+        sysmon.read_bipolar()?;
+        Ok(sysmon)
+    }
+
+    /// Creates a new `AxiSysmon` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt alarms are polled.
+    pub fn from_mmio(
+        registers: Mmio,
+        interrupt: Option<Interrupt>,
+        family: SysmonFamily,
+    ) -> AxiSysmon {
+        AxiSysmon {
+            registers,
+            interrupt,
+            family,
+            bipolar: 0,
+        }
+    }
+
+    /// Sets the converter family, selects the temperature transfer function.
+    pub fn set_family(&mut self, family: SysmonFamily) {
+        self.family = family;
+    }
+
+    /// Returns the converter family.
+    pub fn family(&self) -> SysmonFamily {
+        self.family
+    }
+
+    /// Resets the core, the configuration returns to the hardware defaults.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::SRR, RESET_KEY)?;
+        self.read_bipolar()
+    }
+
+    fn read_bipolar(&mut self) -> Result<(), Error> {
+        let bipolar = self.registers.read(Registers::BIPOLAR)? & 0xffff;
+        let bipolar_aux = self.registers.read(Registers::BIPOLAR_AUX)? & 0xffff;
+        self.bipolar = bipolar | bipolar_aux << 16;
+        Ok(())
+    }
+
+    /// Returns the last conversion result of the channel, the 16-bit code.
+    pub fn raw(&self, channel: SysmonChannel) -> Result<u16, Error> {
+        let register = Registers::STATUS.shifted(channel.number()? * 4);
+        Ok(self.registers.read(register)? as u16)
+    }
+
+    /// Converts a code of the channel to degrees Celsius or volts.
+    pub fn convert(&self, channel: SysmonChannel, code: u16) -> f64 {
+        let full_scale = f64::from(1u32 << 16);
+        match channel {
+            SysmonChannel::Temperature => {
+                let (gain, offset) = self.family.temperature_transfer();
+                f64::from(code) * gain / full_scale - offset
+            }
+            SysmonChannel::VpVn | SysmonChannel::Aux(_) if self.is_bipolar(channel) => {
+                f64::from(code as i16) / full_scale
+            }
+            SysmonChannel::VpVn | SysmonChannel::Aux(_) => f64::from(code) / full_scale,
+            _ => f64::from(code) * SUPPLY_FULL_SCALE / full_scale,
+        }
+    }
+
+    /// Converts degrees Celsius or volts of the channel to a code.
+    fn code(&self, channel: SysmonChannel, value: f64) -> u16 {
+        let full_scale = f64::from(1u32 << 16);
+        let code = match channel {
+            SysmonChannel::Temperature => {
+                let (gain, offset) = self.family.temperature_transfer();
+                (value + offset) * full_scale / gain
+            }
+            _ => value * full_scale / SUPPLY_FULL_SCALE,
+        };
+        code.round().clamp(0.0, f64::from(u16::MAX)) as u16
+    }
+
+    fn is_bipolar(&self, channel: SysmonChannel) -> bool {
+        channel
+            .sequence_bit()
+            .is_ok_and(|bit| self.bipolar & (1 << bit) != 0)
+    }
+
+    /// Returns the last conversion of the channel in degrees Celsius or volts.
+    pub fn read(&self, channel: SysmonChannel) -> Result<f64, Error> {
+        Ok(self.convert(channel, self.raw(channel)?))
+    }
+
+    /// Returns the die temperature in degrees Celsius.
+    pub fn temperature(&self) -> Result<f64, Error> {
+        self.read(SysmonChannel::Temperature)
+    }
+
+    /// Returns the internal supply in volts.
+    pub fn vcc_int(&self) -> Result<f64, Error> {
+        self.read(SysmonChannel::VccInt)
+    }
+
+    /// Returns the auxiliary supply in volts.
+    pub fn vcc_aux(&self) -> Result<f64, Error> {
+        self.read(SysmonChannel::VccAux)
+    }
+
+    /// Returns the block RAM supply in volts.
+    pub fn vcc_bram(&self) -> Result<f64, Error> {
+        self.read(SysmonChannel::VccBram)
+    }
+
+    /// Returns the auxiliary analog input in volts.
+    pub fn aux(&self, input: u8) -> Result<f64, Error> {
+        self.read(SysmonChannel::Aux(input))
+    }
+
+    fn extreme(&self, channel: SysmonChannel, minimum: bool) -> Result<f64, Error> {
+        let index = match channel {
+            SysmonChannel::Temperature => 0,
+            SysmonChannel::VccInt => 1,
+            SysmonChannel::VccAux => 2,
+            SysmonChannel::VccBram => 3,
+            _ => return Err(Error::Unsupported),
+        };
+        let index = if minimum { index + 4 } else { index };
+        let code = self.registers.read(Registers::MAX_MIN.shifted(index * 4))? as u16;
+        Ok(self.convert(channel, code))
+    }
+
+    /// Returns the maximum of the temperature or a supply since the last reset.
+    pub fn maximum(&self, channel: SysmonChannel) -> Result<f64, Error> {
+        self.extreme(channel, false)
+    }
+
+    /// Returns the minimum of the temperature or a supply since the last reset.
+    pub fn minimum(&self, channel: SysmonChannel) -> Result<f64, Error> {
+        self.extreme(channel, true)
+    }
+
+    /// Sets the channels converted by the sequencer and starts the sequencer in `mode`.
+    pub fn set_sequence(
+        &mut self,
+        channels: &[SysmonChannel],
+        mode: SequencerMode,
+    ) -> Result<(), Error> {
+        let mask = SysmonChannel::mask(channels)?;
+        // The sequencer has to be in default mode while the channel selection changes
+        let mut config = self.registers.read(Registers::CONFIG1)?;
+        config.set_sequencer(SequencerMode::Default.bits());
+        self.registers.write(Registers::CONFIG1, config)?;
+        self.registers
+            .write(Registers::SEQ_CHANNEL, mask & 0xffff)?;
+        self.registers
+            .write(Registers::SEQ_CHANNEL_AUX, mask >> 16)?;
+        config.set_sequencer(mode.bits());
+        self.registers.write(Registers::CONFIG1, config)
+    }
+
+    /// Sets the number of samples averaged and the sequencer channels averaged.
+    pub fn set_averaging(
+        &mut self,
+        averaging: Averaging,
+        channels: &[SysmonChannel],
+    ) -> Result<(), Error> {
+        let mask = SysmonChannel::mask(channels)?;
+        self.registers
+            .write(Registers::SEQ_AVERAGE, mask & 0xffff)?;
+        self.registers
+            .write(Registers::SEQ_AVERAGE_AUX, mask >> 16)?;
+        let mut config = self.registers.read(Registers::CONFIG0)?;
+        config.set_averaging(match averaging {
+            Averaging::None => 0,
+            Averaging::Samples16 => 1,
+            Averaging::Samples64 => 2,
+            Averaging::Samples256 => 3,
+        });
+        self.registers.write(Registers::CONFIG0, config)
+    }
+
+    /// Selects the analog inputs converted in bipolar mode.
+    pub fn set_bipolar(&mut self, channels: &[SysmonChannel]) -> Result<(), Error> {
+        let mask = SysmonChannel::mask(channels)?;
+        self.registers.write(Registers::BIPOLAR, mask & 0xffff)?;
+        self.registers.write(Registers::BIPOLAR_AUX, mask >> 16)?;
+        self.bipolar = mask;
+        Ok(())
+    }
+
+    /// Sets the upper and lower thresholds of the alarm in degrees Celsius or volts.
+    pub fn set_alarm_thresholds(
+        &mut self,
+        alarm: SysmonAlarm,
+        upper: f64,
+        lower: f64,
+    ) -> Result<(), Error> {
+        if lower > upper {
+            return Err(Error::InvalidArgument);
+        }
+        let channel = alarm.channel();
+        let mut upper = u32::from(self.code(channel, upper));
+        if alarm == SysmonAlarm::OverTemperature {
+            // The low bits enable the user over temperature threshold
+            upper = (upper & !0xf) | 0x3;
+        }
+        let (upper_register, lower_register) = alarm.thresholds();
+        self.registers.write(upper_register, upper)?;
+        self.registers
+            .write(lower_register, u32::from(self.code(channel, lower)))
+    }
+
+    /// Returns the upper and lower thresholds of the alarm in degrees Celsius or volts.
+    pub fn alarm_thresholds(&self, alarm: SysmonAlarm) -> Result<(f64, f64), Error> {
+        let channel = alarm.channel();
+        let (upper, lower) = alarm.thresholds();
+        let upper = self.registers.read(upper)? as u16;
+        let lower = self.registers.read(lower)? as u16;
+        Ok((self.convert(channel, upper), self.convert(channel, lower)))
+    }
+
+    /// Enables the alarms and their interrupts, other alarms are disabled.
+    pub fn enable_alarms(&mut self, alarms: &[SysmonAlarm]) -> Result<(), Error> {
+        let mut config = self.registers.read(Registers::CONFIG1)?;
+        let mut enable = SysmonInterrupt::empty();
+        for alarm in [
+            SysmonAlarm::OverTemperature,
+            SysmonAlarm::Temperature,
+            SysmonAlarm::VccInt,
+            SysmonAlarm::VccAux,
+            SysmonAlarm::VccBram,
+        ] {
+            if alarms.contains(&alarm) {
+                config &= !alarm.disable_bit();
+                enable |= alarm.interrupt();
+            } else {
+                config |= alarm.disable_bit();
+            }
+        }
+        self.registers.write(Registers::CONFIG1, config)?;
+        self.registers.acknowledge(Registers::IPISR)?;
+        self.registers.write(Registers::IPIER, enable)?;
+        self.registers
+            .write(Registers::GIER, GlobalInterrupt::ENABLE)
+    }
+
+    /// Returns the active alarm outputs.
+    pub fn alarms(&self) -> Result<SysmonInterrupt, Error> {
+        let outputs = self.registers.read(Registers::ALARM)?;
+        // The alarm outputs match the interrupt bits, except for the block RAM supply
+        let mut alarms = SysmonInterrupt(outputs & 0xf);
+        alarms.set_vcc_bram(outputs & (1 << 4) != 0);
+        Ok(alarms)
+    }
+
+    /// Returns and acknowledges the pending interrupts.
+    pub fn interrupt_status(&mut self) -> Result<SysmonInterrupt, Error> {
+        self.registers.acknowledge(Registers::IPISR)
+    }
+
+    /// Waits until an enabled alarm is raised, returns the raised alarms.
+    pub fn wait_alarm(&mut self, timeout: Option<Duration>) -> Result<SysmonInterrupt, Error> {
+        let registers = &mut self.registers;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            let pending = registers.read(Registers::IPISR)? & registers.read(Registers::IPIER)?;
+            if pending == SysmonInterrupt::empty() {
+                return Ok(None);
+            }
+            registers.acknowledge_bits(Registers::IPISR, pending)?;
+            Ok(Some(pending))
+        })
+    }
+}
+
+register_map! {
+    /// System monitor registers
+    struct Registers {
+        /// Software reset
+        SRR: u32, WO @ 0x000;
+        /// Alarm output status
+        ALARM: u32, RO @ 0x008;
+        /// Global interrupt enable
+        GIER: GlobalInterrupt, RW @ 0x05c;
+        /// Interrupt status, toggle on write
+        IPISR: SysmonInterrupt, TOW @ 0x060;
+        /// Interrupt enable
+        IPIER: SysmonInterrupt, RW @ 0x068;
+        /// First channel status register, indexed by channel number
+        STATUS: u32, RO @ 0x200;
+        /// First maximum register, followed by the minimums
+        MAX_MIN: u32, RO @ 0x280;
+        /// Configuration register 0
+        CONFIG0: Config0, RW @ 0x300;
+        /// Configuration register 1
+        CONFIG1: Config1, RW @ 0x304;
+        /// Sequencer channel selection
+        SEQ_CHANNEL: u32, RW @ 0x320;
+        /// Sequencer auxiliary channel selection
+        SEQ_CHANNEL_AUX: u32, RW @ 0x324;
+        /// Sequencer average enable
+        SEQ_AVERAGE: u32, RW @ 0x328;
+        /// Sequencer auxiliary average enable
+        SEQ_AVERAGE_AUX: u32, RW @ 0x32c;
+        /// Sequencer bipolar selection
+        BIPOLAR: u32, RW @ 0x330;
+        /// Sequencer auxiliary bipolar selection
+        BIPOLAR_AUX: u32, RW @ 0x334;
+        /// First alarm threshold register
+        THRESHOLD: u32, RW @ 0x340;
+    }
+}
+
+register_bits! {
+    /// Global interrupt enable register
+    struct GlobalInterrupt(u32) {
+        /// Global interrupt enable
+        ENABLE: enable, set_enable: 31;
+    }
+}
+
+register_bits! {
+    /// Configuration register 0
+    struct Config0(u32) {
+        /// Averaging, 0 none, 1 16 samples, 2 64 samples, 3 256 samples
+        AVERAGING: averaging, set_averaging: 13, 12;
+    }
+}
+
+register_bits! {
+    /// Configuration register 1
+    struct Config1(u32) {
+        /// Disable the over temperature alarm
+        DISABLE_OVER_TEMPERATURE: disable_over_temperature, set_disable_over_temperature: 0;
+        /// Disable the temperature alarm
+        DISABLE_TEMPERATURE: disable_temperature, set_disable_temperature: 1;
+        /// Disable the internal supply alarm
+        DISABLE_VCC_INT: disable_vcc_int, set_disable_vcc_int: 2;
+        /// Disable the auxiliary supply alarm
+        DISABLE_VCC_AUX: disable_vcc_aux, set_disable_vcc_aux: 3;
+        /// Disable the block RAM supply alarm
+        DISABLE_VCC_BRAM: disable_vcc_bram, set_disable_vcc_bram: 8;
+        /// Sequencer mode
+        SEQUENCER: sequencer, set_sequencer: 15, 12;
+    }
+}
+
+register_bits! {
+    /// System monitor alarms and interrupts
+    pub struct SysmonInterrupt(u32) {
+        /// Over temperature
+        OVER_TEMPERATURE: over_temperature, set_over_temperature: 0;
+        /// Temperature out of range
+        TEMPERATURE: temperature, set_temperature: 1;
+        /// Internal supply out of range
+        VCC_INT: vcc_int, set_vcc_int: 2;
+        /// Auxiliary supply out of range
+        VCC_AUX: vcc_aux, set_vcc_aux: 3;
+        /// End of sequence
+        END_OF_SEQUENCE: end_of_sequence, set_end_of_sequence: 4;
+        /// End of conversion
+        END_OF_CONVERSION: end_of_conversion, set_end_of_conversion: 5;
+        /// Over temperature deactivated
+        OVER_TEMPERATURE_DEACTIVE: over_temperature_deactive, set_over_temperature_deactive: 8;
+        /// Temperature alarm deactivated
+        TEMPERATURE_DEACTIVE: temperature_deactive, set_temperature_deactive: 9;
+        /// Block RAM supply out of range
+        VCC_BRAM: vcc_bram, set_vcc_bram: 10;
+    }
+}
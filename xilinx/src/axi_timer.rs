@@ -0,0 +1,410 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::register::{RW, Register};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Timer of an AXI Timer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerChannel {
+    /// Timer 0, the low half in cascade mode
+    Timer0,
+    /// Timer 1, the high half in cascade mode
+    Timer1,
+}
+
+impl TimerChannel {
+    fn index(&self) -> usize {
+        match self {
+            TimerChannel::Timer0 => 0,
+            TimerChannel::Timer1 => 1,
+        }
+    }
+
+    fn registers(&self) -> &'static TimerRegisters {
+        match self {
+            TimerChannel::Timer0 => &TIMER0,
+            TimerChannel::Timer1 => &TIMER1,
+        }
+    }
+}
+
+/// AXI Timer (PG079)
+///
+/// Two timers that generate periodic or single interrupts, capture the counter on an external
+/// trigger, combine into a 64-bit timer in cascade mode or together produce a PWM output.
+/// Times are converted to counter ticks with the AXI clock frequency.
+pub struct AxiTimer {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    clock_frequency: u64,
+    count_width: u32,
+    timers: usize,
+}
+
+impl AxiTimer {
+    /// Creates a new `AxiTimer` instance from a UIO device.
+    ///
+    /// Without `clock_frequency` the AXI clock frequency is taken from the `clock-frequency`
+    /// device tree property. The counter width and single timer configuration are taken from
+    /// `xlnx,count-width` and `xlnx,one-timer-only` when available.
+    pub fn try_from(device: &DeviceInfo, clock_frequency: Option<u64>) -> Result<AxiTimer, Error> {
+        let node = device.of_node();
+        let clock_frequency = match (clock_frequency, node) {
+            (Some(frequency), _) => frequency,
+            (None, Some(node)) => u64::from(devicetree::read_u32(node, "clock-frequency")?),
+            (None, None) => return Err(Error::InvalidArgument),
+        };
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut timer = Self::from_mmio(registers, Some(interrupt), clock_frequency)?;
+        if let Some(node) = node {
+            if let Ok(width) = devicetree::read_u32(node, "xlnx,count-width") {
+                timer.set_count_width(width)?;
+            }
+            if let Ok(one) = devicetree::read_u32(node, "xlnx,one-timer-only") {
+                timer.timers = if one != 0 { 1 } else { 2 };
+            }
+        }
+        Ok(timer)
+    }
+
+    /// Creates a new `AxiTimer` instance from a memory mapped register region and the AXI clock
+    /// frequency in Hz.
+    ///
+    /// Without an interrupt expiry is polled.
+    pub fn from_mmio(
+        registers: Mmio,
+        interrupt: Option<Interrupt>,
+        clock_frequency: u64,
+    ) -> Result<AxiTimer, Error> {
+        if clock_frequency == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(AxiTimer {
+            registers,
+            interrupt,
+            clock_frequency,
+            count_width: 32,
+            timers: 2,
+        })
+    }
+
+    /// Sets the counter width, `C_COUNT_WIDTH`, 8 to 32 bits.
+    pub fn set_count_width(&mut self, bits: u32) -> Result<(), Error> {
+        if !(8..=32).contains(&bits) {
+            return Err(Error::InvalidArgument);
+        }
+        self.count_width = bits;
+        Ok(())
+    }
+
+    /// Returns the AXI clock frequency in Hz.
+    pub fn clock_frequency(&self) -> u64 {
+        self.clock_frequency
+    }
+
+    /// Converts a time to AXI clock ticks, rounded to the nearest tick.
+    pub fn ticks(&self, duration: Duration) -> u64 {
+        let ticks =
+            (duration.as_nanos() * u128::from(self.clock_frequency) + 500_000_000) / 1_000_000_000;
+        ticks.min(u128::from(u64::MAX)) as u64
+    }
+
+    /// Converts AXI clock ticks to a time.
+    pub fn duration(&self, ticks: u64) -> Duration {
+        let nanos = u128::from(ticks) * 1_000_000_000 / u128::from(self.clock_frequency);
+        Duration::from_nanos(nanos.min(u128::from(u64::MAX)) as u64)
+    }
+
+    fn check_timer(&self, timer: TimerChannel) -> Result<(), Error> {
+        if timer.index() >= self.timers {
+            return Err(Error::Unsupported);
+        }
+        Ok(())
+    }
+
+    fn max_count(&self) -> u64 {
+        (1u64 << self.count_width) - 1
+    }
+
+    /// Returns the load value of a down counting generate timer with the period, the timer
+    /// period is the load value plus two clock cycles.
+    fn load_value(&self, period: Duration, max: u64) -> Result<u64, Error> {
+        let ticks = self.ticks(period);
+        if ticks < 2 || ticks - 2 > max {
+            return Err(Error::OutOfRange);
+        }
+        Ok(ticks - 2)
+    }
+
+    /// Writes the control register without acknowledging a pending interrupt.
+    fn write_control(&mut self, timer: TimerChannel, control: TimerControl) -> Result<(), Error> {
+        let mut control = control;
+        control.set_interrupt(false);
+        self.registers.write(timer.registers().tcsr, control)
+    }
+
+    /// Loads the counter from the load register and starts the timer with `control`.
+    fn load_and_start(&mut self, timer: TimerChannel, control: TimerControl) -> Result<(), Error> {
+        self.write_control(timer, control | TimerControl::LOAD)?;
+        // Acknowledge an interrupt left from earlier use
+        self.registers
+            .write(timer.registers().tcsr, control | TimerControl::INTERRUPT)?;
+        self.write_control(timer, control | TimerControl::ENABLE)
+    }
+
+    /// Starts a generate timer with the period, interrupting on expiry.
+    ///
+    /// With `auto_reload` the timer restarts on expiry, otherwise it expires once.
+    pub fn start_generate(
+        &mut self,
+        timer: TimerChannel,
+        period: Duration,
+        auto_reload: bool,
+    ) -> Result<(), Error> {
+        self.check_timer(timer)?;
+        let load = self.load_value(period, self.max_count())?;
+        self.stop(timer)?;
+        self.registers.write(timer.registers().tlr, load as u32)?;
+        let mut control = TimerControl::DOWN_COUNT | TimerControl::INTERRUPT_ENABLE;
+        control.set_auto_reload(auto_reload);
+        log::debug!("{:?} generate {:?}, {} ticks", timer, period, load + 2);
+        self.load_and_start(timer, control)
+    }
+
+    /// Starts capturing the counter on the external capture trigger, interrupting on capture.
+    ///
+    /// With `overwrite` every trigger captures the counter, otherwise the first capture is held
+    /// until the interrupt is acknowledged.
+    pub fn start_capture(&mut self, timer: TimerChannel, overwrite: bool) -> Result<(), Error> {
+        self.check_timer(timer)?;
+        self.stop(timer)?;
+        self.registers.write(timer.registers().tlr, 0)?;
+        let mut control = TimerControl::CAPTURE_MODE
+            | TimerControl::CAPTURE_TRIGGER_ENABLE
+            | TimerControl::INTERRUPT_ENABLE;
+        control.set_auto_reload(overwrite);
+        self.load_and_start(timer, control)
+    }
+
+    /// Returns the last captured counter value.
+    pub fn captured(&self, timer: TimerChannel) -> Result<u32, Error> {
+        self.registers.read(timer.registers().tlr)
+    }
+
+    /// Starts both timers as one 64-bit generate timer, interrupting on expiry.
+    pub fn start_cascade(&mut self, period: Duration, auto_reload: bool) -> Result<(), Error> {
+        self.check_timer(TimerChannel::Timer1)?;
+        let max = if self.count_width == 32 {
+            u64::MAX
+        } else {
+            (1u64 << (2 * self.count_width)) - 1
+        };
+        let load = self.load_value(period, max)?;
+        self.stop_all()?;
+        self.registers.write(Registers::TLR0, load as u32)?;
+        self.registers
+            .write(Registers::TLR1, (load >> self.count_width) as u32)?;
+        let mut control =
+            TimerControl::DOWN_COUNT | TimerControl::INTERRUPT_ENABLE | TimerControl::CASCADE;
+        control.set_auto_reload(auto_reload);
+        log::debug!("cascade generate {:?}, {} ticks", period, load + 2);
+        self.load_and_start(TimerChannel::Timer0, control)
+    }
+
+    /// Starts both timers as one 64-bit capture timer on the external capture trigger of
+    /// timer 0.
+    pub fn start_cascade_capture(&mut self, overwrite: bool) -> Result<(), Error> {
+        self.check_timer(TimerChannel::Timer1)?;
+        self.stop_all()?;
+        self.registers.write(Registers::TLR0, 0)?;
+        self.registers.write(Registers::TLR1, 0)?;
+        let mut control = TimerControl::CAPTURE_MODE
+            | TimerControl::CAPTURE_TRIGGER_ENABLE
+            | TimerControl::INTERRUPT_ENABLE
+            | TimerControl::CASCADE;
+        control.set_auto_reload(overwrite);
+        self.load_and_start(TimerChannel::Timer0, control)
+    }
+
+    /// Returns the last captured value of the 64-bit cascade timer.
+    pub fn cascade_captured(&self) -> Result<u64, Error> {
+        let low = self.registers.read(Registers::TLR0)?;
+        let high = self.registers.read(Registers::TLR1)?;
+        Ok((u64::from(high) << self.count_width) | u64::from(low))
+    }
+
+    /// Starts the PWM output with the period and high time, using both timers.
+    ///
+    /// Timer 0 sets the period and timer 1 the high time. The generate outputs of both timers
+    /// have to be enabled in the hardware configuration.
+    pub fn start_pwm(&mut self, period: Duration, high: Duration) -> Result<(), Error> {
+        self.check_timer(TimerChannel::Timer1)?;
+        let max = self.max_count();
+        let period_load = self.load_value(period, max)?;
+        let high_load = self.load_value(high, max)?;
+        if high_load >= period_load {
+            return Err(Error::InvalidArgument);
+        }
+        self.stop_all()?;
+        self.registers.write(Registers::TLR0, period_load as u32)?;
+        self.registers.write(Registers::TLR1, high_load as u32)?;
+        let control = TimerControl::DOWN_COUNT
+            | TimerControl::GENERATE_ENABLE
+            | TimerControl::AUTO_RELOAD
+            | TimerControl::PWM_ENABLE;
+        for timer in [TimerChannel::Timer0, TimerChannel::Timer1] {
+            self.write_control(timer, control | TimerControl::LOAD)?;
+            self.write_control(timer, control)?;
+        }
+        log::debug!("PWM period {:?}, high {:?}", period, high);
+        // Enable all starts both timers at the same time
+        self.write_control(TimerChannel::Timer0, control | TimerControl::ENABLE_ALL)
+    }
+
+    /// Starts the PWM output with the period and duty cycle, between 0.0 and 1.0 exclusive.
+    ///
+    /// Fails with [`Error::InvalidArgument`] for a duty cycle of 0.0 or 1.0, the timers cannot
+    /// generate a constant output, and with [`Error::OutOfRange`] when the high time is too
+    /// short for the timer.
+    pub fn start_pwm_duty(&mut self, period: Duration, duty: f64) -> Result<(), Error> {
+        if !(duty > 0.0 && duty < 1.0) {
+            return Err(Error::InvalidArgument);
+        }
+        self.start_pwm(period, period.mul_f64(duty))
+    }
+
+    /// Stops the timer.
+    pub fn stop(&mut self, timer: TimerChannel) -> Result<(), Error> {
+        self.check_timer(timer)?;
+        let mut control = self.registers.read(timer.registers().tcsr)?;
+        control.set_enable(false);
+        control.set_enable_all(false);
+        self.write_control(timer, control)
+    }
+
+    /// Stops both timers, and leaves cascade mode.
+    pub fn stop_all(&mut self) -> Result<(), Error> {
+        let timers = [TimerChannel::Timer0, TimerChannel::Timer1];
+        for timer in &timers[..self.timers] {
+            self.write_control(*timer, TimerControl::empty())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the counter value.
+    pub fn counter(&self, timer: TimerChannel) -> Result<u32, Error> {
+        self.check_timer(timer)?;
+        self.registers.read(timer.registers().tcr)
+    }
+
+    /// Returns the counter value of the 64-bit cascade timer.
+    pub fn cascade_counter(&self) -> Result<u64, Error> {
+        // Read the high half again if the low half wrapped in between
+        loop {
+            let high = self.registers.read(Registers::TCR1)?;
+            let low = self.registers.read(Registers::TCR0)?;
+            if self.registers.read(Registers::TCR1)? == high {
+                return Ok((u64::from(high) << self.count_width) | u64::from(low));
+            }
+        }
+    }
+
+    /// Checks if the timer has expired or captured, and acknowledges the interrupt.
+    pub fn is_expired(&mut self, timer: TimerChannel) -> Result<bool, Error> {
+        self.check_timer(timer)?;
+        let tcsr = timer.registers().tcsr;
+        let control = self.registers.read(tcsr)?;
+        if control.interrupt() {
+            self.registers.write(tcsr, control)?;
+        }
+        Ok(control.interrupt())
+    }
+
+    /// Waits until the timer expires or captures. Cascade timers signal on timer 0.
+    pub fn wait(&mut self, timer: TimerChannel, timeout: Option<Duration>) -> Result<(), Error> {
+        self.check_timer(timer)?;
+        let tcsr = timer.registers().tcsr;
+        let registers = &mut self.registers;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            let control = registers.read(tcsr)?;
+            if control.interrupt() {
+                // Writing back the set interrupt bit acknowledges it
+                registers.write(tcsr, control)?;
+                return Ok(Some(()));
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Register set of one timer
+struct TimerRegisters {
+    tcsr: Register<TimerControl, RW>,
+    tlr: Register<u32, RW>,
+    tcr: Register<u32, RW>,
+}
+
+const TIMER0: TimerRegisters = TimerRegisters {
+    tcsr: Registers::TCSR0,
+    tlr: Registers::TLR0,
+    tcr: Registers::TCR0,
+};
+
+const TIMER1: TimerRegisters = TimerRegisters {
+    tcsr: Registers::TCSR1,
+    tlr: Registers::TLR1,
+    tcr: Registers::TCR1,
+};
+
+register_map! {
+    /// AXI Timer registers
+    struct Registers {
+        /// Timer 0 control and status, the interrupt bit is cleared by writing one
+        TCSR0: TimerControl, RW @ 0x00;
+        /// Timer 0 load register, holds the captured value in capture mode
+        TLR0: u32, RW @ 0x04;
+        /// Timer 0 counter
+        TCR0: u32, RW @ 0x08;
+        /// Timer 1 control and status, the interrupt bit is cleared by writing one
+        TCSR1: TimerControl, RW @ 0x10;
+        /// Timer 1 load register, holds the captured value in capture mode
+        TLR1: u32, RW @ 0x14;
+        /// Timer 1 counter
+        TCR1: u32, RW @ 0x18;
+    }
+}
+
+register_bits! {
+    /// Timer control and status register
+    struct TimerControl(u32) {
+        /// Capture mode, generate mode when clear
+        CAPTURE_MODE: capture_mode, set_capture_mode: 0;
+        /// Count down, count up when clear
+        DOWN_COUNT: down_count, set_down_count: 1;
+        /// Enable the external generate signal
+        GENERATE_ENABLE: generate_enable, set_generate_enable: 2;
+        /// Enable the external capture trigger
+        CAPTURE_TRIGGER_ENABLE: capture_trigger_enable, set_capture_trigger_enable: 3;
+        /// Auto reload in generate mode, overwrite in capture mode
+        AUTO_RELOAD: auto_reload, set_auto_reload: 4;
+        /// Load the counter from the load register
+        LOAD: load, set_load: 5;
+        /// Interrupt enable
+        INTERRUPT_ENABLE: interrupt_enable, set_interrupt_enable: 6;
+        /// Enable the timer
+        ENABLE: enable, set_enable: 7;
+        /// Interrupt, the timer expired or captured, write one to clear
+        INTERRUPT: interrupt, set_interrupt: 8;
+        /// PWM enable
+        PWM_ENABLE: pwm_enable, set_pwm_enable: 9;
+        /// Enable both timers, timer 0 only
+        ENABLE_ALL: enable_all, set_enable_all: 10;
+        /// Cascade both timers to 64 bits, timer 0 only
+        CASCADE: cascade, set_cascade: 11;
+    }
+}
@@ -0,0 +1,286 @@
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Depth of the receive and transmit FIFOs
+pub const UART_LITE_FIFO_DEPTH: usize = 16;
+
+/// Registers and the error reported by the last status read, the status register clears the
+/// error bits when read
+struct Port {
+    registers: Mmio,
+    error: Option<Error>,
+}
+
+impl Port {
+    fn status(&mut self) -> Result<UartStatus, Error> {
+        let status = self.registers.read(Registers::STAT_REG)?;
+        if let Some(error) = status.error() {
+            log::warn!("receive error, {:?}", error);
+            self.error.get_or_insert(error);
+        }
+        Ok(status)
+    }
+
+    fn take_error(&mut self) -> Result<(), Error> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn receive(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.take_error()?;
+        let mut count = 0;
+        while count < data.len() && self.status()?.rx_valid() {
+            data[count] = self.registers.read(Registers::RX_FIFO)? as u8;
+            count += 1;
+        }
+        // An error is reported with the next read when data was received
+        if count == 0 {
+            self.take_error()?;
+        }
+        Ok(count)
+    }
+
+    fn transmit(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut count = 0;
+        while count < data.len() && !self.status()?.tx_full() {
+            self.registers
+                .write(Registers::TX_FIFO, u32::from(data[count]))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn control(&mut self, control: Control) -> Result<(), Error> {
+        // The control register is write only, keep the interrupt enable from the status
+        let mut control = control;
+        control.set_interrupt_enable(self.status()?.interrupt_enabled());
+        self.registers.write(Registers::CTRL_REG, control)
+    }
+}
+
+/// AXI UART Lite (PG142)
+///
+/// Implements [`std::io::Read`] and [`std::io::Write`]. Reads block until at least one byte is
+/// available, sleeping on the UIO interrupt when there is one. The line settings are fixed in
+/// the hardware configuration.
+pub struct AxiUartLite {
+    port: Port,
+    interrupt: Option<Interrupt>,
+    timeout: Option<Duration>,
+}
+
+impl AxiUartLite {
+    /// Creates a new `AxiUartLite` instance from a UIO device.
+    ///
+    /// The FIFOs are reset and the core interrupt is enabled.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiUartLite, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut uart = Self::from_mmio(registers, Some(interrupt));
+        uart.reset_fifos()?;
+        uart.enable_interrupt()?;
+        Ok(uart)
+    }
+
+    /// Creates a new `AxiUartLite` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt the status register is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiUartLite {
+        AxiUartLite {
+            port: Port {
+                registers,
+                error: None,
+            },
+            interrupt,
+            timeout: None,
+        }
+    }
+
+    /// Sets the timeout of blocking reads and writes, `None` blocks indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the timeout of blocking reads and writes.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns the status.
+    ///
+    /// Reading the status clears the error bits, a reported error is also returned by the next
+    /// receive.
+    pub fn status(&mut self) -> Result<UartStatus, Error> {
+        self.port.status()
+    }
+
+    /// Discards the contents of both FIFOs and any pending error.
+    pub fn reset_fifos(&mut self) -> Result<(), Error> {
+        self.port
+            .control(Control::RESET_TX_FIFO | Control::RESET_RX_FIFO)?;
+        self.port.status()?;
+        self.port.error = None;
+        Ok(())
+    }
+
+    /// Discards the contents of the receive FIFO.
+    pub fn reset_rx_fifo(&mut self) -> Result<(), Error> {
+        self.port.control(Control::RESET_RX_FIFO)
+    }
+
+    /// Discards the contents of the transmit FIFO.
+    pub fn reset_tx_fifo(&mut self) -> Result<(), Error> {
+        self.port.control(Control::RESET_TX_FIFO)
+    }
+
+    /// Enables the core interrupt, raised when the receive FIFO gets data or the transmit FIFO
+    /// becomes empty.
+    pub fn enable_interrupt(&mut self) -> Result<(), Error> {
+        self.port
+            .registers
+            .write(Registers::CTRL_REG, Control::INTERRUPT_ENABLE)
+    }
+
+    /// Disables the core interrupt.
+    pub fn disable_interrupt(&mut self) -> Result<(), Error> {
+        self.port
+            .registers
+            .write(Registers::CTRL_REG, Control::empty())
+    }
+
+    /// Reads the received bytes without waiting and returns the number of bytes read.
+    ///
+    /// Overrun, framing and parity errors are returned as [`Error::OverRun`],
+    /// [`Error::Framing`] and [`Error::Parity`].
+    pub fn try_receive(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.port.receive(data)
+    }
+
+    /// Waits until data is received and returns the number of bytes read.
+    pub fn receive(&mut self, data: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let port = &mut self.port;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            let count = port.receive(data)?;
+            Ok((count > 0).then_some(count))
+        })
+    }
+
+    /// Writes as many bytes as fit in the transmit FIFO and returns the number of bytes written.
+    pub fn try_transmit(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.port.transmit(data)
+    }
+
+    /// Waits until all bytes are written to the transmit FIFO.
+    pub fn transmit(&mut self, data: &[u8], timeout: Option<Duration>) -> Result<(), Error> {
+        let port = &mut self.port;
+        let mut written = 0;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            written += port.transmit(&data[written..])?;
+            Ok((written == data.len()).then_some(()))
+        })
+    }
+
+    /// Waits until the transmit FIFO is empty.
+    pub fn drain(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let port = &mut self.port;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            Ok(port.status()?.tx_empty().then_some(()))
+        })
+    }
+}
+
+impl std::io::Read for AxiUartLite {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.receive(buf, self.timeout)?)
+    }
+}
+
+impl std::io::Write for AxiUartLite {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let port = &mut self.port;
+        let count = wait_for(self.interrupt.as_ref(), self.timeout, || {
+            let count = port.transmit(buf)?;
+            Ok((count > 0).then_some(count))
+        })?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.drain(self.timeout)?)
+    }
+}
+
+register_map! {
+    /// AXI UART Lite registers
+    struct Registers {
+        /// Receive FIFO
+        RX_FIFO: u32, RO @ 0x00;
+        /// Transmit FIFO
+        TX_FIFO: u32, WO @ 0x04;
+        /// Status register, reading clears the error bits
+        STAT_REG: UartStatus, RO @ 0x08 = 0x0000_0004;
+        /// Control register
+        CTRL_REG: Control, WO @ 0x0c;
+    }
+}
+
+register_bits! {
+    /// Control register
+    struct Control(u32) {
+        /// Reset the transmit FIFO
+        RESET_TX_FIFO: reset_tx_fifo, set_reset_tx_fifo: 0;
+        /// Reset the receive FIFO
+        RESET_RX_FIFO: reset_rx_fifo, set_reset_rx_fifo: 1;
+        /// Interrupt enable
+        INTERRUPT_ENABLE: interrupt_enable, set_interrupt_enable: 4;
+    }
+}
+
+register_bits! {
+    /// UART Lite status register
+    pub struct UartStatus(u32) {
+        /// The receive FIFO has data
+        RX_VALID: rx_valid, set_rx_valid: 0;
+        /// The receive FIFO is full
+        RX_FULL: rx_full, set_rx_full: 1;
+        /// The transmit FIFO is empty
+        TX_EMPTY: tx_empty, set_tx_empty: 2;
+        /// The transmit FIFO is full
+        TX_FULL: tx_full, set_tx_full: 3;
+        /// The interrupt is enabled
+        INTERRUPT_ENABLED: interrupt_enabled, set_interrupt_enabled: 4;
+        /// Received data was lost, the receive FIFO was full
+        OVERRUN_ERROR: overrun_error, set_overrun_error: 5;
+        /// Received character without a valid stop bit
+        FRAME_ERROR: frame_error, set_frame_error: 6;
+        /// Received character with a parity error
+        PARITY_ERROR: parity_error, set_parity_error: 7;
+    }
+}
+
+impl UartStatus {
+    /// Returns the first error reported by the status, if any.
+    pub fn error(&self) -> Option<Error> {
+        if self.overrun_error() {
+            Some(Error::OverRun)
+        } else if self.frame_error() {
+            Some(Error::Framing)
+        } else if self.parity_error() {
+            Some(Error::Parity)
+        } else {
+            None
+        }
+    }
+}
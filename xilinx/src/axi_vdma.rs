@@ -0,0 +1,603 @@
+use crate::axi_dma::DmaChannel;
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::dma_buffer::{DmaBuffer, DmaDirection};
+use crate::error::Error;
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::register::{RW, Register, W1C};
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Time to wait for a channel reset or halt to complete
+const RESET_TIMEOUT: Duration = Duration::from_millis(100);
+/// Largest number of frame stores
+pub const MAX_FRAME_STORES: usize = 32;
+
+/// Frame geometry of a channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameFormat {
+    /// Bytes per line
+    pub hsize: usize,
+    /// Lines per frame
+    pub vsize: usize,
+    /// Bytes between the start of consecutive lines
+    pub stride: usize,
+}
+
+impl FrameFormat {
+    /// Creates a frame format from line length, number of lines and stride.
+    pub fn new(hsize: usize, vsize: usize, stride: usize) -> Self {
+        FrameFormat {
+            hsize,
+            vsize,
+            stride,
+        }
+    }
+
+    /// Returns the number of bytes of image data in a frame, without line padding.
+    pub fn frame_length(&self) -> usize {
+        self.hsize * self.vsize
+    }
+
+    /// Returns the number of bytes a frame store occupies in memory.
+    pub fn frame_size(&self) -> usize {
+        self.stride * self.vsize
+    }
+}
+
+/// Genlock synchronisation of a channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Genlock {
+    /// Use the internal genlock bus of the other channel instead of the external ports
+    pub internal: bool,
+    /// Frame pointer number of the master to follow, used by slaves in dynamic genlock mode
+    pub master: u8,
+}
+
+/// AXI Video DMA (PG020)
+///
+/// Each channel cycles through a set of frame stores in memory, in circular mode, or stays on
+/// one frame store, in park mode.
+pub struct AxiVdma {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    address_width: u32,
+    frames: [Vec<u64>; 2],
+    formats: [Option<FrameFormat>; 2],
+    completed: [bool; 2],
+}
+
+impl AxiVdma {
+    /// Creates a new `AxiVdma` instance from a UIO device.
+    ///
+    /// The address width is taken from the `xlnx,addrwidth` device tree property when
+    /// available.
+    pub fn try_from(device: &DeviceInfo) -> Result<AxiVdma, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        let mut vdma = Self::from_mmio(registers, Some(interrupt));
+        if let Some(node) = device.of_node()
+            && let Ok(width) = devicetree::read_u32(node, "xlnx,addrwidth")
+        {
+            vdma.address_width = width;
+        }
+        Ok(vdma)
+    }
+
+    /// Creates a new `AxiVdma` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt completion is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> AxiVdma {
+        AxiVdma {
+            registers,
+            interrupt,
+            address_width: 32,
+            frames: [Vec::new(), Vec::new()],
+            formats: [None, None],
+            completed: [false, false],
+        }
+    }
+
+    /// Sets the address width, `C_M_AXI_MM2S_ADDR_WIDTH`, in bits.
+    pub fn set_address_width(&mut self, bits: u32) {
+        self.address_width = bits;
+    }
+
+    /// Resets the channel.
+    pub fn reset(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        let registers = channel_registers(channel);
+        self.registers.write(registers.vdmacr, Control::RESET)?;
+        let vdma = &self.registers;
+        wait_for(None, Some(RESET_TIMEOUT), || {
+            Ok((!vdma.read(registers.vdmacr)?.reset()).then_some(()))
+        })
+    }
+
+    /// Halts the channel and waits until the halt is complete.
+    pub fn halt(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        let registers = channel_registers(channel);
+        self.registers
+            .modify(registers.vdmacr, |control| control.set_run(false))?;
+        let vdma = &self.registers;
+        wait_for(None, Some(RESET_TIMEOUT), || {
+            Ok(vdma.read(registers.vdmasr)?.halted().then_some(()))
+        })
+    }
+
+    /// Returns the channel status.
+    pub fn status(&self, channel: DmaChannel) -> Result<VdmaStatus, Error> {
+        self.registers.read(channel_registers(channel).vdmasr)
+    }
+
+    /// Checks if the channel is halted.
+    pub fn is_halted(&self, channel: DmaChannel) -> Result<bool, Error> {
+        Ok(self.status(channel)?.halted())
+    }
+
+    /// Starts the channel in circular mode over the frame stores at bus addresses `frames`.
+    ///
+    /// The channel has to be halted. The frame store count is set to the number of frames, the
+    /// frame error and frame count interrupts are enabled.
+    pub fn start(
+        &mut self,
+        channel: DmaChannel,
+        format: &FrameFormat,
+        frames: &[u64],
+    ) -> Result<(), Error> {
+        if format.hsize == 0 || format.hsize > 0xffff || format.stride > 0xffff {
+            return Err(Error::InvalidArgument);
+        }
+        if format.vsize == 0 || format.vsize > 0x1fff || format.stride < format.hsize {
+            return Err(Error::InvalidArgument);
+        }
+        if frames.is_empty() || frames.len() > MAX_FRAME_STORES {
+            return Err(Error::InvalidArgument);
+        }
+        if self.address_width <= 32 && frames.iter().any(|a| *a > u64::from(u32::MAX)) {
+            return Err(Error::InvalidArgument);
+        }
+        if !self.is_halted(channel)? {
+            return Err(Error::Busy);
+        }
+        let registers = channel_registers(channel);
+        self.registers
+            .write(registers.frmstore, frames.len() as u32)?;
+        self.registers
+            .write(registers.vdmasr, VdmaStatus::CLEAR_ALL)?;
+        self.registers.modify(registers.vdmacr, |control| {
+            *control |= Control::RUN
+                | Control::CIRCULAR
+                | Control::FRAME_COUNT_IRQ_ENABLE
+                | Control::ERROR_IRQ_ENABLE;
+        })?;
+        self.write_frame_addresses(channel, frames)?;
+        self.registers.modify(registers.frmdly_stride, |value| {
+            value.set_stride(format.stride as u32)
+        })?;
+        self.registers.write(registers.hsize, format.hsize as u32)?;
+        log::debug!(
+            "{:?} start {}x{} stride {}, {} frames",
+            channel,
+            format.hsize,
+            format.vsize,
+            format.stride,
+            frames.len()
+        );
+        // Writing the vertical size starts the channel
+        self.registers.write(registers.vsize, format.vsize as u32)?;
+        self.frames[index(channel)] = frames.to_vec();
+        self.formats[index(channel)] = Some(*format);
+        self.completed[index(channel)] = false;
+        Ok(())
+    }
+
+    fn write_frame_addresses(&mut self, channel: DmaChannel, frames: &[u64]) -> Result<(), Error> {
+        let registers = channel_registers(channel);
+        let wide = self.address_width > 32;
+        // Sixteen address registers are visible at a time, the register index selects the bank
+        let per_bank = if wide { 8 } else { 16 };
+        for (n, address) in frames.iter().enumerate() {
+            if n % per_bank == 0 {
+                self.registers
+                    .write(registers.reg_index, (n / per_bank) as u32)?;
+            }
+            if wide {
+                let offset = (n % per_bank) * 8;
+                self.registers
+                    .write(registers.start_address.shifted(offset), *address as u32)?;
+                self.registers.write(
+                    registers.start_address.shifted(offset + 4),
+                    (address >> 32) as u32,
+                )?;
+            } else {
+                let offset = (n % per_bank) * 4;
+                self.registers
+                    .write(registers.start_address.shifted(offset), *address as u32)?;
+            }
+        }
+        self.registers.write(registers.reg_index, 0)
+    }
+
+    /// Parks the channel on frame store `frame`.
+    ///
+    /// The channel completes the current frame and then repeatedly transfers the parked frame.
+    /// The frame store has to be one of the frames given to [`AxiVdma::start`].
+    pub fn park(&mut self, channel: DmaChannel, frame: usize) -> Result<(), Error> {
+        if frame >= self.frames[index(channel)].len() {
+            return Err(Error::InvalidArgument);
+        }
+        self.registers
+            .modify(Registers::PARK_PTR, |park| match channel {
+                DmaChannel::Mm2s => park.set_read_frame_reference(frame as u32),
+                DmaChannel::S2mm => park.set_write_frame_reference(frame as u32),
+            })?;
+        self.registers
+            .modify(channel_registers(channel).vdmacr, |control| {
+                control.set_circular(false)
+            })
+    }
+
+    /// Returns the channel to circular mode over all frame stores.
+    pub fn circular(&mut self, channel: DmaChannel) -> Result<(), Error> {
+        self.registers
+            .modify(channel_registers(channel).vdmacr, |control| {
+                control.set_circular(true)
+            })
+    }
+
+    /// Returns the frame store the channel currently transfers.
+    pub fn current_frame(&self, channel: DmaChannel) -> Result<usize, Error> {
+        let park = self.registers.read(Registers::PARK_PTR)?;
+        let frame = match channel {
+            DmaChannel::Mm2s => park.read_frame_store(),
+            DmaChannel::S2mm => park.write_frame_store(),
+        };
+        Ok(frame as usize)
+    }
+
+    /// Enables genlock synchronisation of the channel, or disables it with `None`.
+    pub fn set_genlock(
+        &mut self,
+        channel: DmaChannel,
+        genlock: Option<Genlock>,
+    ) -> Result<(), Error> {
+        self.registers
+            .modify(channel_registers(channel).vdmacr, |control| match genlock {
+                Some(genlock) => {
+                    control.set_genlock_enable(true);
+                    control.set_genlock_source(genlock.internal);
+                    control.set_master(u32::from(genlock.master));
+                }
+                None => control.set_genlock_enable(false),
+            })
+    }
+
+    /// Sets the number of frames per frame count interrupt, 1 to 255.
+    ///
+    /// With `halt` the channel halts after the frames are transferred.
+    pub fn set_frame_count(
+        &mut self,
+        channel: DmaChannel,
+        frames: u8,
+        halt: bool,
+    ) -> Result<(), Error> {
+        if frames == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        self.registers
+            .modify(channel_registers(channel).vdmacr, |control| {
+                control.set_irq_frame_count(u32::from(frames));
+                control.set_frame_count_enable(halt);
+            })
+    }
+
+    /// Waits for the frame count interrupt of the channel.
+    ///
+    /// Errors reported in the status register are cleared and returned, the channel is reset
+    /// when the error halted it.
+    pub fn wait_frame(
+        &mut self,
+        channel: DmaChannel,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error> {
+        let registers = channel_registers(channel);
+        let vdma = &mut self.registers;
+        let result = wait_for(self.interrupt.as_ref(), timeout, || {
+            let status = vdma.read(registers.vdmasr)?;
+            if let Some(error) = status.error() {
+                vdma.write(registers.vdmasr, VdmaStatus::CLEAR_ALL)?;
+                return Err(error);
+            }
+            if status.frame_count_irq() {
+                vdma.write(registers.vdmasr, VdmaStatus::FRAME_COUNT_IRQ)?;
+                return Ok(Some(()));
+            }
+            Ok(None)
+        });
+        if result.is_ok() {
+            self.completed[index(channel)] = true;
+        }
+        if let Err(ref error) = result
+            && *error != Error::Timeout
+        {
+            log::warn!("{:?} frame failed, {:?}", channel, error);
+            if self.is_halted(channel)? {
+                self.reset(channel)?;
+            }
+        }
+        result
+    }
+
+    /// Copies the latest completely written S2MM frame into `data` and returns its frame
+    /// store index.
+    ///
+    /// `frames` are the buffers of the frame stores given to [`AxiVdma::start`], in the same
+    /// order. The channel is parked on the frame it is writing while the previous frame is
+    /// copied, so at least three frame stores are required. The lines are packed into `data`
+    /// without the stride padding. Fails with [`Error::Empty`] until the channel has completed
+    /// its first frame.
+    pub fn grab_latest(&mut self, frames: &[DmaBuffer], data: &mut [u8]) -> Result<usize, Error> {
+        let channel = DmaChannel::S2mm;
+        let format = self.formats[index(channel)].ok_or(Error::InvalidArgument)?;
+        let addresses = &self.frames[index(channel)];
+        if frames.len() != addresses.len()
+            || frames
+                .iter()
+                .zip(addresses)
+                .any(|(frame, address)| frame.address() != Some(*address))
+        {
+            return Err(Error::InvalidArgument);
+        }
+        if frames.len() < 3 {
+            return Err(Error::Unsupported);
+        }
+        if data.len() < format.frame_length() {
+            return Err(Error::InvalidArgument);
+        }
+        if !self.frame_completed(channel)? {
+            return Err(Error::Empty);
+        }
+        let count = frames.len();
+        let current = self.current_frame(channel)?;
+        let latest = (current + count - 1) % count;
+        self.park(channel, current)?;
+        let result = copy_frame(&frames[latest], &format, data);
+        self.circular(channel)?;
+        result.map(|_| latest)
+    }
+
+    /// Checks if the channel has completed a frame since it was started.
+    ///
+    /// The frames remaining until the frame count interrupt start at the configured count and
+    /// are reloaded when the interrupt is raised, so either differs once a frame is complete.
+    fn frame_completed(&mut self, channel: DmaChannel) -> Result<bool, Error> {
+        if !self.completed[index(channel)] {
+            let registers = channel_registers(channel);
+            let status = self.registers.read(registers.vdmasr)?;
+            let control = self.registers.read(registers.vdmacr)?;
+            self.completed[index(channel)] = status.frame_count_irq()
+                || status.irq_frame_count() != control.irq_frame_count();
+        }
+        Ok(self.completed[index(channel)])
+    }
+}
+
+/// Copies the lines of a frame store into `data`.
+fn copy_frame(frame: &DmaBuffer, format: &FrameFormat, data: &mut [u8]) -> Result<(), Error> {
+    frame.sync_for_cpu(0, format.frame_size(), DmaDirection::FromDevice)?;
+    for (line, chunk) in data.chunks_mut(format.hsize).take(format.vsize).enumerate() {
+        frame.read(line * format.stride, chunk)?;
+    }
+    Ok(())
+}
+
+fn index(channel: DmaChannel) -> usize {
+    match channel {
+        DmaChannel::Mm2s => 0,
+        DmaChannel::S2mm => 1,
+    }
+}
+
+fn channel_registers(channel: DmaChannel) -> &'static ChannelRegisters {
+    match channel {
+        DmaChannel::Mm2s => &MM2S,
+        DmaChannel::S2mm => &S2MM,
+    }
+}
+
+/// Register set of one channel
+struct ChannelRegisters {
+    vdmacr: Register<Control, RW>,
+    vdmasr: Register<VdmaStatus, W1C>,
+    reg_index: Register<u32, RW>,
+    frmstore: Register<u32, RW>,
+    vsize: Register<u32, RW>,
+    hsize: Register<u32, RW>,
+    frmdly_stride: Register<FrameDelayStride, RW>,
+    start_address: Register<u32, RW>,
+}
+
+const MM2S: ChannelRegisters = ChannelRegisters {
+    vdmacr: Registers::MM2S_VDMACR,
+    vdmasr: Registers::MM2S_VDMASR,
+    reg_index: Registers::MM2S_REG_INDEX,
+    frmstore: Registers::MM2S_FRMSTORE,
+    vsize: Registers::MM2S_VSIZE,
+    hsize: Registers::MM2S_HSIZE,
+    frmdly_stride: Registers::MM2S_FRMDLY_STRIDE,
+    start_address: Registers::MM2S_START_ADDRESS,
+};
+
+const S2MM: ChannelRegisters = ChannelRegisters {
+    vdmacr: Registers::S2MM_VDMACR,
+    vdmasr: Registers::S2MM_VDMASR,
+    reg_index: Registers::S2MM_REG_INDEX,
+    frmstore: Registers::S2MM_FRMSTORE,
+    vsize: Registers::S2MM_VSIZE,
+    hsize: Registers::S2MM_HSIZE,
+    frmdly_stride: Registers::S2MM_FRMDLY_STRIDE,
+    start_address: Registers::S2MM_START_ADDRESS,
+};
+
+register_map! {
+    /// AXI VDMA registers
+    struct Registers {
+        /// MM2S VDMA control register
+        MM2S_VDMACR: Control, RW @ 0x00 = 0x0001_0000;
+        /// MM2S VDMA status register
+        MM2S_VDMASR: VdmaStatus, W1C @ 0x04 = 0x0001_0001;
+        /// MM2S start address register bank
+        MM2S_REG_INDEX: u32, RW @ 0x14;
+        /// MM2S number of frame stores
+        MM2S_FRMSTORE: u32, RW @ 0x18;
+        /// Park pointers and current frame stores of both channels
+        PARK_PTR: ParkPointer, RW @ 0x28;
+        /// Core version
+        VERSION: u32, RO @ 0x2c;
+        /// S2MM VDMA control register
+        S2MM_VDMACR: Control, RW @ 0x30 = 0x0001_0000;
+        /// S2MM VDMA status register
+        S2MM_VDMASR: VdmaStatus, W1C @ 0x34 = 0x0001_0001;
+        /// S2MM start address register bank
+        S2MM_REG_INDEX: u32, RW @ 0x44;
+        /// S2MM number of frame stores
+        S2MM_FRMSTORE: u32, RW @ 0x48;
+        /// MM2S lines per frame, writing starts the channel
+        MM2S_VSIZE: u32, RW @ 0x50;
+        /// MM2S bytes per line
+        MM2S_HSIZE: u32, RW @ 0x54;
+        /// MM2S frame delay and stride
+        MM2S_FRMDLY_STRIDE: FrameDelayStride, RW @ 0x58;
+        /// MM2S first frame store start address
+        MM2S_START_ADDRESS: u32, RW @ 0x5c;
+        /// S2MM lines per frame, writing starts the channel
+        S2MM_VSIZE: u32, RW @ 0xa0;
+        /// S2MM bytes per line
+        S2MM_HSIZE: u32, RW @ 0xa4;
+        /// S2MM frame delay and stride
+        S2MM_FRMDLY_STRIDE: FrameDelayStride, RW @ 0xa8;
+        /// S2MM first frame store start address
+        S2MM_START_ADDRESS: u32, RW @ 0xac;
+    }
+}
+
+register_bits! {
+    /// VDMA control register
+    struct Control(u32) {
+        /// Run, clear to halt
+        RUN: run, set_run: 0;
+        /// Circular mode, clear for park mode
+        CIRCULAR: circular, set_circular: 1;
+        /// Soft reset of the channel
+        RESET: reset, set_reset: 2;
+        /// Genlock enable
+        GENLOCK_ENABLE: genlock_enable, set_genlock_enable: 3;
+        /// Halt after the frame count is reached
+        FRAME_COUNT_ENABLE: frame_count_enable, set_frame_count_enable: 4;
+        /// Genlock source, internal when set
+        GENLOCK_SOURCE: genlock_source, set_genlock_source: 7;
+        /// Frame pointer number of the genlock master
+        MASTER: master, set_master: 11, 8;
+        /// Frame count interrupt enable
+        FRAME_COUNT_IRQ_ENABLE: frame_count_irq_enable, set_frame_count_irq_enable: 12;
+        /// Delay count interrupt enable
+        DELAY_COUNT_IRQ_ENABLE: delay_count_irq_enable, set_delay_count_irq_enable: 13;
+        /// Error interrupt enable
+        ERROR_IRQ_ENABLE: error_irq_enable, set_error_irq_enable: 14;
+        /// Repeat the previous frame on frame errors
+        REPEAT_ENABLE: repeat_enable, set_repeat_enable: 15;
+        /// Frames per frame count interrupt
+        IRQ_FRAME_COUNT: irq_frame_count, set_irq_frame_count: 23, 16;
+        /// Delay count interrupt timeout
+        IRQ_DELAY_COUNT: irq_delay_count, set_irq_delay_count: 31, 24;
+    }
+}
+
+register_bits! {
+    /// VDMA status register
+    pub struct VdmaStatus(u32) {
+        /// Channel is halted
+        HALTED: halted, set_halted: 0;
+        /// VDMA internal error, such as a frame size mismatch
+        INTERNAL_ERROR: internal_error, set_internal_error: 4;
+        /// VDMA slave error
+        SLAVE_ERROR: slave_error, set_slave_error: 5;
+        /// VDMA decode error
+        DECODE_ERROR: decode_error, set_decode_error: 6;
+        /// Start of frame early
+        SOF_EARLY_ERROR: sof_early_error, set_sof_early_error: 7;
+        /// End of line early
+        EOL_EARLY_ERROR: eol_early_error, set_eol_early_error: 8;
+        /// Start of frame late
+        SOF_LATE_ERROR: sof_late_error, set_sof_late_error: 11;
+        /// Frame count interrupt
+        FRAME_COUNT_IRQ: frame_count_irq, set_frame_count_irq: 12;
+        /// Delay count interrupt
+        DELAY_COUNT_IRQ: delay_count_irq, set_delay_count_irq: 13;
+        /// Error interrupt
+        ERROR_IRQ: error_irq, set_error_irq: 14;
+        /// End of line late
+        EOL_LATE_ERROR: eol_late_error, set_eol_late_error: 15;
+        /// Frames remaining until the frame count interrupt
+        IRQ_FRAME_COUNT: irq_frame_count, set_irq_frame_count: 23, 16;
+        /// Delay count
+        IRQ_DELAY_COUNT: irq_delay_count, set_irq_delay_count: 31, 24;
+    }
+}
+
+impl VdmaStatus {
+    /// All interrupt and clearable error bits
+    pub const CLEAR_ALL: VdmaStatus = VdmaStatus(
+        VdmaStatus::SOF_EARLY_ERROR.0
+            | VdmaStatus::EOL_EARLY_ERROR.0
+            | VdmaStatus::SOF_LATE_ERROR.0
+            | VdmaStatus::FRAME_COUNT_IRQ.0
+            | VdmaStatus::DELAY_COUNT_IRQ.0
+            | VdmaStatus::ERROR_IRQ.0
+            | VdmaStatus::EOL_LATE_ERROR.0,
+    );
+
+    /// Returns the first error reported by the status, if any.
+    pub fn error(&self) -> Option<Error> {
+        if self.internal_error() {
+            Some(Error::DmaInternal)
+        } else if self.slave_error() {
+            Some(Error::DmaSlave)
+        } else if self.decode_error() {
+            Some(Error::DmaDecode)
+        } else if self.sof_early_error()
+            || self.eol_early_error()
+            || self.sof_late_error()
+            || self.eol_late_error()
+        {
+            Some(Error::FrameSize)
+        } else {
+            None
+        }
+    }
+}
+
+register_bits! {
+    /// Park pointer register
+    struct ParkPointer(u32) {
+        /// MM2S park frame store
+        READ_FRAME_REFERENCE: read_frame_reference, set_read_frame_reference: 4, 0;
+        /// S2MM park frame store
+        WRITE_FRAME_REFERENCE: write_frame_reference, set_write_frame_reference: 12, 8;
+        /// MM2S current frame store
+        READ_FRAME_STORE: read_frame_store, set_read_frame_store: 20, 16;
+        /// S2MM current frame store
+        WRITE_FRAME_STORE: write_frame_store, set_write_frame_store: 28, 24;
+    }
+}
+
+register_bits! {
+    /// Frame delay and stride register
+    struct FrameDelayStride(u32) {
+        /// Bytes between the start of consecutive lines
+        STRIDE: stride, set_stride: 15, 0;
+        /// Frame delay of a genlock slave behind its master
+        FRAME_DELAY: frame_delay, set_frame_delay: 28, 24;
+    }
+}
@@ -0,0 +1,235 @@
+use crate::error::Error;
+use std::path::Path;
+
+/// Synchronisation word starting the configuration packets
+pub(crate) const SYNC_WORD: u32 = 0xaa99_5566;
+
+/// Field header of a `.bit` file
+const BIT_HEADER: [u8; 13] = [
+    0x00, 0x09, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// FPGA configuration bitstream
+///
+/// Parsed from a `.bit` file with its header fields, or from a raw `.bin` file. The data is
+/// normalised to big endian configuration words, as written to the configuration interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitstream {
+    design: String,
+    part: String,
+    date: String,
+    time: String,
+    data: Vec<u8>,
+}
+
+impl Bitstream {
+    /// Reads and parses a bitstream file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Bitstream, Error> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Parses a `.bit` file, or a raw bitstream in either byte order.
+    pub fn parse(bytes: &[u8]) -> Result<Bitstream, Error> {
+        let mut bitstream = Bitstream {
+            design: String::new(),
+            part: String::new(),
+            date: String::new(),
+            time: String::new(),
+            data: Vec::new(),
+        };
+        let data = if bytes.starts_with(&BIT_HEADER) {
+            bitstream.parse_header(&bytes[BIT_HEADER.len()..])?
+        } else {
+            bytes
+        };
+        if data.len() % 4 != 0 {
+            return Err(Error::Misaligned);
+        }
+        let sync = SYNC_WORD.to_be_bytes();
+        let swapped = SYNC_WORD.to_le_bytes();
+        let position = |pattern: &[u8; 4]| data.chunks_exact(4).position(|word| word == pattern);
+        bitstream.data = match (position(&sync), position(&swapped)) {
+            (Some(_), _) => data.to_vec(),
+            (None, Some(_)) => data
+                .chunks_exact(4)
+                .flat_map(|word| [word[3], word[2], word[1], word[0]])
+                .collect(),
+            (None, None) => {
+                log::warn!("no synchronisation word in bitstream");
+                return Err(Error::InvalidArgument);
+            }
+        };
+        Ok(bitstream)
+    }
+
+    /// Parses the header fields and returns the configuration data.
+    fn parse_header<'a>(&mut self, bytes: &'a [u8]) -> Result<&'a [u8], Error> {
+        let mut rest = bytes;
+        loop {
+            let (&key, tail) = rest.split_first().ok_or(Error::InvalidArgument)?;
+            if key == b'e' {
+                let length = tail.get(..4).ok_or(Error::InvalidArgument)?;
+                let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+                return tail.get(4..4 + length).ok_or(Error::InvalidArgument);
+            }
+            let length = tail.get(..2).ok_or(Error::InvalidArgument)?;
+            let length = u16::from_be_bytes(length.try_into().unwrap()) as usize;
+            let value = tail.get(2..2 + length).ok_or(Error::InvalidArgument)?;
+            let value = String::from_utf8_lossy(value)
+                .trim_end_matches('\0')
+                .to_string();
+            match key {
+                b'a' => self.design = value,
+                b'b' => self.part = value,
+                b'c' => self.date = value,
+                b'd' => self.time = value,
+                _ => return Err(Error::InvalidArgument),
+            }
+            rest = &tail[2 + length..];
+        }
+    }
+
+    /// Returns the design name and properties, empty for a raw bitstream.
+    pub fn design(&self) -> &str {
+        &self.design
+    }
+
+    /// Returns the target part, empty for a raw bitstream.
+    pub fn part(&self) -> &str {
+        &self.part
+    }
+
+    /// Returns the creation date, empty for a raw bitstream.
+    pub fn date(&self) -> &str {
+        &self.date
+    }
+
+    /// Returns the creation time, empty for a raw bitstream.
+    pub fn time(&self) -> &str {
+        &self.time
+    }
+
+    /// Returns the configuration data in big endian byte order.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the configuration words.
+    pub fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        self.data
+            .chunks_exact(4)
+            .map(|word| u32::from_be_bytes(word.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuration words, dummy, bus width detection, sync word and a NOOP
+    const WORDS: [u32; 7] = [
+        0xffff_ffff,
+        0x0000_00bb,
+        0x1122_0044,
+        0xffff_ffff,
+        SYNC_WORD,
+        0x2000_0000,
+        0x3000_8001,
+    ];
+
+    fn raw() -> Vec<u8> {
+        WORDS.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    fn field(key: u8, value: &str) -> Vec<u8> {
+        let mut field = vec![key];
+        field.extend_from_slice(&(value.len() as u16 + 1).to_be_bytes());
+        field.extend_from_slice(value.as_bytes());
+        field.push(0);
+        field
+    }
+
+    /// Returns a `.bit` file as written by Vivado.
+    fn bit_file() -> Vec<u8> {
+        let mut file = BIT_HEADER.to_vec();
+        file.extend(field(
+            b'a',
+            "design_1_wrapper;UserID=0XFFFFFFFF;Version=2024.1",
+        ));
+        file.extend(field(b'b', "xczu3eg-sbva484-1-e"));
+        file.extend(field(b'c', "2024/06/12"));
+        file.extend(field(b'd', "10:42:17"));
+        let data = raw();
+        file.push(b'e');
+        file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        file.extend(data);
+        file
+    }
+
+    #[test]
+    fn bit_file_header() {
+        let bitstream = Bitstream::parse(&bit_file()).unwrap();
+        assert_eq!(
+            bitstream.design(),
+            "design_1_wrapper;UserID=0XFFFFFFFF;Version=2024.1"
+        );
+        assert_eq!(bitstream.part(), "xczu3eg-sbva484-1-e");
+        assert_eq!(bitstream.date(), "2024/06/12");
+        assert_eq!(bitstream.time(), "10:42:17");
+        assert_eq!(bitstream.data(), raw());
+        assert!(bitstream.words().eq(WORDS));
+    }
+
+    #[test]
+    fn raw_bitstream() {
+        let bitstream = Bitstream::parse(&raw()).unwrap();
+        assert_eq!(bitstream.design(), "");
+        assert_eq!(bitstream.part(), "");
+        assert!(bitstream.words().eq(WORDS));
+    }
+
+    #[test]
+    fn byte_swapped_bitstream() {
+        let swapped: Vec<u8> = WORDS.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let bitstream = Bitstream::parse(&swapped).unwrap();
+        assert_eq!(bitstream.data(), raw());
+        assert!(bitstream.words().eq(WORDS));
+    }
+
+    #[test]
+    fn missing_sync_word() {
+        let data: Vec<u8> = WORDS
+            .iter()
+            .filter(|word| **word != SYNC_WORD)
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        assert_eq!(Bitstream::parse(&data), Err(Error::InvalidArgument));
+        assert_eq!(Bitstream::parse(&[]), Err(Error::InvalidArgument));
+        // The sync word has to be word aligned
+        let mut shifted = vec![0, 0];
+        shifted.extend(raw());
+        shifted.extend([0, 0]);
+        assert_eq!(Bitstream::parse(&shifted), Err(Error::InvalidArgument));
+        let mut odd = raw();
+        odd.push(0);
+        assert_eq!(Bitstream::parse(&odd), Err(Error::Misaligned));
+    }
+
+    #[test]
+    fn truncated_fields() {
+        let file = bit_file();
+        // Cut inside every field of the header, including the data length and the data
+        for length in BIT_HEADER.len()..file.len() {
+            assert_eq!(
+                Bitstream::parse(&file[..length]),
+                Err(Error::InvalidArgument),
+                "length {}",
+                length
+            );
+        }
+        // Unknown field key
+        let mut unknown = BIT_HEADER.to_vec();
+        unknown.extend(field(b'x', "value"));
+        assert_eq!(Bitstream::parse(&unknown), Err(Error::InvalidArgument));
+    }
+}
@@ -0,0 +1,644 @@
+use crate::devicetree;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::time::Duration;
+
+/// Maximum number of clock outputs
+pub const CLOCK_WIZARD_MAX_OUTPUTS: usize = 7;
+
+/// Software reset key
+const RESET_KEY: u32 = 0x0000_000a;
+
+/// Time to wait for the clock to lock
+const LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Clocking primitive configured by the Clocking Wizard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockPrimitive {
+    /// 7 series MMCM
+    Mmcme2,
+    /// 7 series PLL
+    Plle2,
+    /// UltraScale MMCM
+    Mmcme3,
+    /// UltraScale PLL
+    Plle3,
+    /// UltraScale+ MMCM
+    Mmcme4,
+    /// UltraScale+ PLL
+    Plle4,
+}
+
+/// Operating limits of a clocking primitive, frequencies in Hz
+#[derive(Clone, Copy, Debug)]
+struct Limits {
+    vco: (u64, u64),
+    pfd: (u64, u64),
+    multiply: (u32, u32),
+    divide: (u32, u32),
+    output_divide: (u32, u32),
+    outputs: usize,
+    fractional: bool,
+}
+
+impl ClockPrimitive {
+    /// Returns the limits of the slowest speed grade.
+    fn limits(&self) -> Limits {
+        const MHZ: u64 = 1_000_000;
+        match self {
+            ClockPrimitive::Mmcme2 => Limits {
+                vco: (600 * MHZ, 1200 * MHZ),
+                pfd: (10 * MHZ, 450 * MHZ),
+                multiply: (2, 64),
+                divide: (1, 106),
+                output_divide: (1, 128),
+                outputs: 7,
+                fractional: true,
+            },
+            ClockPrimitive::Plle2 => Limits {
+                vco: (800 * MHZ, 1600 * MHZ),
+                pfd: (19 * MHZ, 450 * MHZ),
+                multiply: (2, 64),
+                divide: (1, 56),
+                output_divide: (1, 128),
+                outputs: 6,
+                fractional: false,
+            },
+            ClockPrimitive::Mmcme3 => Limits {
+                vco: (600 * MHZ, 1440 * MHZ),
+                pfd: (10 * MHZ, 450 * MHZ),
+                multiply: (2, 64),
+                divide: (1, 106),
+                output_divide: (1, 128),
+                outputs: 7,
+                fractional: true,
+            },
+            ClockPrimitive::Plle3 => Limits {
+                vco: (600 * MHZ, 1335 * MHZ),
+                pfd: (70 * MHZ, 667 * MHZ),
+                multiply: (1, 19),
+                divide: (1, 15),
+                output_divide: (1, 128),
+                outputs: 2,
+                fractional: false,
+            },
+            ClockPrimitive::Mmcme4 => Limits {
+                vco: (800 * MHZ, 1600 * MHZ),
+                pfd: (10 * MHZ, 450 * MHZ),
+                multiply: (2, 128),
+                divide: (1, 106),
+                output_divide: (1, 128),
+                outputs: 7,
+                fractional: true,
+            },
+            ClockPrimitive::Plle4 => Limits {
+                vco: (750 * MHZ, 1500 * MHZ),
+                pfd: (70 * MHZ, 667 * MHZ),
+                multiply: (1, 19),
+                divide: (1, 15),
+                output_divide: (1, 128),
+                outputs: 2,
+                fractional: false,
+            },
+        }
+    }
+}
+
+/// Multiplier and divider settings of a clocking primitive
+///
+/// The output frequency of output `n` is `input * multiply / divide / output_divide[n]`. The
+/// multiplier and the divider of output 0 are multiples of 1/8 on an MMCM, the divider of
+/// output 0 is either 1 or at least 2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClockSettings {
+    /// Input divider, `DIVCLK_DIVIDE`
+    pub divide: u32,
+    /// Feedback multiplier, `CLKFBOUT_MULT_F`
+    pub multiply: f64,
+    /// Output dividers, `CLKOUTn_DIVIDE`
+    pub output_divide: Vec<f64>,
+}
+
+impl ClockSettings {
+    /// Returns the VCO frequency in Hz for the input frequency.
+    pub fn vco_frequency(&self, input_frequency: u64) -> f64 {
+        input_frequency as f64 * self.multiply / f64::from(self.divide)
+    }
+
+    /// Returns the output frequencies in Hz for the input frequency.
+    pub fn frequencies(&self, input_frequency: u64) -> Vec<u64> {
+        let vco = self.vco_frequency(input_frequency);
+        self.output_divide
+            .iter()
+            .map(|divide| (vco / divide).round() as u64)
+            .collect()
+    }
+}
+
+/// Clocking Wizard (PG065)
+///
+/// Dynamically reconfigures the MMCM or PLL through the AXI4-Lite interface. The multiplier and
+/// dividers for the requested output frequencies are computed within the VCO and phase detector
+/// limits of the primitive, the slowest speed grade unless the VCO range is changed.
+pub struct ClockingWizard {
+    registers: Mmio,
+    primitive: ClockPrimitive,
+    limits: Limits,
+    input_frequency: u64,
+    outputs: usize,
+}
+
+impl ClockingWizard {
+    /// Creates a new `ClockingWizard` instance from a UIO device.
+    ///
+    /// The number of outputs is taken from `xlnx,nr-outputs` when available.
+    pub fn try_from(
+        device: &DeviceInfo,
+        primitive: ClockPrimitive,
+        input_frequency: u64,
+    ) -> Result<ClockingWizard, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let mut wizard = Self::from_mmio(registers, primitive, input_frequency)?;
+        if let Some(node) = device.of_node()
+            && let Ok(outputs) = devicetree::read_u32(node, "xlnx,nr-outputs")
+        {
+            wizard.set_output_count(outputs as usize)?;
+        }
+        Ok(wizard)
+    }
+
+    /// Creates a new `ClockingWizard` instance from a memory mapped register region and the
+    /// input clock frequency in Hz.
+    pub fn from_mmio(
+        registers: Mmio,
+        primitive: ClockPrimitive,
+        input_frequency: u64,
+    ) -> Result<ClockingWizard, Error> {
+        if input_frequency == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        let limits = primitive.limits();
+        Ok(ClockingWizard {
+            registers,
+            primitive,
+            limits,
+            input_frequency,
+            outputs: limits.outputs,
+        })
+    }
+
+    /// Returns the clocking primitive.
+    pub fn primitive(&self) -> ClockPrimitive {
+        self.primitive
+    }
+
+    /// Returns the input clock frequency in Hz.
+    pub fn input_frequency(&self) -> u64 {
+        self.input_frequency
+    }
+
+    /// Sets the number of enabled outputs.
+    pub fn set_output_count(&mut self, outputs: usize) -> Result<(), Error> {
+        if outputs == 0 || outputs > self.limits.outputs {
+            return Err(Error::InvalidArgument);
+        }
+        self.outputs = outputs;
+        Ok(())
+    }
+
+    /// Returns the number of enabled outputs.
+    pub fn output_count(&self) -> usize {
+        self.outputs
+    }
+
+    /// Sets the VCO frequency range in Hz, for faster speed grades.
+    pub fn set_vco_range(&mut self, minimum: u64, maximum: u64) -> Result<(), Error> {
+        if minimum == 0 || minimum > maximum {
+            return Err(Error::InvalidArgument);
+        }
+        self.limits.vco = (minimum, maximum);
+        Ok(())
+    }
+
+    /// Resets the clocking wizard to the configuration of the bitstream.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.registers.write(Registers::SRR, RESET_KEY)?;
+        self.wait_locked()
+    }
+
+    /// Checks if the clock is locked.
+    pub fn is_locked(&self) -> Result<bool, Error> {
+        Ok(self.registers.read(Registers::SR)?.locked())
+    }
+
+    fn wait_locked(&self) -> Result<(), Error> {
+        wait_for(None, Some(LOCK_TIMEOUT), || {
+            Ok(self.is_locked()?.then_some(()))
+        })
+    }
+
+    /// Computes the settings closest to the output frequencies in Hz.
+    ///
+    /// Outputs are matched in order, the dividers of the remaining outputs are left as they are.
+    /// Among equally close settings the highest VCO frequency is chosen for lower jitter.
+    pub fn compute(&self, frequencies: &[u64]) -> Result<ClockSettings, Error> {
+        compute(
+            &self.limits,
+            self.input_frequency,
+            self.outputs,
+            frequencies,
+        )
+    }
+
+    /// Programs the settings, loads them and waits for the clock to lock.
+    pub fn configure(&mut self, settings: &ClockSettings) -> Result<(), Error> {
+        if settings.output_divide.len() > self.outputs {
+            return Err(Error::InvalidArgument);
+        }
+        let mut config = ClockConfig0::empty();
+        config.set_divide(settings.divide);
+        config.set_multiply(settings.multiply.trunc() as u32);
+        config.set_multiply_fraction(fraction(settings.multiply));
+        self.registers.write(Registers::CONFIG0, config)?;
+        for (n, divide) in settings.output_divide.iter().enumerate() {
+            let mut output = OutputDivide::empty();
+            output.set_divide(divide.trunc() as u32);
+            if n == 0 {
+                output.set_fraction(fraction(*divide));
+            }
+            let offset = n * OUTPUT_STRIDE;
+            self.registers
+                .write(Registers::CLKOUT0_DIVIDE.shifted(offset), output)?;
+            self.registers
+                .write(Registers::CLKOUT0_DUTY.shifted(offset), DUTY_50_PERCENT)?;
+        }
+        self.registers.write(
+            Registers::RECONFIGURE,
+            Reconfigure::LOAD | Reconfigure::SAVED,
+        )?;
+        self.wait_locked()
+    }
+
+    /// Computes and programs the settings closest to the output frequencies in Hz, returns the
+    /// configured frequencies.
+    pub fn set_frequencies(&mut self, frequencies: &[u64]) -> Result<Vec<u64>, Error> {
+        let settings = self.compute(frequencies)?;
+        self.configure(&settings)?;
+        Ok(settings.frequencies(self.input_frequency))
+    }
+
+    /// Reads the current settings.
+    pub fn settings(&self) -> Result<ClockSettings, Error> {
+        let config = self.registers.read(Registers::CONFIG0)?;
+        let multiply =
+            f64::from(config.multiply()) + f64::from(config.multiply_fraction()) / 1000.0;
+        let output_divide = (0..self.outputs)
+            .map(|n| {
+                let output = self
+                    .registers
+                    .read(Registers::CLKOUT0_DIVIDE.shifted(n * OUTPUT_STRIDE))?;
+                let fraction = if n == 0 { output.fraction() } else { 0 };
+                Ok(f64::from(output.divide()) + f64::from(fraction) / 1000.0)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(ClockSettings {
+            divide: config.divide().max(1),
+            multiply,
+            output_divide,
+        })
+    }
+
+    /// Returns the configured output frequencies in Hz.
+    pub fn frequencies(&self) -> Result<Vec<u64>, Error> {
+        Ok(self.settings()?.frequencies(self.input_frequency))
+    }
+}
+
+/// Computes the settings closest to the output frequencies within the limits.
+fn compute(
+    limits: &Limits,
+    input_frequency: u64,
+    outputs: usize,
+    frequencies: &[u64],
+) -> Result<ClockSettings, Error> {
+    if frequencies.is_empty() || frequencies.len() > outputs || frequencies.contains(&0) {
+        return Err(Error::InvalidArgument);
+    }
+    let input = input_frequency as f64;
+    // Fractional values are in eighths
+    let step = if limits.fractional { 8 } else { 1 };
+    let mut best: Option<(f64, f64, ClockSettings)> = None;
+    for divide in limits.divide.0..=limits.divide.1 {
+        let pfd = input_frequency / u64::from(divide);
+        if pfd < limits.pfd.0 {
+            break;
+        }
+        if pfd > limits.pfd.1 {
+            continue;
+        }
+        for multiply in limits.multiply.0 * step..=limits.multiply.1 * step {
+            let multiply = f64::from(multiply) / f64::from(step);
+            let vco = input * multiply / f64::from(divide);
+            if vco < limits.vco.0 as f64 || vco > limits.vco.1 as f64 {
+                continue;
+            }
+            let mut error = 0.0;
+            let mut output_divide = Vec::with_capacity(frequencies.len());
+            for (n, frequency) in frequencies.iter().enumerate() {
+                let fractional = n == 0 && limits.fractional;
+                let value = output_divider(limits, vco, *frequency, fractional);
+                error += relative_error(vco / value, *frequency);
+                output_divide.push(value);
+            }
+            let better = match &best {
+                None => true,
+                Some((best_error, best_vco, _)) => {
+                    error < *best_error - f64::EPSILON
+                        || ((error - *best_error).abs() <= f64::EPSILON && vco > *best_vco)
+                }
+            };
+            if better {
+                best = Some((
+                    error,
+                    vco,
+                    ClockSettings {
+                        divide,
+                        multiply,
+                        output_divide,
+                    },
+                ));
+            }
+        }
+    }
+    let (error, _, settings) = best.ok_or(Error::OutOfRange)?;
+    log::debug!("clock settings {:?}, error {:.6}", settings, error);
+    Ok(settings)
+}
+
+/// Returns the output divider closest to `vco / frequency` within the limits.
+///
+/// A fractional divider is a multiple of 1/8, but only from 2 on, below 2 the divider is 1.
+fn output_divider(limits: &Limits, vco: f64, frequency: u64, fractional: bool) -> f64 {
+    let step = if fractional { 8 } else { 1 };
+    let ideal = vco / frequency as f64 * f64::from(step);
+    let value = ideal.round().clamp(
+        f64::from(limits.output_divide.0 * step),
+        f64::from(limits.output_divide.1 * step),
+    ) / f64::from(step);
+    if fractional && value < 2.0 {
+        if relative_error(vco, frequency) < relative_error(vco / 2.0, frequency) {
+            1.0
+        } else {
+            2.0
+        }
+    } else {
+        value
+    }
+}
+
+/// Returns the deviation of `actual` from `frequency` relative to `frequency`.
+fn relative_error(actual: f64, frequency: u64) -> f64 {
+    (actual - frequency as f64).abs() / frequency as f64
+}
+
+/// Returns the fractional part in thousandths, as programmed in the fraction fields.
+fn fraction(value: f64) -> u32 {
+    (value.fract() * 1000.0).round() as u32
+}
+
+/// Distance between the register sets of consecutive outputs
+const OUTPUT_STRIDE: usize = 12;
+
+/// Duty cycle of 50 percent, in thousandths of a percent
+const DUTY_50_PERCENT: u32 = 50_000;
+
+register_map! {
+    /// Clocking Wizard registers
+    struct Registers {
+        /// Software reset
+        SRR: u32, WO @ 0x000;
+        /// Status
+        SR: Status, RO @ 0x004;
+        /// Clock configuration 0, input divider and feedback multiplier
+        CONFIG0: ClockConfig0, RW @ 0x200;
+        /// Output 0 divider, followed by the other outputs
+        CLKOUT0_DIVIDE: OutputDivide, RW @ 0x208;
+        /// Output 0 duty cycle, followed by the other outputs
+        CLKOUT0_DUTY: u32, RW @ 0x210;
+        /// Load the configuration
+        RECONFIGURE: Reconfigure, RW @ 0x25c;
+    }
+}
+
+register_bits! {
+    /// Status register
+    struct Status(u32) {
+        /// The clock is locked
+        LOCKED: locked, set_locked: 0;
+    }
+}
+
+register_bits! {
+    /// Clock configuration register 0
+    struct ClockConfig0(u32) {
+        /// Input divider
+        DIVIDE: divide, set_divide: 7, 0;
+        /// Feedback multiplier integer part
+        MULTIPLY: multiply, set_multiply: 15, 8;
+        /// Feedback multiplier fractional part, in thousandths
+        MULTIPLY_FRACTION: multiply_fraction, set_multiply_fraction: 25, 16;
+    }
+}
+
+register_bits! {
+    /// Output divider register
+    struct OutputDivide(u32) {
+        /// Divider integer part
+        DIVIDE: divide, set_divide: 7, 0;
+        /// Divider fractional part in thousandths, output 0 only
+        FRACTION: fraction, set_fraction: 17, 8;
+    }
+}
+
+register_bits! {
+    /// Reconfiguration register
+    struct Reconfigure(u32) {
+        /// Load the configuration
+        LOAD: load, set_load: 0;
+        /// Load the programmed configuration, the bitstream configuration when clear
+        SAVED: saved, set_saved: 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MHZ: u64 = 1_000_000;
+    const PRIMITIVES: [ClockPrimitive; 6] = [
+        ClockPrimitive::Mmcme2,
+        ClockPrimitive::Plle2,
+        ClockPrimitive::Mmcme3,
+        ClockPrimitive::Plle3,
+        ClockPrimitive::Mmcme4,
+        ClockPrimitive::Plle4,
+    ];
+
+    /// Checks that the settings are within the limits of the primitive.
+    fn assert_within_limits(primitive: ClockPrimitive, input: u64, settings: &ClockSettings) {
+        let limits = primitive.limits();
+        let step = if limits.fractional { 8.0 } else { 1.0 };
+        assert!((limits.divide.0..=limits.divide.1).contains(&settings.divide));
+        let pfd = input / u64::from(settings.divide);
+        assert!(
+            (limits.pfd.0..=limits.pfd.1).contains(&pfd),
+            "{:?} pfd {}",
+            primitive,
+            pfd
+        );
+        let multiply = settings.multiply;
+        assert_eq!(
+            (multiply * step).fract(),
+            0.0,
+            "{:?} multiply {}",
+            primitive,
+            multiply
+        );
+        assert!(multiply >= f64::from(limits.multiply.0));
+        assert!(multiply <= f64::from(limits.multiply.1));
+        let vco = settings.vco_frequency(input);
+        assert!(vco >= limits.vco.0 as f64 && vco <= limits.vco.1 as f64);
+        for (n, divide) in settings.output_divide.iter().enumerate() {
+            assert!(*divide >= f64::from(limits.output_divide.0));
+            assert!(*divide <= f64::from(limits.output_divide.1));
+            if n == 0 && limits.fractional {
+                assert_eq!(
+                    (divide * 8.0).fract(),
+                    0.0,
+                    "{:?} divide {}",
+                    primitive,
+                    divide
+                );
+                assert!(
+                    *divide == 1.0 || *divide >= 2.0,
+                    "{:?} divide {}",
+                    primitive,
+                    divide
+                );
+            } else {
+                assert_eq!(divide.fract(), 0.0, "{:?} divide {}", primitive, divide);
+            }
+        }
+    }
+
+    fn compute_for(
+        primitive: ClockPrimitive,
+        input: u64,
+        frequencies: &[u64],
+    ) -> Result<ClockSettings, Error> {
+        let limits = primitive.limits();
+        compute(&limits, input, limits.outputs, frequencies)
+    }
+
+    #[test]
+    fn within_limits() {
+        let requests: [&[u64]; 6] = [
+            &[100 * MHZ, 200 * MHZ],
+            &[33_333_333, 74_250_000],
+            &[148_500_000],
+            &[10 * MHZ, 450 * MHZ],
+            &[700 * MHZ, 3 * MHZ],
+            &[123_456_789, 987_654],
+        ];
+        for primitive in PRIMITIVES {
+            let inputs = [24 * MHZ, 33_333_333, 100 * MHZ, 125 * MHZ];
+            // The PLLs of UltraScale devices need a phase detector frequency of 70 MHz
+            for input in inputs
+                .into_iter()
+                .filter(|i| *i >= primitive.limits().pfd.0)
+            {
+                for frequencies in requests {
+                    let settings = compute_for(primitive, input, frequencies).unwrap();
+                    assert_eq!(settings.output_divide.len(), frequencies.len());
+                    assert_within_limits(primitive, input, &settings);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_frequencies() {
+        let cases: [(ClockPrimitive, u64, &[u64]); 8] = [
+            (
+                ClockPrimitive::Mmcme2,
+                100 * MHZ,
+                &[100 * MHZ, 200 * MHZ, 50 * MHZ, 25 * MHZ],
+            ),
+            (ClockPrimitive::Mmcme2, 100 * MHZ, &[156_250_000, 125 * MHZ]),
+            (ClockPrimitive::Mmcme2, 100 * MHZ, &[800 * MHZ]),
+            (
+                ClockPrimitive::Plle2,
+                100 * MHZ,
+                &[100 * MHZ, 200 * MHZ, 400 * MHZ],
+            ),
+            (
+                ClockPrimitive::Mmcme3,
+                125 * MHZ,
+                &[250 * MHZ, 125 * MHZ, 62_500_000],
+            ),
+            (ClockPrimitive::Plle3, 100 * MHZ, &[100 * MHZ, 200 * MHZ]),
+            (
+                ClockPrimitive::Mmcme4,
+                100 * MHZ,
+                &[300 * MHZ, 100 * MHZ, 75 * MHZ],
+            ),
+            (ClockPrimitive::Plle4, 100 * MHZ, &[250 * MHZ, 125 * MHZ]),
+        ];
+        for (primitive, input, frequencies) in cases {
+            let settings = compute_for(primitive, input, frequencies).unwrap();
+            assert_within_limits(primitive, input, &settings);
+            let actual = settings.frequencies(input);
+            for (actual, expected) in actual.iter().zip(frequencies) {
+                assert_eq!(actual, expected, "{:?}", primitive);
+            }
+        }
+    }
+
+    #[test]
+    fn fractional_divider_below_two() {
+        // Dividers of 1.125 to 1.875 are not available, the closer of 1 and 2 is used
+        let limits = ClockPrimitive::Mmcme2.limits();
+        assert_eq!(output_divider(&limits, 1200e6, 1000 * MHZ, true), 1.0);
+        assert_eq!(output_divider(&limits, 1200e6, 800 * MHZ, true), 2.0);
+        assert_eq!(output_divider(&limits, 1200e6, 600 * MHZ, true), 2.0);
+        assert_eq!(output_divider(&limits, 1200e6, 560 * MHZ, true), 2.125);
+        assert_eq!(output_divider(&limits, 1200e6, 800 * MHZ, false), 2.0);
+        let settings = compute_for(ClockPrimitive::Mmcme2, 100 * MHZ, &[800 * MHZ]).unwrap();
+        assert_eq!(settings.output_divide, [1.0]);
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let limits = ClockPrimitive::Plle4.limits();
+        assert_eq!(
+            compute(&limits, 100 * MHZ, 2, &[]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            compute(&limits, 100 * MHZ, 2, &[0]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            compute(&limits, 100 * MHZ, 2, &[MHZ, MHZ, MHZ]),
+            Err(Error::InvalidArgument)
+        );
+        // No phase detector frequency within the limits
+        assert_eq!(
+            compute(&limits, 10 * MHZ, 2, &[MHZ]),
+            Err(Error::OutOfRange)
+        );
+    }
+}
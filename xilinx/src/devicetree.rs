@@ -0,0 +1,68 @@
+use crate::error::Error;
+use crate::mmio::Region;
+use std::path::Path;
+
+/// Root of the live device tree exposed by the kernel
+pub const DEVICE_TREE_ROOT: &str = "/sys/firmware/devicetree/base";
+
+/// Reads a string list property, such as `compatible`, from a device tree node.
+pub fn read_strings(node: &Path, property: &str) -> Result<Vec<String>, Error> {
+    let raw = std::fs::read(node.join(property))?;
+    Ok(raw
+        .split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect())
+}
+
+/// Returns the `compatible` strings of a device tree node.
+pub fn compatible(node: &Path) -> Result<Vec<String>, Error> {
+    read_strings(node, "compatible")
+}
+
+/// Reads a property made of big-endian 32-bit cells.
+pub fn read_cells(node: &Path, property: &str) -> Result<Vec<u32>, Error> {
+    let raw = std::fs::read(node.join(property))?;
+    if raw.len() % 4 != 0 {
+        return Err(Error::Io(std::io::ErrorKind::InvalidData));
+    }
+    Ok(raw
+        .chunks_exact(4)
+        .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+        .collect())
+}
+
+/// Reads a single cell property, such as `#address-cells`.
+pub fn read_u32(node: &Path, property: &str) -> Result<u32, Error> {
+    read_cells(node, property)?
+        .first()
+        .copied()
+        .ok_or(Error::Io(std::io::ErrorKind::InvalidData))
+}
+
+/// Returns the memory regions of a device tree node from its `reg` property.
+///
+/// The cell sizes are taken from `#address-cells` and `#size-cells` of the parent node. The
+/// addresses are not translated through any `ranges` of the parent buses. Cell counts above
+/// two do not fit a 64-bit address or size and are reported as unsupported.
+pub fn reg(node: &Path) -> Result<Vec<Region>, Error> {
+    let parent = node.parent().unwrap_or(node);
+    let address_cells = read_u32(parent, "#address-cells").unwrap_or(2) as usize;
+    let size_cells = read_u32(parent, "#size-cells").unwrap_or(1) as usize;
+    if address_cells > 2 || size_cells > 2 {
+        return Err(Error::Unsupported);
+    }
+    let cells = read_cells(node, "reg")?;
+    let stride = address_cells + size_cells;
+    if stride == 0 || cells.len() % stride != 0 {
+        return Err(Error::Io(std::io::ErrorKind::InvalidData));
+    }
+    let combine = |cells: &[u32]| cells.iter().fold(0u64, |v, c| (v << 32) | u64::from(*c));
+    Ok(cells
+        .chunks_exact(stride)
+        .map(|entry| {
+            let (address, size) = entry.split_at(address_cells);
+            Region::new(combine(address), combine(size))
+        })
+        .collect())
+}
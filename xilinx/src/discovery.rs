@@ -0,0 +1,587 @@
+use crate::devicetree;
+use crate::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default location of the UIO class in sysfs
+pub const UIO_CLASS_ROOT: &str = "/sys/class/uio";
+
+/// Default location of the UIO device nodes
+pub const UIO_DEV_ROOT: &str = "/dev";
+
+/// Interval between sysfs scans while waiting for devices
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Selects a UIO device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selector {
+    /// UIO number, the `N` in `/dev/uioN`
+    Number(u16),
+    /// Device name as reported by the UIO driver
+    Name(String),
+    /// Device tree `compatible` string, e.g. `xlnx,axi-fifo-mm-s-4.1`
+    Compatible(String),
+    /// Physical base address of one of the device memory maps
+    Address(u64),
+    /// Device node path, e.g. `/dev/uio0`
+    Path(PathBuf),
+    /// Device tree node path, e.g. `/amba_pl/axi_fifo_mm_s@43c00000`
+    DevicePath(PathBuf),
+}
+
+impl Selector {
+    /// Interprets a user supplied string as a selector.
+    ///
+    /// `/dev/...` is a device node, other absolute paths are device tree paths, `0x` prefixed
+    /// values are base addresses, plain numbers are UIO numbers, strings containing a comma are
+    /// `compatible` strings and anything else is a device name.
+    pub fn parse(text: &str) -> Selector {
+        if text.starts_with("/dev/") {
+            Selector::Path(PathBuf::from(text))
+        } else if text.starts_with('/') {
+            Selector::DevicePath(PathBuf::from(text))
+        } else if let Some(address) = text
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+        {
+            Selector::Address(address)
+        } else if let Ok(number) = text.parse::<u16>() {
+            Selector::Number(number)
+        } else if text.contains(',') {
+            Selector::Compatible(text.to_string())
+        } else {
+            Selector::Name(text.to_string())
+        }
+    }
+}
+
+impl From<&str> for Selector {
+    fn from(text: &str) -> Self {
+        Selector::parse(text)
+    }
+}
+
+/// Memory map of a UIO device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapInfo {
+    /// Map name, if any
+    pub name: Option<String>,
+    /// Physical address
+    pub address: u64,
+    /// Size in bytes
+    pub size: u64,
+    /// Offset into the first page
+    pub offset: u64,
+}
+
+/// Description of a UIO device found in sysfs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    uio: u16,
+    path: PathBuf,
+    name: String,
+    maps: Vec<MapInfo>,
+    of_node: Option<PathBuf>,
+    compatible: Vec<String>,
+}
+
+impl DeviceInfo {
+    /// Returns the UIO number.
+    pub fn uio(&self) -> u16 {
+        self.uio
+    }
+
+    /// Returns the device name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the device memory maps.
+    pub fn maps(&self) -> &[MapInfo] {
+        &self.maps
+    }
+
+    /// Returns the device tree node backing the device, if any.
+    pub fn of_node(&self) -> Option<&Path> {
+        self.of_node.as_deref()
+    }
+
+    /// Returns the device tree `compatible` strings.
+    pub fn compatible(&self) -> &[String] {
+        &self.compatible
+    }
+
+    /// Returns the device node path, `/dev/uioN`.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Checks if the device matches the selector.
+    pub fn matches(&self, selector: &Selector) -> bool {
+        match selector {
+            Selector::Number(number) => self.uio == *number,
+            Selector::Name(name) => self.name == *name,
+            Selector::Compatible(compatible) => self.compatible.iter().any(|c| c == compatible),
+            Selector::Address(address) => self.maps.iter().any(|m| m.address == *address),
+            Selector::Path(path) => {
+                let canonical = |path: &Path| path.canonicalize().unwrap_or(path.to_path_buf());
+                canonical(path) == canonical(&self.path)
+            }
+            Selector::DevicePath(path) => {
+                let path = path.strip_prefix("/").unwrap_or(path);
+                self.of_node
+                    .as_ref()
+                    .map(|node| !path.as_os_str().is_empty() && node.ends_with(path))
+                    .unwrap_or(false)
+            }
+        }
+    }
+
+    /// Opens the UIO device.
+    pub fn open(&self) -> Result<uio_rs::Device, Error> {
+        uio_rs::Device::new(self.uio).map_err(|e| e.into())
+    }
+
+    fn read(path: &Path, dev: &Path, uio: u16) -> Result<DeviceInfo, Error> {
+        let name = read_trimmed(&path.join("name"))?;
+        let mut maps = Vec::new();
+        let mut index = 0;
+        loop {
+            let map_path = path.join("maps").join(format!("map{}", index));
+            if !map_path.is_dir() {
+                break;
+            }
+            maps.push(MapInfo {
+                name: read_trimmed(&map_path.join("name"))
+                    .ok()
+                    .filter(|n| !n.is_empty()),
+                address: read_hex(&map_path.join("addr"))?,
+                size: read_hex(&map_path.join("size"))?,
+                offset: read_hex(&map_path.join("offset")).unwrap_or(0),
+            });
+            index += 1;
+        }
+        let of_node = path.join("device").join("of_node").canonicalize().ok();
+        let compatible = of_node
+            .as_deref()
+            .and_then(|node| devicetree::compatible(node).ok())
+            .unwrap_or_default();
+        Ok(DeviceInfo {
+            uio,
+            path: dev.join(format!("uio{}", uio)),
+            name,
+            maps,
+            of_node,
+            compatible,
+        })
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uio{} {}", self.uio, self.name)?;
+        if !self.compatible.is_empty() {
+            write!(f, " [{}]", self.compatible.join(" "))?;
+        }
+        for map in &self.maps {
+            write!(f, " 0x{:08x}+0x{:x}", map.address, map.size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds UIO devices through sysfs
+#[derive(Clone, Debug)]
+pub struct Discovery {
+    root: PathBuf,
+    dev: PathBuf,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Discovery::new(UIO_CLASS_ROOT)
+    }
+}
+
+impl Discovery {
+    /// Creates a discovery rooted at the given UIO class directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self::with_dev_root(root, UIO_DEV_ROOT)
+    }
+
+    /// Creates a discovery rooted at the given UIO class directory, with the device nodes in
+    /// `dev` instead of `/dev`.
+    pub fn with_dev_root<P: AsRef<Path>, Q: AsRef<Path>>(root: P, dev: Q) -> Self {
+        Discovery {
+            root: root.as_ref().to_path_buf(),
+            dev: dev.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Lists all UIO devices, ordered by UIO number.
+    pub fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        let mut devices = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let uio = match file_name
+                .to_str()
+                .and_then(|n| n.strip_prefix("uio"))
+                .and_then(|n| n.parse::<u16>().ok())
+            {
+                Some(uio) => uio,
+                None => continue,
+            };
+            match DeviceInfo::read(&entry.path(), &self.dev, uio) {
+                Ok(device) => devices.push(device),
+                Err(error) => log::warn!("Failed to read uio{}, {:?}", uio, error),
+            }
+        }
+        devices.sort_by_key(|d| d.uio);
+        Ok(devices)
+    }
+
+    /// Lists all UIO devices matching the selector.
+    pub fn find_all(&self, selector: &Selector) -> Result<Vec<DeviceInfo>, Error> {
+        Ok(self
+            .enumerate()?
+            .into_iter()
+            .filter(|d| d.matches(selector))
+            .collect())
+    }
+
+    /// Finds the single UIO device matching the selector.
+    ///
+    /// Fails with [`Error::NotFound`] listing all devices when nothing matches, or with
+    /// [`Error::Ambiguous`] listing the matching devices when more than one matches.
+    pub fn find(&self, selector: &Selector) -> Result<DeviceInfo, Error> {
+        let devices = self.enumerate()?;
+        let mut matching: Vec<DeviceInfo> = devices
+            .iter()
+            .filter(|d| d.matches(selector))
+            .cloned()
+            .collect();
+        match matching.len() {
+            1 => Ok(matching.remove(0)),
+            0 => Err(Error::NotFound(
+                devices.iter().map(|d| d.to_string()).collect(),
+            )),
+            _ => Err(Error::Ambiguous(
+                matching.iter().map(|d| d.to_string()).collect(),
+            )),
+        }
+    }
+
+    /// Finds and opens the single UIO device matching the selector.
+    pub fn open(&self, selector: &Selector) -> Result<uio_rs::Device, Error> {
+        self.find(selector)?.open()
+    }
+
+    /// Waits until a single UIO device matches each selector, such as after applying a device
+    /// tree overlay, returns the devices in selector order.
+    ///
+    /// The device node of each device has to exist and open for reading and writing as well,
+    /// udev creates it and sets its permissions after the device appears in sysfs.
+    ///
+    /// Fails with [`Error::Timeout`] when a device is still missing after `timeout`, or with
+    /// [`Error::Ambiguous`] when more than one device matches a selector.
+    pub fn wait_for(
+        &self,
+        selectors: &[Selector],
+        timeout: Duration,
+    ) -> Result<Vec<DeviceInfo>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut devices = Vec::with_capacity(selectors.len());
+            for selector in selectors {
+                match self.find(selector) {
+                    Ok(device) if is_openable(&device.path)? => devices.push(device),
+                    Ok(_) => break,
+                    // The class directory only exists once a UIO device is registered
+                    Err(Error::NotFound(_)) | Err(Error::Io(std::io::ErrorKind::NotFound)) => {
+                        break;
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            if devices.len() == selectors.len() {
+                return Ok(devices);
+            }
+            if Instant::now() >= deadline {
+                log::warn!("missing {:?}", selectors[devices.len()]);
+                return Err(Error::Timeout);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Checks if a device node can be opened, it is missing or inaccessible until udev handled it.
+fn is_openable(path: &Path) -> Result<bool, Error> {
+    let opened = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path);
+    match opened.map_err(|error| error.kind()) {
+        Ok(_) => Ok(true),
+        Err(std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied) => Ok(false),
+        Err(kind) => Err(Error::Io(kind)),
+    }
+}
+
+fn read_trimmed(path: &Path) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_hex(path: &Path) -> Result<u64, Error> {
+    let text = read_trimmed(path)?;
+    let hex = text.strip_prefix("0x").unwrap_or(&text);
+    u64::from_str_radix(hex, 16).map_err(|_| Error::Io(std::io::ErrorKind::InvalidData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeRoot, assert_enumerated, fake_root};
+    use std::fs;
+
+    /// Adds a UIO device and its device node to a fake sysfs root, with a device tree node when
+    /// `compatible` is given.
+    fn add_device(
+        root: &FakeRoot,
+        uio: u16,
+        name: &str,
+        maps: &[(u64, u64)],
+        compatible: Option<&str>,
+    ) {
+        let device = PathBuf::from("class").join(format!("uio{}", uio));
+        root.write(device.join("name"), format!("{}\n", name));
+        for (index, (address, size)) in maps.iter().enumerate() {
+            let map = device.join("maps").join(format!("map{}", index));
+            root.write(map.join("addr"), format!("0x{:08x}\n", address));
+            root.write(map.join("size"), format!("0x{:08x}\n", size));
+            root.write(map.join("offset"), "0x0\n");
+        }
+        if let Some(compatible) = compatible {
+            let node = PathBuf::from("base")
+                .join("amba_pl")
+                .join(format!("{}@{:x}", name, maps[0].0));
+            root.write(node.join("compatible"), format!("{}\0", compatible));
+            root.symlink(root.join(node), device.join("device").join("of_node"));
+        }
+        root.write(PathBuf::from("dev").join(format!("uio{}", uio)), "");
+    }
+
+    fn discovery(root: &FakeRoot) -> Discovery {
+        Discovery::with_dev_root(root.join("class"), root.join("dev"))
+    }
+
+    fn fake_sysfs() -> (FakeRoot, Discovery) {
+        fake_root(|root| {
+            add_device(
+                root,
+                1,
+                "axi_fifo_mm_s",
+                &[(0xa000_0000, 0x1_0000), (0xa001_0000, 0x1000)],
+                Some("xlnx,axi-fifo-mm-s-4.3"),
+            );
+            add_device(
+                root,
+                0,
+                "gpio",
+                &[(0xa002_0000, 0x1_0000)],
+                Some("xlnx,xps-gpio-1.00.a"),
+            );
+            add_device(root, 10, "gpio", &[(0xa003_0000, 0x1_0000)], None);
+            root.create_dir("class/not_uio");
+            discovery(root)
+        })
+    }
+
+    #[test]
+    fn parse_selector() {
+        assert_eq!(
+            Selector::parse("/dev/uio3"),
+            Selector::Path(PathBuf::from("/dev/uio3"))
+        );
+        assert_eq!(
+            Selector::parse("/amba_pl/axi_fifo_mm_s@a0000000"),
+            Selector::DevicePath(PathBuf::from("/amba_pl/axi_fifo_mm_s@a0000000"))
+        );
+        assert_eq!(
+            Selector::parse("0xa0000000"),
+            Selector::Address(0xa000_0000)
+        );
+        assert_eq!(Selector::parse("12"), Selector::Number(12));
+        assert_eq!(
+            Selector::parse("xlnx,axi-fifo-mm-s-4.3"),
+            Selector::Compatible("xlnx,axi-fifo-mm-s-4.3".to_string())
+        );
+        assert_eq!(
+            Selector::parse("axi_fifo_mm_s"),
+            Selector::Name("axi_fifo_mm_s".to_string())
+        );
+        // Not hexadecimal, falls through to a name
+        assert_eq!(Selector::parse("0xzz"), Selector::Name("0xzz".to_string()));
+    }
+
+    #[test]
+    fn enumerate_sorted() {
+        let (root, discovery) = fake_sysfs();
+        assert_enumerated(
+            discovery.enumerate(),
+            |d| format!("uio{}", d.uio()),
+            &["uio0", "uio1", "uio10"],
+        );
+        let devices = discovery.enumerate().unwrap();
+        let fifo = &devices[1];
+        assert_eq!(fifo.name(), "axi_fifo_mm_s");
+        assert_eq!(fifo.path(), root.join("dev/uio1"));
+        let default = Discovery::new(root.join("class")).enumerate().unwrap();
+        assert_eq!(default[1].path(), PathBuf::from("/dev/uio1"));
+        assert_eq!(fifo.compatible(), ["xlnx,axi-fifo-mm-s-4.3"]);
+        assert_eq!(
+            fifo.maps(),
+            [
+                MapInfo {
+                    name: None,
+                    address: 0xa000_0000,
+                    size: 0x1_0000,
+                    offset: 0,
+                },
+                MapInfo {
+                    name: None,
+                    address: 0xa001_0000,
+                    size: 0x1000,
+                    offset: 0,
+                },
+            ]
+        );
+        assert!(devices[2].of_node().is_none());
+    }
+
+    #[test]
+    fn find_by_selector() {
+        let (root, discovery) = fake_sysfs();
+        let find = |text: &str| discovery.find(&Selector::parse(text)).map(|d| d.uio());
+        assert_eq!(find("1"), Ok(1));
+        assert_eq!(find("axi_fifo_mm_s"), Ok(1));
+        assert_eq!(find("xlnx,xps-gpio-1.00.a"), Ok(0));
+        assert_eq!(find("0xa0010000"), Ok(1));
+        assert_eq!(find("0xa0030000"), Ok(10));
+        let path = Selector::Path(root.join("dev/uio10"));
+        assert_eq!(discovery.find(&path).map(|d| d.uio()), Ok(10));
+        // Links to the device node, as created by udev rules, match the device
+        root.symlink(root.join("dev/uio10"), "dev/gpio");
+        let link = Selector::Path(root.join("dev/gpio"));
+        assert_eq!(discovery.find(&link).map(|d| d.uio()), Ok(10));
+        assert_eq!(find("/amba_pl/axi_fifo_mm_s@a0000000"), Ok(1));
+        let relative = Selector::DevicePath(PathBuf::from("amba_pl/gpio@a0020000"));
+        assert_eq!(discovery.find(&relative).map(|d| d.uio()), Ok(0));
+    }
+
+    #[test]
+    fn find_not_found_or_ambiguous() {
+        let (_root, discovery) = fake_sysfs();
+        match discovery.find(&Selector::Name("missing".to_string())) {
+            Err(Error::NotFound(candidates)) => assert_eq!(candidates.len(), 3),
+            other => panic!("unexpected {:?}", other),
+        }
+        match discovery.find(&Selector::Name("gpio".to_string())) {
+            Err(Error::Ambiguous(candidates)) => {
+                assert_eq!(candidates.len(), 2);
+                assert!(candidates[0].starts_with("uio0 gpio"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let all = discovery
+            .find_all(&Selector::Name("gpio".to_string()))
+            .unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(
+            discovery
+                .find_all(&Selector::DevicePath(PathBuf::from("/")))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn missing_root() {
+        let root = FakeRoot::new();
+        let discovery = Discovery::new(root.join("missing"));
+        assert_eq!(
+            discovery.enumerate(),
+            Err(Error::Io(std::io::ErrorKind::NotFound))
+        );
+    }
+
+    #[test]
+    fn wait_for_present() {
+        let (_root, discovery) = fake_sysfs();
+        let selectors = [
+            Selector::Address(0xa002_0000),
+            Selector::Name("axi_fifo_mm_s".to_string()),
+        ];
+        let devices = discovery
+            .wait_for(&selectors, Duration::from_secs(5))
+            .unwrap();
+        let numbers: Vec<_> = devices.iter().map(|d| d.uio()).collect();
+        assert_eq!(numbers, [0, 1]);
+    }
+
+    #[test]
+    fn wait_for_timeout() {
+        let (_root, discovery) = fake_sysfs();
+        let selectors = [
+            Selector::Name("axi_fifo_mm_s".to_string()),
+            Selector::Name("late".to_string()),
+        ];
+        let start = Instant::now();
+        assert_eq!(
+            discovery.wait_for(&selectors, Duration::from_millis(50)),
+            Err(Error::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        // The device is in sysfs, but its device node is missing
+        let (root, discovery) = fake_sysfs();
+        fs::remove_file(root.join("dev/uio1")).unwrap();
+        let selectors = [Selector::Name("axi_fifo_mm_s".to_string())];
+        assert_eq!(
+            discovery.wait_for(&selectors, Duration::from_millis(50)),
+            Err(Error::Timeout)
+        );
+        // Ambiguous selectors fail without waiting
+        let selectors = [Selector::Name("gpio".to_string())];
+        assert!(matches!(
+            discovery.wait_for(&selectors, Duration::from_secs(5)),
+            Err(Error::Ambiguous(_))
+        ));
+    }
+
+    #[test]
+    fn wait_for_late_device() {
+        let root = FakeRoot::new();
+        let discovery = discovery(&root);
+        let class = root.join("class");
+        let dev = root.join("dev");
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            // Move the complete device into place, as the class directory appears with it
+            let staging = FakeRoot::new();
+            add_device(&staging, 5, "late", &[(0xa004_0000, 0x1000)], None);
+            fs::rename(staging.join("class"), class).unwrap();
+            // The device node appears after the sysfs entry
+            std::thread::sleep(Duration::from_millis(50));
+            fs::rename(staging.join("dev"), dev).unwrap();
+        });
+        let selectors = [Selector::Name("late".to_string())];
+        let devices = discovery
+            .wait_for(&selectors, Duration::from_secs(5))
+            .unwrap();
+        thread.join().unwrap();
+        assert_eq!(devices[0].uio(), 5);
+        assert_eq!(devices[0].maps()[0].address, 0xa004_0000);
+    }
+}
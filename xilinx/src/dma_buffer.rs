@@ -0,0 +1,326 @@
+use crate::axi_dma::Segment;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use crate::mmio::Mmio;
+use std::fs::File;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+/// sysfs classes of the u-dma-buf driver, current and older names
+const U_DMA_BUF_CLASSES: [&str; 2] = ["/sys/class/u-dma-buf", "/sys/class/udmabuf"];
+/// Kernel udmabuf device
+const DEV_UDMABUF: &str = "/dev/udmabuf";
+
+/// `_IOW('u', 0x42, struct udmabuf_create)`
+const UDMABUF_CREATE: libc::c_ulong = 0x4018_7542;
+/// `UDMABUF_FLAGS_CLOEXEC`
+const UDMABUF_FLAGS_CLOEXEC: u32 = 0x01;
+/// `_IOW('b', 0, struct dma_buf_sync)`
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x4008_6200;
+const DMA_BUF_SYNC_READ: u64 = 1;
+const DMA_BUF_SYNC_WRITE: u64 = 2;
+const DMA_BUF_SYNC_START: u64 = 0;
+const DMA_BUF_SYNC_END: u64 = 4;
+
+#[repr(C)]
+struct UdmabufCreate {
+    memfd: u32,
+    flags: u32,
+    offset: u64,
+    size: u64,
+}
+
+#[repr(C)]
+struct DmaBufSync {
+    flags: u64,
+}
+
+/// Direction of a DMA transfer, used for cache maintenance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// The device both reads and writes the buffer
+    Bidirectional,
+    /// The device reads the buffer
+    ToDevice,
+    /// The device writes the buffer
+    FromDevice,
+}
+
+impl DmaDirection {
+    fn u_dma_buf_value(&self) -> u32 {
+        match self {
+            DmaDirection::Bidirectional => 0,
+            DmaDirection::ToDevice => 1,
+            DmaDirection::FromDevice => 2,
+        }
+    }
+
+    fn dma_buf_flags(&self) -> u64 {
+        match self {
+            DmaDirection::Bidirectional => DMA_BUF_SYNC_READ | DMA_BUF_SYNC_WRITE,
+            DmaDirection::ToDevice => DMA_BUF_SYNC_WRITE,
+            DmaDirection::FromDevice => DMA_BUF_SYNC_READ,
+        }
+    }
+}
+
+/// Cache maintenance of the buffer backend
+enum Sync {
+    /// Uncached or coherent memory
+    None,
+    /// u-dma-buf, the sysfs directory of the buffer
+    UDmaBuf(PathBuf),
+    /// Kernel udmabuf, the dma-buf and the backing memfd
+    DmaBuf { dma_buf: File, _memfd: File },
+}
+
+/// Physically contiguous memory usable by DMA capable devices
+pub struct DmaBuffer {
+    memory: Mmio,
+    address: Option<u64>,
+    sync: Sync,
+}
+
+impl DmaBuffer {
+    /// Opens a u-dma-buf buffer by name, such as `udmabuf0`.
+    ///
+    /// The bus address and size are read from sysfs. When `cached` is false the buffer is
+    /// opened with `O_SYNC` and the CPU mapping is uncached.
+    pub fn try_from_u_dma_buf(name: &str, cached: bool) -> Result<DmaBuffer, Error> {
+        let class = U_DMA_BUF_CLASSES
+            .iter()
+            .map(|class| Path::new(class).join(name))
+            .find(|path| path.is_dir())
+            .ok_or_else(|| Error::NotFound(Self::u_dma_buf_names()))?;
+        let address = read_number(&class.join("phys_addr"))?;
+        let size = read_number(&class.join("size"))? as usize;
+        let flags = if cached { 0 } else { libc::O_SYNC };
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(flags)
+            .open(Path::new("/dev").join(name))?;
+        let memory = Mmio::map_fd(file.as_raw_fd(), address, 0, 0, size)?;
+        let sync = if cached {
+            Sync::UDmaBuf(class)
+        } else {
+            Sync::None
+        };
+        Ok(DmaBuffer {
+            memory,
+            address: Some(address),
+            sync,
+        })
+    }
+
+    /// Lists the names of the available u-dma-buf buffers.
+    pub fn u_dma_buf_names() -> Vec<String> {
+        let mut names: Vec<String> = U_DMA_BUF_CLASSES
+            .iter()
+            .filter_map(|class| std::fs::read_dir(class).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Uses memory map `index` of a UIO device, such as a `reserved-memory` region bound to UIO.
+    ///
+    /// The UIO mapping is uncached, no cache maintenance is required.
+    pub fn try_from_uio(device: &DeviceInfo, index: usize) -> Result<DmaBuffer, Error> {
+        let memory = Mmio::try_from_device(device, index)?;
+        let address = memory.address();
+        Ok(DmaBuffer {
+            memory,
+            address: Some(address),
+            sync: Sync::None,
+        })
+    }
+
+    /// Uses an uncached memory region, such as a reserved region mapped through `/dev/mem`.
+    pub fn from_mmio(memory: Mmio) -> DmaBuffer {
+        let address = memory.address();
+        DmaBuffer {
+            memory,
+            address: Some(address),
+            sync: Sync::None,
+        }
+    }
+
+    /// Allocates `size` bytes through the kernel `udmabuf` driver.
+    ///
+    /// The memory is backed by a memfd and exported as a dma-buf, see
+    /// [`DmaBuffer::dma_buf_fd`]. The pages are not physically contiguous, so the buffer has no
+    /// bus address and is only usable by devices behind an IOMMU or by kernel drivers importing
+    /// the dma-buf.
+    pub fn try_from_udmabuf(size: usize) -> Result<DmaBuffer, Error> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let size = size.div_ceil(page_size) * page_size;
+        let name = c"plrs-dma-buffer";
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_ALLOW_SEALING) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let memfd = unsafe { File::from_raw_fd(fd) };
+        memfd.set_len(size as u64)?;
+        if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, libc::F_SEAL_SHRINK) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(DEV_UDMABUF)?;
+        let create = UdmabufCreate {
+            memfd: fd as u32,
+            flags: UDMABUF_FLAGS_CLOEXEC,
+            offset: 0,
+            size: size as u64,
+        };
+        let dma_buf = unsafe { libc::ioctl(device.as_raw_fd(), UDMABUF_CREATE, &create) };
+        if dma_buf < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let dma_buf = unsafe { File::from_raw_fd(dma_buf) };
+        let memory = Mmio::map_fd(fd, 0, 0, 0, size)?;
+        Ok(DmaBuffer {
+            memory,
+            address: None,
+            sync: Sync::DmaBuf {
+                dma_buf,
+                _memfd: memfd,
+            },
+        })
+    }
+
+    /// Returns the bus address of the buffer, if known.
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn size(&self) -> usize {
+        self.memory.size()
+    }
+
+    /// Returns the dma-buf file descriptor of a kernel udmabuf buffer.
+    pub fn dma_buf_fd(&self) -> Option<RawFd> {
+        match self.sync {
+            Sync::DmaBuf { ref dma_buf, .. } => Some(dma_buf.as_raw_fd()),
+            _ => None,
+        }
+    }
+
+    /// Returns the CPU mapping of the buffer.
+    pub fn mmio(&self) -> &Mmio {
+        &self.memory
+    }
+
+    /// Returns the mutable CPU mapping of the buffer.
+    pub fn mmio_mut(&mut self) -> &mut Mmio {
+        &mut self.memory
+    }
+
+    /// Returns a DMA segment of `length` bytes at `offset` into the buffer.
+    pub fn segment(&self, offset: usize, length: usize) -> Result<Segment, Error> {
+        let address = self.address.ok_or(Error::Unsupported)?;
+        if offset.checked_add(length).ok_or(Error::OutOfRange)? > self.size() {
+            return Err(Error::OutOfRange);
+        }
+        Ok(Segment::new(address + offset as u64, length))
+    }
+
+    /// Copies bytes at `offset` into `data`.
+    pub fn read(&self, offset: usize, data: &mut [u8]) -> Result<(), Error> {
+        self.memory.read_bytes(offset, data)
+    }
+
+    /// Copies `data` into the buffer at `offset`.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        self.memory.write_bytes(offset, data)
+    }
+
+    /// Hands the range over to the CPU after the device has accessed it.
+    ///
+    /// Does nothing for uncached buffers.
+    pub fn sync_for_cpu(
+        &self,
+        offset: usize,
+        size: usize,
+        direction: DmaDirection,
+    ) -> Result<(), Error> {
+        self.sync(offset, size, direction, true)
+    }
+
+    /// Hands the range over to the device after the CPU has accessed it.
+    ///
+    /// Does nothing for uncached buffers.
+    pub fn sync_for_device(
+        &self,
+        offset: usize,
+        size: usize,
+        direction: DmaDirection,
+    ) -> Result<(), Error> {
+        self.sync(offset, size, direction, false)
+    }
+
+    fn sync(
+        &self,
+        offset: usize,
+        size: usize,
+        direction: DmaDirection,
+        for_cpu: bool,
+    ) -> Result<(), Error> {
+        if offset.checked_add(size).ok_or(Error::OutOfRange)? > self.size() {
+            return Err(Error::OutOfRange);
+        }
+        match self.sync {
+            Sync::None => Ok(()),
+            Sync::UDmaBuf(ref class) => {
+                std::fs::write(class.join("sync_offset"), offset.to_string())?;
+                std::fs::write(class.join("sync_size"), size.to_string())?;
+                std::fs::write(
+                    class.join("sync_direction"),
+                    direction.u_dma_buf_value().to_string(),
+                )?;
+                let control = if for_cpu {
+                    "sync_for_cpu"
+                } else {
+                    "sync_for_device"
+                };
+                std::fs::write(class.join(control), "1")?;
+                Ok(())
+            }
+            Sync::DmaBuf { ref dma_buf, .. } => {
+                // The dma-buf interface syncs the whole buffer
+                let stage = if for_cpu {
+                    DMA_BUF_SYNC_START
+                } else {
+                    DMA_BUF_SYNC_END
+                };
+                let sync = DmaBufSync {
+                    flags: stage | direction.dma_buf_flags(),
+                };
+                let result = unsafe { libc::ioctl(dma_buf.as_raw_fd(), DMA_BUF_IOCTL_SYNC, &sync) };
+                if result < 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads a decimal or `0x` prefixed hexadecimal number from a sysfs attribute.
+fn read_number(path: &Path) -> Result<u64, Error> {
+    let text = std::fs::read_to_string(path)?;
+    let text = text.trim();
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+    value.map_err(|_| Error::Io(std::io::ErrorKind::InvalidData))
+}
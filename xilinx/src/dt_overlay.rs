@@ -0,0 +1,314 @@
+use crate::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Default location of the device tree overlays in configfs
+pub const OVERLAY_CONFIGFS_ROOT: &str = "/sys/kernel/config/device-tree/overlays";
+
+/// Status of a device tree overlay, as reported by its `status` attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverlayStatus {
+    /// The overlay is applied to the live device tree
+    Applied,
+    /// No overlay was written, or applying it failed
+    Unapplied,
+    /// Unknown status
+    Unknown,
+}
+
+impl OverlayStatus {
+    /// Parses the text of the `status` attribute.
+    pub fn parse(text: &str) -> OverlayStatus {
+        match text.trim() {
+            "applied" => OverlayStatus::Applied,
+            "unapplied" => OverlayStatus::Unapplied,
+            _ => OverlayStatus::Unknown,
+        }
+    }
+}
+
+/// Device tree overlay in configfs, such as `/sys/kernel/config/device-tree/overlays/fifo`
+///
+/// The overlay is applied by writing either a firmware file name to `path` or the `.dtbo` blob
+/// to `dtbo`, and removed with its directory.
+#[derive(Clone, Debug)]
+pub struct DeviceTreeOverlay {
+    path: PathBuf,
+}
+
+impl DeviceTreeOverlay {
+    /// Creates an overlay from its configfs directory.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        DeviceTreeOverlay {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the configfs directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the overlay name.
+    pub fn name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Returns the status.
+    pub fn status(&self) -> Result<OverlayStatus, Error> {
+        let text = std::fs::read_to_string(self.path.join("status"))?;
+        Ok(OverlayStatus::parse(&text))
+    }
+
+    /// Applies a `.dtbo` file in the firmware search directory, given by its name relative to
+    /// that directory.
+    ///
+    /// Fails with [`Error::Configuration`] if the overlay is not applied afterwards.
+    pub fn apply_firmware(&self, name: &str) -> Result<(), Error> {
+        log::debug!("overlay {} apply {}", self.name(), name);
+        let written = std::fs::write(self.path.join("path"), name);
+        self.check_applied(written)
+    }
+
+    /// Applies a `.dtbo` blob.
+    ///
+    /// Fails with [`Error::Configuration`] if the overlay is not applied afterwards.
+    pub fn apply_blob(&self, dtbo: &[u8]) -> Result<(), Error> {
+        log::debug!("overlay {} apply {} bytes", self.name(), dtbo.len());
+        let written = std::fs::write(self.path.join("dtbo"), dtbo);
+        self.check_applied(written)
+    }
+
+    /// Reads a `.dtbo` file and applies it as a blob, the file can be anywhere.
+    pub fn apply_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.apply_blob(&std::fs::read(path)?)
+    }
+
+    fn check_applied(&self, written: std::io::Result<()>) -> Result<(), Error> {
+        let status = self.status()?;
+        match written {
+            // The kernel rejects an overlay that fails to apply with an error on the write
+            Err(error) => {
+                log::warn!("overlay {} failed to apply, {}", self.name(), error);
+                Err(if status == OverlayStatus::Applied {
+                    error.into()
+                } else {
+                    Error::Configuration
+                })
+            }
+            Ok(()) if status != OverlayStatus::Applied => {
+                log::warn!("overlay {} not applied, {:?}", self.name(), status);
+                Err(Error::Configuration)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Removes the overlay from the live device tree and configfs.
+    pub fn remove(self) -> Result<(), Error> {
+        log::debug!("overlay {} remove", self.name());
+        std::fs::remove_dir(&self.path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceTreeOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if let Ok(status) = self.status() {
+            write!(f, " {:?}", status)?;
+        }
+        Ok(())
+    }
+}
+
+/// Manages device tree overlays through configfs
+#[derive(Clone, Debug)]
+pub struct DeviceTreeOverlays {
+    root: PathBuf,
+}
+
+impl Default for DeviceTreeOverlays {
+    fn default() -> Self {
+        DeviceTreeOverlays::new(OVERLAY_CONFIGFS_ROOT)
+    }
+}
+
+impl DeviceTreeOverlays {
+    /// Creates a manager rooted at the given configfs overlays directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        DeviceTreeOverlays {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Lists all overlays, ordered by name.
+    pub fn enumerate(&self) -> Result<Vec<DeviceTreeOverlay>, Error> {
+        let mut overlays = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                overlays.push(DeviceTreeOverlay::new(entry.path()));
+            }
+        }
+        overlays.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok(overlays)
+    }
+
+    /// Finds the overlay with the name.
+    ///
+    /// Fails with [`Error::NotFound`] listing all overlays when nothing matches.
+    pub fn find(&self, name: &str) -> Result<DeviceTreeOverlay, Error> {
+        let overlays = self.enumerate()?;
+        match overlays.iter().find(|overlay| overlay.name() == name) {
+            Some(overlay) => Ok(overlay.clone()),
+            None => Err(Error::NotFound(
+                overlays.iter().map(|o| o.to_string()).collect(),
+            )),
+        }
+    }
+
+    /// Creates an empty overlay.
+    ///
+    /// Fails with [`Error::Busy`] if an overlay with the name exists.
+    pub fn create(&self, name: &str) -> Result<DeviceTreeOverlay, Error> {
+        if name.is_empty() || name.contains('/') {
+            return Err(Error::InvalidArgument);
+        }
+        let path = self.root.join(name);
+        match std::fs::create_dir(&path) {
+            Ok(()) => Ok(DeviceTreeOverlay::new(path)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Err(Error::Busy),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Creates an overlay and applies a `.dtbo` file, the overlay is removed again if it fails
+    /// to apply.
+    pub fn apply_file<P: AsRef<Path>>(
+        &self,
+        name: &str,
+        path: P,
+    ) -> Result<DeviceTreeOverlay, Error> {
+        let dtbo = std::fs::read(path)?;
+        self.apply_blob(name, &dtbo)
+    }
+
+    /// Creates an overlay and applies a `.dtbo` blob, the overlay is removed again if it fails
+    /// to apply.
+    pub fn apply_blob(&self, name: &str, dtbo: &[u8]) -> Result<DeviceTreeOverlay, Error> {
+        let overlay = self.create(name)?;
+        match overlay.apply_blob(dtbo) {
+            Ok(()) => Ok(overlay),
+            Err(error) => {
+                if let Err(remove_error) = overlay.remove() {
+                    log::warn!("failed to remove overlay {}, {:?}", name, remove_error);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Removes the overlay with the name.
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        self.find(name)?.remove()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeRoot, assert_enumerated, fake_root};
+    use std::fs;
+
+    fn fake_configfs() -> (FakeRoot, DeviceTreeOverlays) {
+        fake_root(|root| DeviceTreeOverlays::new(root.path()))
+    }
+
+    #[test]
+    fn parse_status() {
+        assert_eq!(OverlayStatus::parse("applied\n"), OverlayStatus::Applied);
+        assert_eq!(OverlayStatus::parse("unapplied"), OverlayStatus::Unapplied);
+        assert_eq!(OverlayStatus::parse(""), OverlayStatus::Unknown);
+    }
+
+    #[test]
+    fn create_enumerate_remove() {
+        let (root, overlays) = fake_configfs();
+        let fifo = overlays.create("fifo").unwrap();
+        assert_eq!(fifo.name(), "fifo");
+        assert_eq!(fifo.path(), root.join("fifo"));
+        assert!(fifo.path().is_dir());
+        overlays.create("base").unwrap();
+        root.write("not_an_overlay", "");
+        assert_eq!(
+            overlays.create("fifo").map(|o| o.to_string()),
+            Err(Error::Busy)
+        );
+        assert_eq!(
+            overlays.create("").map(|o| o.to_string()),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            overlays.create("a/b").map(|o| o.to_string()),
+            Err(Error::InvalidArgument)
+        );
+        assert_enumerated(
+            overlays.enumerate(),
+            |o| o.name().to_string(),
+            &["base", "fifo"],
+        );
+        assert_eq!(overlays.find("fifo").unwrap().path(), fifo.path());
+        overlays.remove("fifo").unwrap();
+        assert!(!root.join("fifo").exists());
+        match overlays.find("fifo") {
+            Err(Error::NotFound(candidates)) => assert_eq!(candidates, ["base"]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(overlays.remove("fifo"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn apply_blob() {
+        let (root, overlays) = fake_configfs();
+        let overlay = overlays.create("fifo").unwrap();
+        // configfs reports the status once the blob is written, the fake reports it up front
+        root.write("fifo/status", "applied\n");
+        overlay.apply_blob(&[0xd0, 0x0d, 0xfe, 0xed]).unwrap();
+        assert_eq!(
+            fs::read(root.join("fifo/dtbo")).unwrap(),
+            [0xd0, 0x0d, 0xfe, 0xed]
+        );
+        assert_eq!(overlay.status().unwrap(), OverlayStatus::Applied);
+        assert_eq!(overlay.to_string(), "fifo Applied");
+        overlay.apply_firmware("fifo.dtbo").unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("fifo/path")).unwrap(),
+            "fifo.dtbo"
+        );
+    }
+
+    #[test]
+    fn check_applied() {
+        let (root, overlays) = fake_configfs();
+        let overlay = overlays.create("fifo").unwrap();
+        // Without a status the overlay state is unknown
+        assert_eq!(
+            overlay.apply_blob(&[0]),
+            Err(Error::Io(std::io::ErrorKind::NotFound))
+        );
+        // Written, but not applied
+        root.write("fifo/status", "unapplied\n");
+        assert_eq!(overlay.apply_blob(&[0]), Err(Error::Configuration));
+        // The kernel rejects the blob on the write
+        fs::remove_file(root.join("fifo/dtbo")).unwrap();
+        fs::create_dir(root.join("fifo/dtbo")).unwrap();
+        assert_eq!(overlay.apply_blob(&[0]), Err(Error::Configuration));
+        // The write failed for another reason than the overlay
+        root.write("fifo/status", "applied\n");
+        assert!(matches!(overlay.apply_blob(&[0]), Err(Error::Io(_))));
+    }
+}
@@ -1,8 +1,5 @@
-use uio_rs;
 /// Crate errors
-
-/// Error
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Error {
     /// No memory map found
     NoMemoryMap,
@@ -16,6 +13,48 @@ pub enum Error {
     OverRun,
     /// The length register does not match the number of bytes written
     LengthMismatch,
+    /// Access outside of the mapped region
+    OutOfRange,
+    /// Access not aligned to its width
+    Misaligned,
+    /// Invalid argument, such as a too long transfer
+    InvalidArgument,
+    /// The operation is not supported by the device configuration
+    Unsupported,
+    /// The device is busy with an earlier operation
+    Busy,
+    /// The operation did not complete in time
+    Timeout,
+    /// DMA internal error
+    DmaInternal,
+    /// DMA slave error, the addressed slave responded with an error
+    DmaSlave,
+    /// DMA decode error, no slave at the address
+    DmaDecode,
+    /// Scatter gather internal error, such as a completed descriptor
+    SgInternal,
+    /// Scatter gather slave error while fetching or updating a descriptor
+    SgSlave,
+    /// Scatter gather decode error, no slave at the descriptor address
+    SgDecode,
+    /// Video frame size mismatch, start of frame or end of line early or late
+    FrameSize,
+    /// Serial framing error, missing stop bit
+    Framing,
+    /// Serial parity error
+    Parity,
+    /// Bus arbitration lost to another controller
+    ArbitrationLost,
+    /// The addressed device did not acknowledge the address or data
+    NoAcknowledge,
+    /// SPI mode fault, another controller drove the slave select
+    ModeFault,
+    /// Configuration error reported by the device, such as a bitstream CRC error
+    Configuration,
+    /// No device matched, contains the available candidates
+    NotFound(Vec<String>),
+    /// Several devices matched, contains the matching candidates
+    Ambiguous(Vec<String>),
     /// Underlying IO error
     Io(std::io::ErrorKind),
     /// Underlying UIO error
@@ -33,3 +72,16 @@ impl From<uio_rs::Error> for Error {
         Error::Uio(error)
     }
 }
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(kind) => kind.into(),
+            Error::Timeout => std::io::ErrorKind::TimedOut.into(),
+            Error::Framing | Error::Parity | Error::OverRun => {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", error))
+            }
+            error => std::io::Error::other(format!("{:?}", error)),
+        }
+    }
+}
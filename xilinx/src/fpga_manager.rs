@@ -0,0 +1,502 @@
+use crate::bitstream::Bitstream;
+use crate::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Default location of the FPGA manager class in sysfs
+pub const FPGA_MANAGER_CLASS_ROOT: &str = "/sys/class/fpga_manager";
+
+/// Default firmware search directory
+pub const FIRMWARE_ROOT: &str = "/lib/firmware";
+
+/// State of an FPGA manager, as reported by its `state` attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FpgaState {
+    /// Unknown state
+    Unknown,
+    /// The FPGA is powered off
+    PowerOff,
+    /// The FPGA is powered up
+    PowerUp,
+    /// The FPGA is in reset
+    Reset,
+    /// Loading the bitstream firmware
+    FirmwareRequest,
+    /// Loading the bitstream firmware failed
+    FirmwareRequestError,
+    /// Preparing to write the bitstream
+    WriteInit,
+    /// Preparing to write the bitstream failed
+    WriteInitError,
+    /// Writing the bitstream
+    Write,
+    /// Writing the bitstream failed
+    WriteError,
+    /// Finishing the write
+    WriteComplete,
+    /// Finishing the write failed
+    WriteCompleteError,
+    /// The FPGA is programmed and operating
+    Operating,
+}
+
+impl FpgaState {
+    /// Parses the text of the `state` attribute.
+    pub fn parse(text: &str) -> FpgaState {
+        match text.trim() {
+            "power off" => FpgaState::PowerOff,
+            "power up" => FpgaState::PowerUp,
+            "reset" => FpgaState::Reset,
+            "firmware request" => FpgaState::FirmwareRequest,
+            "firmware request error" => FpgaState::FirmwareRequestError,
+            "write init" => FpgaState::WriteInit,
+            "write init error" => FpgaState::WriteInitError,
+            "write" => FpgaState::Write,
+            "write error" => FpgaState::WriteError,
+            "write complete" => FpgaState::WriteComplete,
+            "write complete error" => FpgaState::WriteCompleteError,
+            "operating" => FpgaState::Operating,
+            _ => FpgaState::Unknown,
+        }
+    }
+
+    /// Checks if the state reports a failed programming step.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            FpgaState::FirmwareRequestError
+                | FpgaState::WriteInitError
+                | FpgaState::WriteError
+                | FpgaState::WriteCompleteError
+        )
+    }
+}
+
+/// Flag of the `flags` attribute, partial reconfiguration
+pub const FPGA_FLAG_PARTIAL: u32 = 0x01;
+/// Flag of the `flags` attribute, the FPGA is configured externally
+pub const FPGA_FLAG_EXTERNAL_CONFIG: u32 = 0x02;
+/// Flag of the `flags` attribute, the bitstream is encrypted
+pub const FPGA_FLAG_ENCRYPTED: u32 = 0x04;
+/// Flag of the `flags` attribute, the bitstream is bit swapped
+pub const FPGA_FLAG_LSB_FIRST: u32 = 0x08;
+/// Flag of the `flags` attribute, the bitstream is compressed
+pub const FPGA_FLAG_COMPRESSED: u32 = 0x10;
+
+/// FPGA manager in sysfs, such as `/sys/class/fpga_manager/fpga0`
+///
+/// Programs bitstreams through the `firmware` attribute of the Xilinx kernel, which loads the
+/// named file from the firmware search directory.
+#[derive(Clone, Debug)]
+pub struct FpgaManager {
+    path: PathBuf,
+    firmware_root: PathBuf,
+}
+
+impl FpgaManager {
+    /// Creates a manager from its sysfs directory, with the firmware search directory.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(path: P, firmware_root: Q) -> Self {
+        FpgaManager {
+            path: path.as_ref().to_path_buf(),
+            firmware_root: firmware_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the sysfs directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the firmware search directory.
+    pub fn firmware_root(&self) -> &Path {
+        &self.firmware_root
+    }
+
+    /// Returns the device name, such as `fpga0`.
+    pub fn device(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Returns the driver name of the manager.
+    pub fn name(&self) -> Result<String, Error> {
+        read_trimmed(&self.path.join("name"))
+    }
+
+    /// Returns the state.
+    pub fn state(&self) -> Result<FpgaState, Error> {
+        Ok(FpgaState::parse(&read_trimmed(&self.path.join("state"))?))
+    }
+
+    /// Returns the programming flags.
+    pub fn flags(&self) -> Result<u32, Error> {
+        let text = read_trimmed(&self.path.join("flags"))?;
+        let hex = text.strip_prefix("0x").unwrap_or(&text);
+        u32::from_str_radix(hex, 16).map_err(|_| Error::Io(std::io::ErrorKind::InvalidData))
+    }
+
+    /// Sets the programming flags for the next bitstream.
+    pub fn set_flags(&self, flags: u32) -> Result<(), Error> {
+        std::fs::write(self.path.join("flags"), format!("{:x}", flags))?;
+        Ok(())
+    }
+
+    /// Returns the manager status text, empty when the driver does not report a status.
+    pub fn status(&self) -> Result<String, Error> {
+        match read_trimmed(&self.path.join("status")) {
+            Err(Error::Io(std::io::ErrorKind::NotFound)) => Ok(String::new()),
+            result => result,
+        }
+    }
+
+    /// Programs a bitstream file in the firmware search directory, given by its name
+    /// relative to that directory.
+    ///
+    /// Fails with [`Error::Configuration`] if the manager does not reach the operating state.
+    pub fn program_firmware(&self, name: &str, partial: bool) -> Result<(), Error> {
+        let flags = if partial { FPGA_FLAG_PARTIAL } else { 0 };
+        self.set_flags(flags)?;
+        log::debug!("{} program {}, flags {:x}", self.device(), name, flags);
+        let written = std::fs::write(self.path.join("firmware"), name);
+        let state = self.state()?;
+        if let Err(error) = written {
+            log::warn!("{} failed to program {}, {:?}", self.device(), name, state);
+            return Err(if state.is_error() {
+                Error::Configuration
+            } else {
+                error.into()
+            });
+        }
+        if state != FpgaState::Operating {
+            log::warn!(
+                "{} not operating after programming, {:?}",
+                self.device(),
+                state
+            );
+            return Err(Error::Configuration);
+        }
+        Ok(())
+    }
+
+    /// Programs a bitstream file, copying it into the firmware search directory unless it is
+    /// already there.
+    pub fn program<P: AsRef<Path>>(&self, path: P, partial: bool) -> Result<(), Error> {
+        let path = path.as_ref();
+        let name = self.install(path)?;
+        self.program_firmware(&name, partial)
+    }
+
+    /// Programs a parsed bitstream, written into the firmware search directory as `name`.
+    ///
+    /// The configuration words are written byte swapped, the `.bin` layout the Zynq and Zynq
+    /// UltraScale+ managers expect.
+    pub fn program_bitstream(
+        &self,
+        bitstream: &Bitstream,
+        name: &str,
+        partial: bool,
+    ) -> Result<(), Error> {
+        std::fs::write(self.firmware_root.join(name), firmware_data(bitstream))?;
+        self.program_firmware(name, partial)
+    }
+
+    /// Copies the file into the firmware search directory when needed, returns the name
+    /// relative to that directory.
+    fn install(&self, path: &Path) -> Result<String, Error> {
+        let firmware_root = self
+            .firmware_root
+            .canonicalize()
+            .unwrap_or_else(|_| self.firmware_root.clone());
+        let path = path.canonicalize()?;
+        if let Ok(relative) = path.strip_prefix(&firmware_root) {
+            return relative
+                .to_str()
+                .map(str::to_string)
+                .ok_or(Error::InvalidArgument);
+        }
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(Error::InvalidArgument)?;
+        log::debug!("copy {} to {}", path.display(), firmware_root.display());
+        std::fs::copy(&path, firmware_root.join(name))?;
+        Ok(name.to_string())
+    }
+}
+
+impl fmt::Display for FpgaManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.device())?;
+        if let Ok(name) = self.name() {
+            write!(f, " {}", name)?;
+        }
+        if let Ok(state) = self.state() {
+            write!(f, " {:?}", state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds FPGA managers through sysfs
+#[derive(Clone, Debug)]
+pub struct FpgaManagers {
+    root: PathBuf,
+    firmware_root: PathBuf,
+}
+
+impl Default for FpgaManagers {
+    fn default() -> Self {
+        FpgaManagers::new(FPGA_MANAGER_CLASS_ROOT, FIRMWARE_ROOT)
+    }
+}
+
+impl FpgaManagers {
+    /// Creates a lookup rooted at the given FPGA manager class directory, programming from the
+    /// given firmware search directory.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(root: P, firmware_root: Q) -> Self {
+        FpgaManagers {
+            root: root.as_ref().to_path_buf(),
+            firmware_root: firmware_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Lists all FPGA managers, ordered by device number.
+    pub fn enumerate(&self) -> Result<Vec<FpgaManager>, Error> {
+        let mut managers = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let number = match file_name
+                .to_str()
+                .and_then(|n| n.strip_prefix("fpga"))
+                .and_then(|n| n.parse::<u16>().ok())
+            {
+                Some(number) => number,
+                None => continue,
+            };
+            managers.push((number, FpgaManager::new(entry.path(), &self.firmware_root)));
+        }
+        managers.sort_by_key(|(number, _)| *number);
+        Ok(managers.into_iter().map(|(_, manager)| manager).collect())
+    }
+
+    /// Finds the manager with the device name, such as `fpga0`.
+    ///
+    /// Fails with [`Error::NotFound`] listing all managers when nothing matches.
+    pub fn find(&self, device: &str) -> Result<FpgaManager, Error> {
+        let managers = self.enumerate()?;
+        match managers.iter().find(|manager| manager.device() == device) {
+            Some(manager) => Ok(manager.clone()),
+            None => Err(Error::NotFound(
+                managers.iter().map(|m| m.to_string()).collect(),
+            )),
+        }
+    }
+
+    /// Returns the first manager, usually the only one.
+    pub fn first(&self) -> Result<FpgaManager, Error> {
+        let mut managers = self.enumerate()?;
+        if managers.is_empty() {
+            return Err(Error::NotFound(Vec::new()));
+        }
+        Ok(managers.remove(0))
+    }
+}
+
+/// Returns the firmware file contents of a bitstream, the configuration words byte swapped.
+pub(crate) fn firmware_data(bitstream: &Bitstream) -> Vec<u8> {
+    bitstream
+        .data()
+        .chunks_exact(4)
+        .flat_map(|word| [word[3], word[2], word[1], word[0]])
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeRoot, assert_enumerated, fake_root};
+    use std::fs;
+
+    /// Adds an FPGA manager to a fake sysfs root.
+    fn add_manager(root: &FakeRoot, device: &str, state: &str) -> PathBuf {
+        let path = PathBuf::from("class").join(device);
+        root.write(path.join("name"), "Xilinx Zynq FPGA Manager\n");
+        root.write(path.join("state"), format!("{}\n", state));
+        root.write(path.join("flags"), "0\n");
+        root.write(path.join("firmware"), "");
+        root.join(path)
+    }
+
+    fn fake_sysfs() -> (FakeRoot, FpgaManagers) {
+        fake_root(|root| {
+            add_manager(root, "fpga10", "operating");
+            add_manager(root, "fpga2", "power off");
+            add_manager(root, "fpga0", "operating");
+            root.create_dir("class/not_fpga");
+            root.create_dir("firmware");
+            FpgaManagers::new(root.join("class"), root.join("firmware"))
+        })
+    }
+
+    #[test]
+    fn parse_state() {
+        assert_eq!(FpgaState::parse("operating\n"), FpgaState::Operating);
+        assert_eq!(FpgaState::parse("power off"), FpgaState::PowerOff);
+        assert_eq!(
+            FpgaState::parse("write init error"),
+            FpgaState::WriteInitError
+        );
+        assert_eq!(FpgaState::parse("something"), FpgaState::Unknown);
+        assert!(FpgaState::WriteError.is_error());
+        assert!(FpgaState::FirmwareRequestError.is_error());
+        assert!(!FpgaState::Operating.is_error());
+        assert!(!FpgaState::Unknown.is_error());
+    }
+
+    #[test]
+    fn enumerate_sorted() {
+        let (_root, managers) = fake_sysfs();
+        assert_enumerated(
+            managers.enumerate(),
+            |m| m.device().to_string(),
+            &["fpga0", "fpga2", "fpga10"],
+        );
+        assert_eq!(managers.first().unwrap().device(), "fpga0");
+    }
+
+    #[test]
+    fn find() {
+        let (_root, managers) = fake_sysfs();
+        let manager = managers.find("fpga2").unwrap();
+        assert_eq!(manager.name().unwrap(), "Xilinx Zynq FPGA Manager");
+        assert_eq!(manager.state().unwrap(), FpgaState::PowerOff);
+        match managers.find("fpga1") {
+            Err(Error::NotFound(candidates)) => assert_eq!(candidates.len(), 3),
+            other => panic!("unexpected {:?}", other.map(|m| m.to_string())),
+        }
+    }
+
+    #[test]
+    fn no_managers() {
+        let root = FakeRoot::new();
+        let managers = FpgaManagers::new(root.create_dir("class"), root.path());
+        assert_eq!(
+            managers.first().map(|m| m.to_string()),
+            Err(Error::NotFound(Vec::new()))
+        );
+        let managers = FpgaManagers::new(root.join("missing"), root.path());
+        assert_eq!(
+            managers.enumerate().map(|m| m.len()),
+            Err(Error::Io(std::io::ErrorKind::NotFound))
+        );
+    }
+
+    #[test]
+    fn flags_and_status() {
+        let (root, managers) = fake_sysfs();
+        let manager = managers.find("fpga0").unwrap();
+        assert_eq!(manager.flags().unwrap(), 0);
+        manager
+            .set_flags(FPGA_FLAG_PARTIAL | FPGA_FLAG_COMPRESSED)
+            .unwrap();
+        assert_eq!(manager.flags().unwrap(), 0x11);
+        root.write("class/fpga0/flags", "0x18\n");
+        assert_eq!(manager.flags().unwrap(), 0x18);
+        root.write("class/fpga0/flags", "none\n");
+        assert_eq!(
+            manager.flags(),
+            Err(Error::Io(std::io::ErrorKind::InvalidData))
+        );
+        // A missing status attribute is an empty status
+        assert_eq!(manager.status().unwrap(), "");
+        root.write("class/fpga0/status", "reconfig error\n");
+        assert_eq!(manager.status().unwrap(), "reconfig error");
+    }
+
+    #[test]
+    fn program_firmware() {
+        let (root, managers) = fake_sysfs();
+        let manager = managers.find("fpga0").unwrap();
+        manager.program_firmware("design.bin", true).unwrap();
+        let path = root.join("class/fpga0");
+        assert_eq!(
+            fs::read_to_string(path.join("firmware")).unwrap(),
+            "design.bin"
+        );
+        assert_eq!(manager.flags().unwrap(), FPGA_FLAG_PARTIAL);
+        manager.program_firmware("design.bin", false).unwrap();
+        assert_eq!(manager.flags().unwrap(), 0);
+    }
+
+    #[test]
+    fn program_firmware_errors() {
+        let (root, managers) = fake_sysfs();
+        // Written, but the manager did not reach the operating state
+        let manager = managers.find("fpga2").unwrap();
+        assert_eq!(
+            manager.program_firmware("design.bin", false),
+            Err(Error::Configuration)
+        );
+        // A failed write with an error state is a configuration error
+        let path = add_manager(&root, "fpga3", "write init error");
+        fs::remove_file(path.join("firmware")).unwrap();
+        fs::create_dir(path.join("firmware")).unwrap();
+        let manager = managers.find("fpga3").unwrap();
+        assert_eq!(
+            manager.program_firmware("design.bin", false),
+            Err(Error::Configuration)
+        );
+        // Otherwise the write error is returned
+        fs::write(path.join("state"), "operating\n").unwrap();
+        assert!(matches!(
+            manager.program_firmware("design.bin", false),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn program_installs_file() {
+        let (root, managers) = fake_sysfs();
+        let manager = managers.find("fpga0").unwrap();
+        let outside = root.join("design.bin");
+        fs::write(&outside, [1, 2, 3, 4]).unwrap();
+        manager.program(&outside, false).unwrap();
+        let firmware = root.join("firmware");
+        assert_eq!(fs::read(firmware.join("design.bin")).unwrap(), [1, 2, 3, 4]);
+        let path = root.join("class/fpga0/firmware");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "design.bin");
+        // Files in the firmware directory are programmed by their relative name
+        fs::create_dir(firmware.join("designs")).unwrap();
+        fs::write(firmware.join("designs/other.bin"), [5]).unwrap();
+        manager
+            .program(firmware.join("designs/other.bin"), false)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "designs/other.bin");
+    }
+
+    #[test]
+    fn program_bitstream_byte_swapped() {
+        let (root, managers) = fake_sysfs();
+        let manager = managers.find("fpga0").unwrap();
+        let data = [
+            0xff, 0xff, 0xff, 0xff, 0xaa, 0x99, 0x55, 0x66, 0x30, 0x00, 0x80, 0x01,
+        ];
+        let bitstream = Bitstream::parse(&data).unwrap();
+        manager
+            .program_bitstream(&bitstream, "design.bin", false)
+            .unwrap();
+        assert_eq!(
+            fs::read(root.join("firmware/design.bin")).unwrap(),
+            [
+                0xff, 0xff, 0xff, 0xff, 0x66, 0x55, 0x99, 0xaa, 0x01, 0x80, 0x00, 0x30
+            ]
+        );
+    }
+}
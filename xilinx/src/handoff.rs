@@ -0,0 +1,950 @@
+use crate::error::Error;
+use crate::mmio::Region;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// Local file header signature of a zip archive, such as an `.xsa`
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Vendor, library, name and version of an IP core, e.g. `xilinx.com:ip:axi_fifo_mm_s:4.3`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vlnv {
+    /// Vendor, e.g. `xilinx.com`
+    pub vendor: String,
+    /// Library, e.g. `ip`
+    pub library: String,
+    /// Name, e.g. `axi_fifo_mm_s`
+    pub name: String,
+    /// Version, e.g. `4.3`
+    pub version: String,
+}
+
+impl Vlnv {
+    /// Parses a colon separated VLNV string.
+    pub fn parse(text: &str) -> Result<Vlnv, Error> {
+        let mut parts = text.split(':');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(vendor), Some(library), Some(name), Some(version)) if parts.next().is_none() => {
+                Ok(Vlnv {
+                    vendor: vendor.to_string(),
+                    library: library.to_string(),
+                    name: name.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Checks if the VLNV matches `vendor:library:name`, ignoring the version.
+    pub fn matches(&self, vendor_library_name: &str) -> bool {
+        let mut parts = vendor_library_name.split(':');
+        parts.next() == Some(self.vendor.as_str())
+            && parts.next() == Some(self.library.as_str())
+            && parts.next() == Some(self.name.as_str())
+            && parts.next().is_none()
+    }
+}
+
+impl fmt::Display for Vlnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.vendor, self.library, self.name, self.version
+        )
+    }
+}
+
+/// Address range of an IP instance, as seen by a processor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressRange {
+    /// Slave bus interface, e.g. `S_AXI`
+    pub interface: String,
+    /// Address block of the interface, e.g. `Mem0`
+    pub block: String,
+    /// Parameter holding the base address, e.g. `C_BASEADDR`
+    pub base_name: String,
+    /// First address
+    pub base: u64,
+    /// Last address
+    pub high: u64,
+    /// The range is memory rather than registers
+    pub memory: bool,
+}
+
+impl AddressRange {
+    /// Returns the size in bytes.
+    ///
+    /// Ranges read from a handoff end at or after their base and are smaller than the full
+    /// 64-bit address space, otherwise the size saturates.
+    pub fn size(&self) -> u64 {
+        self.high.saturating_sub(self.base).saturating_add(1)
+    }
+
+    /// Returns the physical memory region.
+    pub fn region(&self) -> Region {
+        Region::new(self.base, self.size())
+    }
+}
+
+/// Register of an IP instance, as listed for HLS kernels and other IP with a register
+/// description
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpRegister {
+    /// Slave bus interface, e.g. `s_axi_control`
+    pub interface: String,
+    /// Register name
+    pub name: String,
+    /// Offset from the base address of the interface
+    pub offset: u64,
+    /// Width in bits
+    pub bits: u32,
+    /// Access, e.g. `read-write`
+    pub access: String,
+    /// Description
+    pub description: String,
+}
+
+/// Connection of a port to the port of another instance
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortConnection {
+    /// Instance name
+    pub instance: String,
+    /// Port name
+    pub port: String,
+}
+
+/// Port of an IP instance
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpPort {
+    /// Port name
+    pub name: String,
+    /// Direction, `I`, `O` or `IO`
+    pub direction: String,
+    /// Name of the connected net
+    pub signal: String,
+    /// Signal type, e.g. `INTERRUPT` or `clk`
+    pub kind: String,
+    /// Interrupt sensitivity, e.g. `LEVEL_HIGH`
+    pub sensitivity: String,
+    /// Connected ports of other instances
+    pub connections: Vec<PortConnection>,
+}
+
+impl IpPort {
+    /// Checks if the port is an interrupt.
+    pub fn is_interrupt(&self) -> bool {
+        self.kind.eq_ignore_ascii_case("INTERRUPT")
+    }
+}
+
+/// Interrupt input an interrupt output is wired to, through any concatenation blocks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterruptTarget {
+    /// Instance receiving the interrupt, e.g. a processor or an AXI Interrupt Controller
+    pub instance: String,
+    /// Interrupt input port, e.g. `pl_ps_irq0` or `intr`
+    pub port: String,
+    /// Bit of the input port
+    pub index: u32,
+}
+
+/// IP instance of a hardware design
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpInstance {
+    name: String,
+    vlnv: Vlnv,
+    kind: String,
+    parameters: BTreeMap<String, String>,
+    ranges: Vec<AddressRange>,
+    ports: Vec<IpPort>,
+    registers: Vec<IpRegister>,
+}
+
+impl IpInstance {
+    /// Returns the instance name, e.g. `axi_fifo_mm_s_0`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the VLNV of the IP core.
+    pub fn vlnv(&self) -> &Vlnv {
+        &self.vlnv
+    }
+
+    /// Returns the instance type, e.g. `PERIPHERAL` or `PROCESSOR`.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Returns all configuration parameters.
+    pub fn parameters(&self) -> &BTreeMap<String, String> {
+        &self.parameters
+    }
+
+    /// Returns a configuration parameter, e.g. `C_S_AXIS_TDATA_WIDTH`.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    /// Returns a numeric configuration parameter, decimal or `0x` prefixed hexadecimal.
+    ///
+    /// Fails with [`Error::NotFound`] when the parameter is missing and with
+    /// [`Error::InvalidArgument`] when it is not a number.
+    pub fn parameter_u64(&self, name: &str) -> Result<u64, Error> {
+        let value = self
+            .parameter(name)
+            .ok_or_else(|| Error::NotFound(vec![format!("{} {}", self.name, name)]))?;
+        parse_u64(value).ok_or(Error::InvalidArgument)
+    }
+
+    /// Returns the address ranges, ordered by base address.
+    pub fn ranges(&self) -> &[AddressRange] {
+        &self.ranges
+    }
+
+    /// Returns the address range of the slave bus interface, e.g. `S_AXI`.
+    pub fn range(&self, interface: &str) -> Option<&AddressRange> {
+        self.ranges.iter().find(|r| r.interface == interface)
+    }
+
+    /// Returns the lowest base address.
+    pub fn base_address(&self) -> Option<u64> {
+        self.ranges.first().map(|r| r.base)
+    }
+
+    /// Returns the ports.
+    pub fn ports(&self) -> &[IpPort] {
+        &self.ports
+    }
+
+    /// Returns the port with the name.
+    pub fn port(&self, name: &str) -> Option<&IpPort> {
+        self.ports.iter().find(|p| p.name == name)
+    }
+
+    /// Returns the interrupt ports.
+    pub fn interrupts(&self) -> impl Iterator<Item = &IpPort> {
+        self.ports.iter().filter(|p| p.is_interrupt())
+    }
+
+    /// Returns the registers, empty when the handoff has no register description.
+    pub fn registers(&self) -> &[IpRegister] {
+        &self.registers
+    }
+}
+
+impl fmt::Display for IpInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.vlnv)?;
+        if let Some(base) = self.base_address() {
+            write!(f, " @ {:#x}", base)?;
+        }
+        Ok(())
+    }
+}
+
+/// Vivado hardware handoff, the `.hwh` description of a block design
+///
+/// Lists the IP instances with their VLNV, configuration parameters, address ranges as seen by
+/// the processors and port connections. Read from a `.hwh` file or from the `.hwh` inside an
+/// `.xsa` archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HardwareHandoff {
+    name: String,
+    device: String,
+    vivado_version: String,
+    instances: Vec<IpInstance>,
+}
+
+impl HardwareHandoff {
+    /// Reads a `.hwh` file, or the design `.hwh` of an `.xsa` archive.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HardwareHandoff, Error> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(ZIP_SIGNATURE) {
+            Self::from_xsa(std::io::Cursor::new(bytes), None)
+        } else {
+            Self::parse(&String::from_utf8_lossy(&bytes))
+        }
+    }
+
+    /// Reads a `.hwh` from an `.xsa` archive.
+    ///
+    /// Without a `name` the archive has to contain a single `.hwh`, or one named after the
+    /// `.bit` in the archive. Fails with [`Error::Ambiguous`] listing the candidates otherwise.
+    pub fn from_xsa<R: Read + Seek>(
+        reader: R,
+        name: Option<&str>,
+    ) -> Result<HardwareHandoff, Error> {
+        let mut archive = zip::ZipArchive::new(reader).map_err(zip_error)?;
+        let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        let entry = match name {
+            Some(name) => names
+                .iter()
+                .find(|n| *n == name || Path::new(n).file_name() == Some(name.as_ref()))
+                .ok_or_else(|| Error::NotFound(names.clone()))?,
+            None => select_hwh(&names)?,
+        };
+        log::debug!("read {} from archive", entry);
+        let mut text = String::new();
+        archive
+            .by_name(entry)
+            .map_err(zip_error)?
+            .read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    /// Parses the XML of a `.hwh` file.
+    pub fn parse(text: &str) -> Result<HardwareHandoff, Error> {
+        let document = roxmltree::Document::parse(text).map_err(|error| {
+            log::warn!("invalid hardware handoff, {}", error);
+            Error::Io(std::io::ErrorKind::InvalidData)
+        })?;
+        let root = document.root_element();
+        if !root.has_tag_name("EDKSYSTEM") {
+            log::warn!("not a hardware handoff, {}", root.tag_name().name());
+            return Err(Error::Io(std::io::ErrorKind::InvalidData));
+        }
+        let mut handoff = HardwareHandoff {
+            name: String::new(),
+            device: String::new(),
+            vivado_version: root.attribute("VIVADOVERSION").unwrap_or("").to_string(),
+            instances: Vec::new(),
+        };
+        if let Some(info) = child(root, "SYSTEMINFO") {
+            handoff.name = info.attribute("NAME").unwrap_or("").to_string();
+            handoff.device = [
+                info.attribute("DEVICE"),
+                info.attribute("PACKAGE"),
+                info.attribute("SPEEDGRADE"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+        }
+        let modules: Vec<_> = child(root, "MODULES")
+            .map(|m| children(m, "MODULE").collect())
+            .unwrap_or_default();
+        for module in &modules {
+            handoff.instances.push(parse_module(*module)?);
+        }
+        // Address ranges are listed in the memory maps of the processors
+        for module in &modules {
+            let Some(map) = child(*module, "MEMORYMAP") else {
+                continue;
+            };
+            for range in children(map, "MEMRANGE") {
+                let (Some(instance), Some(base), Some(high)) = (
+                    range.attribute("INSTANCE"),
+                    range.attribute("BASEVALUE").and_then(parse_u64),
+                    range.attribute("HIGHVALUE").and_then(parse_u64),
+                ) else {
+                    continue;
+                };
+                if high < base || high - base == u64::MAX {
+                    log::warn!("{} invalid range {:#x} to {:#x}", instance, base, high);
+                    continue;
+                }
+                let range = AddressRange {
+                    interface: range
+                        .attribute("SLAVEBUSINTERFACE")
+                        .unwrap_or("")
+                        .to_string(),
+                    block: range.attribute("ADDRESSBLOCK").unwrap_or("").to_string(),
+                    base_name: range.attribute("BASENAME").unwrap_or("").to_string(),
+                    base,
+                    high,
+                    memory: range.attribute("MEMTYPE") == Some("MEMORY"),
+                };
+                if let Some(ip) = handoff.instances.iter_mut().find(|i| i.name == instance)
+                    && !ip.ranges.contains(&range)
+                {
+                    ip.ranges.push(range);
+                }
+            }
+        }
+        for ip in handoff.instances.iter_mut() {
+            ip.ranges.sort_by_key(|r| r.base);
+        }
+        Ok(handoff)
+    }
+
+    /// Returns the block design name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the target part, e.g. `xczu3egsbva484-1`.
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Returns the Vivado version that wrote the handoff.
+    pub fn vivado_version(&self) -> &str {
+        &self.vivado_version
+    }
+
+    /// Returns all IP instances.
+    pub fn instances(&self) -> &[IpInstance] {
+        &self.instances
+    }
+
+    /// Finds the IP instance with the name.
+    ///
+    /// Fails with [`Error::NotFound`] listing all instances when nothing matches.
+    pub fn instance(&self, name: &str) -> Result<&IpInstance, Error> {
+        self.instances
+            .iter()
+            .find(|i| i.name == name)
+            .ok_or_else(|| Error::NotFound(self.instances.iter().map(|i| i.to_string()).collect()))
+    }
+
+    /// Lists the IP instances of the core, given as `vendor:library:name`.
+    pub fn instances_of<'a>(
+        &'a self,
+        vendor_library_name: &'a str,
+    ) -> impl Iterator<Item = &'a IpInstance> {
+        self.instances
+            .iter()
+            .filter(move |i| i.vlnv.matches(vendor_library_name))
+    }
+
+    /// Follows an interrupt output through concatenation blocks to the interrupt inputs it
+    /// drives.
+    pub fn interrupt_targets(
+        &self,
+        instance: &str,
+        port: &str,
+    ) -> Result<Vec<InterruptTarget>, Error> {
+        let source = self.instance(instance)?;
+        let port = source.port(port).ok_or_else(|| {
+            Error::NotFound(source.ports.iter().map(|p| p.name.clone()).collect())
+        })?;
+        let mut targets = Vec::new();
+        let mut pending: Vec<(&PortConnection, u32)> =
+            port.connections.iter().map(|c| (c, 0)).collect();
+        while let Some((connection, offset)) = pending.pop() {
+            let Ok(sink) = self.instance(&connection.instance) else {
+                continue;
+            };
+            let input = connection
+                .port
+                .strip_prefix("In")
+                .and_then(|n| n.parse::<u32>().ok());
+            match input {
+                Some(input) if sink.vlnv.name == "xlconcat" => {
+                    let width = |i: u32| sink.parameter_u64(&format!("IN{}_WIDTH", i)).unwrap_or(1);
+                    let offset = offset + (0..input).map(|i| width(i) as u32).sum::<u32>();
+                    if let Some(output) = sink.port("dout") {
+                        pending.extend(output.connections.iter().map(|c| (c, offset)));
+                    }
+                }
+                _ => targets.push(InterruptTarget {
+                    instance: connection.instance.clone(),
+                    port: connection.port.clone(),
+                    index: offset,
+                }),
+            }
+        }
+        Ok(targets)
+    }
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn children<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn parse_module(module: roxmltree::Node) -> Result<IpInstance, Error> {
+    let name = module.attribute("INSTANCE").unwrap_or("").to_string();
+    let vlnv = Vlnv::parse(module.attribute("VLNV").unwrap_or("")).inspect_err(|_| {
+        log::warn!("invalid VLNV of {}", name);
+    })?;
+    let parameters = child(module, "PARAMETERS")
+        .map(|p| {
+            children(p, "PARAMETER")
+                .filter_map(|p| Some((p.attribute("NAME")?, p.attribute("VALUE").unwrap_or(""))))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let ports = child(module, "PORTS")
+        .map(|p| children(p, "PORT").map(parse_port).collect())
+        .unwrap_or_default();
+    let registers = child(module, "ADDRESSBLOCKS")
+        .map(|b| {
+            children(b, "ADDRESSBLOCK")
+                .flat_map(parse_registers)
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(IpInstance {
+        name,
+        vlnv,
+        kind: module.attribute("IPTYPE").unwrap_or("").to_string(),
+        parameters,
+        ranges: Vec::new(),
+        ports,
+        registers,
+    })
+}
+
+fn parse_registers(block: roxmltree::Node) -> Vec<IpRegister> {
+    let interface = block.attribute("INTERFACE").unwrap_or("");
+    let Some(registers) = child(block, "REGISTERS") else {
+        return Vec::new();
+    };
+    children(registers, "REGISTER")
+        .filter_map(|register| {
+            let property = |name: &str| {
+                children(register, "PROPERTY")
+                    .find(|p| p.attribute("NAME") == Some(name))
+                    .and_then(|p| p.attribute("VALUE"))
+            };
+            Some(IpRegister {
+                interface: interface.to_string(),
+                name: register.attribute("NAME")?.to_string(),
+                offset: parse_u64(property("ADDRESS_OFFSET")?)?,
+                bits: parse_u64(property("SIZE").unwrap_or("32"))? as u32,
+                access: property("ACCESS").unwrap_or("").to_string(),
+                description: property("DESCRIPTION").unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_port(port: roxmltree::Node) -> IpPort {
+    let attribute = |name| port.attribute(name).unwrap_or("").to_string();
+    IpPort {
+        name: attribute("NAME"),
+        direction: attribute("DIR"),
+        signal: attribute("SIGNAME"),
+        kind: attribute("SIGIS"),
+        sensitivity: attribute("SENSITIVITY"),
+        connections: child(port, "CONNECTIONS")
+            .map(|c| {
+                children(c, "CONNECTION")
+                    .map(|c| PortConnection {
+                        instance: c.attribute("INSTANCE").unwrap_or("").to_string(),
+                        port: c.attribute("PORT").unwrap_or("").to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Selects the design `.hwh` among the archive entries.
+fn select_hwh(names: &[String]) -> Result<&String, Error> {
+    let stem = |name: &str| {
+        Path::new(name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(str::to_string)
+    };
+    let handoffs: Vec<&String> = names.iter().filter(|n| n.ends_with(".hwh")).collect();
+    let bitstreams: Vec<Option<String>> = names
+        .iter()
+        .filter(|n| n.ends_with(".bit"))
+        .map(|n| stem(n))
+        .collect();
+    match handoffs.as_slice() {
+        [] => Err(Error::NotFound(names.to_vec())),
+        [handoff] => Ok(handoff),
+        _ => {
+            let mut matching = handoffs
+                .iter()
+                .filter(|n| bitstreams.contains(&stem(n)))
+                .copied();
+            match (matching.next(), matching.next()) {
+                (Some(handoff), None) => Ok(handoff),
+                _ => Err(Error::Ambiguous(
+                    handoffs.iter().map(|n| n.to_string()).collect(),
+                )),
+            }
+        }
+    }
+}
+
+fn zip_error(error: zip::result::ZipError) -> Error {
+    match error {
+        zip::result::ZipError::Io(error) => error.into(),
+        error => {
+            log::warn!("invalid archive, {}", error);
+            Error::Io(std::io::ErrorKind::InvalidData)
+        }
+    }
+}
+
+fn parse_u64(text: &str) -> Option<u64> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    /// Block design with two FIFOs, a GPIO and interrupts concatenated into the PS
+    const HWH: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<EDKSYSTEM EDWVERSION="1.2" TIMESTAMP="Wed Jun 12 10:42:17 2024" VIVADOVERSION="2024.1">
+  <SYSTEMINFO ARCH="zynquplus" DEVICE="xczu3eg" NAME="design_1" PACKAGE="sbva484" SPEEDGRADE="-1"/>
+  <MODULES>
+    <MODULE FULLNAME="/zynq_ultra_ps_e_0" INSTANCE="zynq_ultra_ps_e_0" IPTYPE="PROCESSOR" VLNV="xilinx.com:ip:zynq_ultra_ps_e:3.5">
+      <MEMORYMAP>
+        <MEMRANGE ADDRESSBLOCK="Mem1" BASENAME="C_AXI4_BASEADDR" BASEVALUE="0xA0010000" HIGHNAME="C_AXI4_HIGHADDR" HIGHVALUE="0xA0010FFF" INSTANCE="axi_fifo_mm_s_0" MEMTYPE="MEMORY" SLAVEBUSINTERFACE="S_AXI_FULL"/>
+        <MEMRANGE ADDRESSBLOCK="Mem0" BASENAME="C_BASEADDR" BASEVALUE="0xA0000000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA000FFFF" INSTANCE="axi_fifo_mm_s_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI"/>
+        <MEMRANGE ADDRESSBLOCK="Mem0" BASENAME="C_BASEADDR" BASEVALUE="0xA0000000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA000FFFF" INSTANCE="axi_fifo_mm_s_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI"/>
+        <MEMRANGE ADDRESSBLOCK="Mem0" BASENAME="C_BASEADDR" BASEVALUE="0xA0020000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA002FFFF" INSTANCE="axi_fifo_mm_s_1" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI"/>
+        <MEMRANGE ADDRESSBLOCK="Reg" BASENAME="C_BASEADDR" BASEVALUE="0xA0030000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA002FFFF" INSTANCE="axi_gpio_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI"/>
+        <MEMRANGE ADDRESSBLOCK="Reg" BASENAME="C_BASEADDR" BASEVALUE="0x0" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xFFFFFFFFFFFFFFFF" INSTANCE="axi_gpio_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI"/>
+      </MEMORYMAP>
+      <PORTS>
+        <PORT DIR="I" LEFT="7" NAME="pl_ps_irq0" RIGHT="0" SENSITIVITY="LEVEL_HIGH" SIGIS="INTERRUPT" SIGNAME="xlconcat_0_dout">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="xlconcat_0" PORT="dout"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+    <MODULE FULLNAME="/axi_fifo_mm_s_0" INSTANCE="axi_fifo_mm_s_0" IPTYPE="PERIPHERAL" VLNV="xilinx.com:ip:axi_fifo_mm_s:4.3">
+      <PARAMETERS>
+        <PARAMETER NAME="C_DATA_INTERFACE_TYPE" VALUE="1"/>
+        <PARAMETER NAME="C_S_AXIS_TDATA_WIDTH" VALUE="64"/>
+        <PARAMETER NAME="C_RX_FIFO_DEPTH" VALUE="0x200"/>
+        <PARAMETER NAME="Component_Name" VALUE="design_1_axi_fifo_mm_s_0_0"/>
+      </PARAMETERS>
+      <PORTS>
+        <PORT DIR="I" NAME="s_axi_aclk" SIGIS="clk" SIGNAME="zynq_ultra_ps_e_0_pl_clk0"/>
+        <PORT DIR="O" NAME="interrupt" SENSITIVITY="LEVEL_HIGH" SIGIS="INTERRUPT" SIGNAME="axi_fifo_mm_s_0_interrupt">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="xlconcat_0" PORT="In2"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+    <MODULE FULLNAME="/axi_fifo_mm_s_1" INSTANCE="axi_fifo_mm_s_1" IPTYPE="PERIPHERAL" VLNV="xilinx.com:ip:axi_fifo_mm_s:4.2">
+      <PARAMETERS>
+        <PARAMETER NAME="C_DATA_INTERFACE_TYPE" VALUE="0"/>
+        <PARAMETER NAME="C_S_AXIS_TDATA_WIDTH" VALUE="512"/>
+      </PARAMETERS>
+      <PORTS>
+        <PORT DIR="O" NAME="interrupt" SENSITIVITY="LEVEL_HIGH" SIGIS="INTERRUPT" SIGNAME="axi_fifo_mm_s_1_interrupt">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="xlconcat_0" PORT="In0"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+    <MODULE FULLNAME="/axi_gpio_0" INSTANCE="axi_gpio_0" IPTYPE="PERIPHERAL" VLNV="xilinx.com:ip:axi_gpio:2.0">
+      <PARAMETERS>
+        <PARAMETER NAME="C_GPIO_WIDTH" VALUE="8"/>
+        <PARAMETER NAME="C_ALL_INPUTS" VALUE="abc"/>
+      </PARAMETERS>
+      <PORTS>
+        <PORT DIR="O" NAME="ip2intc_irpt" SENSITIVITY="LEVEL_HIGH" SIGIS="INTERRUPT" SIGNAME="axi_gpio_0_ip2intc_irpt">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="xlconcat_1" PORT="In1"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+    <MODULE FULLNAME="/xlconcat_1" INSTANCE="xlconcat_1" IPTYPE="PERIPHERAL" VLNV="xilinx.com:ip:xlconcat:2.1">
+      <PARAMETERS>
+        <PARAMETER NAME="NUM_PORTS" VALUE="2"/>
+        <PARAMETER NAME="IN0_WIDTH" VALUE="1"/>
+        <PARAMETER NAME="IN1_WIDTH" VALUE="1"/>
+      </PARAMETERS>
+      <PORTS>
+        <PORT DIR="O" LEFT="1" NAME="dout" RIGHT="0" SIGNAME="xlconcat_1_dout">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="xlconcat_0" PORT="In1"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+    <MODULE FULLNAME="/xlconcat_0" INSTANCE="xlconcat_0" IPTYPE="PERIPHERAL" VLNV="xilinx.com:ip:xlconcat:2.1">
+      <PARAMETERS>
+        <PARAMETER NAME="NUM_PORTS" VALUE="3"/>
+        <PARAMETER NAME="IN0_WIDTH" VALUE="1"/>
+        <PARAMETER NAME="IN1_WIDTH" VALUE="2"/>
+        <PARAMETER NAME="IN2_WIDTH" VALUE="1"/>
+      </PARAMETERS>
+      <PORTS>
+        <PORT DIR="O" LEFT="3" NAME="dout" RIGHT="0" SIGNAME="xlconcat_0_dout">
+          <CONNECTIONS>
+            <CONNECTION INSTANCE="zynq_ultra_ps_e_0" PORT="pl_ps_irq0"/>
+          </CONNECTIONS>
+        </PORT>
+      </PORTS>
+    </MODULE>
+  </MODULES>
+</EDKSYSTEM>
+"#;
+
+    /// Returns an `.xsa` archive with the given entries.
+    fn xsa(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut archive = writer.finish().unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    #[test]
+    fn vlnv() {
+        let vlnv = Vlnv::parse("xilinx.com:ip:axi_fifo_mm_s:4.3").unwrap();
+        assert_eq!(vlnv.vendor, "xilinx.com");
+        assert_eq!(vlnv.library, "ip");
+        assert_eq!(vlnv.name, "axi_fifo_mm_s");
+        assert_eq!(vlnv.version, "4.3");
+        assert_eq!(vlnv.to_string(), "xilinx.com:ip:axi_fifo_mm_s:4.3");
+        assert!(vlnv.matches("xilinx.com:ip:axi_fifo_mm_s"));
+        assert!(!vlnv.matches("xilinx.com:ip:axi_fifo_mm_s:4.3"));
+        assert!(!vlnv.matches("xilinx.com:ip:axi_fifo"));
+        assert!(!vlnv.matches("xilinx.com:ip"));
+        assert_eq!(
+            Vlnv::parse("xilinx.com:ip:axi_fifo_mm_s"),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(Vlnv::parse("a:b:c:d:e"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn parse() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        assert_eq!(handoff.name(), "design_1");
+        assert_eq!(handoff.device(), "xczu3egsbva484-1");
+        assert_eq!(handoff.vivado_version(), "2024.1");
+        let names: Vec<_> = handoff.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "zynq_ultra_ps_e_0",
+                "axi_fifo_mm_s_0",
+                "axi_fifo_mm_s_1",
+                "axi_gpio_0",
+                "xlconcat_1",
+                "xlconcat_0"
+            ]
+        );
+        let fifos: Vec<_> = handoff
+            .instances_of("xilinx.com:ip:axi_fifo_mm_s")
+            .map(|i| i.name())
+            .collect();
+        assert_eq!(fifos, ["axi_fifo_mm_s_0", "axi_fifo_mm_s_1"]);
+        match handoff.instance("missing") {
+            Err(Error::NotFound(candidates)) => assert_eq!(candidates.len(), 6),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            HardwareHandoff::parse("<MODULES/>"),
+            Err(Error::Io(std::io::ErrorKind::InvalidData))
+        );
+        assert_eq!(
+            HardwareHandoff::parse("<EDKSYSTEM>"),
+            Err(Error::Io(std::io::ErrorKind::InvalidData))
+        );
+    }
+
+    #[test]
+    fn instance_parameters_and_ports() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        let fifo = handoff.instance("axi_fifo_mm_s_0").unwrap();
+        assert_eq!(fifo.kind(), "PERIPHERAL");
+        assert_eq!(fifo.vlnv().version, "4.3");
+        assert_eq!(fifo.parameters().len(), 4);
+        assert_eq!(
+            fifo.parameter("Component_Name"),
+            Some("design_1_axi_fifo_mm_s_0_0")
+        );
+        assert_eq!(fifo.parameter_u64("C_S_AXIS_TDATA_WIDTH"), Ok(64));
+        assert_eq!(fifo.parameter_u64("C_RX_FIFO_DEPTH"), Ok(0x200));
+        assert!(matches!(
+            fifo.parameter_u64("C_MISSING"),
+            Err(Error::NotFound(_))
+        ));
+        let gpio = handoff.instance("axi_gpio_0").unwrap();
+        assert_eq!(
+            gpio.parameter_u64("C_ALL_INPUTS"),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(fifo.ports().len(), 2);
+        assert!(!fifo.port("s_axi_aclk").unwrap().is_interrupt());
+        let interrupts: Vec<_> = fifo.interrupts().map(|p| p.name.as_str()).collect();
+        assert_eq!(interrupts, ["interrupt"]);
+        let interrupt = fifo.port("interrupt").unwrap();
+        assert_eq!(interrupt.direction, "O");
+        assert_eq!(interrupt.sensitivity, "LEVEL_HIGH");
+        assert_eq!(
+            interrupt.connections,
+            [PortConnection {
+                instance: "xlconcat_0".to_string(),
+                port: "In2".to_string(),
+            }]
+        );
+        assert!(fifo.registers().is_empty());
+        assert_eq!(
+            fifo.to_string(),
+            "axi_fifo_mm_s_0 xilinx.com:ip:axi_fifo_mm_s:4.3 @ 0xa0000000"
+        );
+    }
+
+    #[test]
+    fn address_ranges() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        let fifo = handoff.instance("axi_fifo_mm_s_0").unwrap();
+        // Duplicates are dropped and the ranges are ordered by base address
+        let interfaces: Vec<_> = fifo.ranges().iter().map(|r| r.interface.as_str()).collect();
+        assert_eq!(interfaces, ["S_AXI", "S_AXI_FULL"]);
+        let registers = fifo.range("S_AXI").unwrap();
+        assert_eq!(registers.block, "Mem0");
+        assert_eq!(registers.base_name, "C_BASEADDR");
+        assert_eq!(registers.size(), 0x1_0000);
+        assert!(!registers.memory);
+        let data = fifo.range("S_AXI_FULL").unwrap();
+        assert!(data.memory);
+        assert_eq!(data.region(), Region::new(0xa001_0000, 0x1000));
+        assert_eq!(fifo.base_address(), Some(0xa000_0000));
+        // Ranges ending before their base or covering all addresses are rejected
+        let gpio = handoff.instance("axi_gpio_0").unwrap();
+        assert!(gpio.ranges().is_empty());
+        assert_eq!(gpio.base_address(), None);
+        assert!(handoff.instance("xlconcat_0").unwrap().ranges().is_empty());
+    }
+
+    #[test]
+    fn interrupt_targets() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        let target = |instance: &str, port: &str| {
+            let targets = handoff.interrupt_targets(instance, port).unwrap();
+            assert_eq!(targets.len(), 1);
+            assert_eq!(targets[0].instance, "zynq_ultra_ps_e_0");
+            assert_eq!(targets[0].port, "pl_ps_irq0");
+            targets[0].index
+        };
+        assert_eq!(target("axi_fifo_mm_s_1", "interrupt"), 0);
+        // Through a second concatenation on the 2-bit wide input 1
+        assert_eq!(target("axi_gpio_0", "ip2intc_irpt"), 2);
+        assert_eq!(target("axi_fifo_mm_s_0", "interrupt"), 3);
+        assert!(matches!(
+            handoff.interrupt_targets("axi_fifo_mm_s_0", "irq"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            handoff.interrupt_targets("axi_fifo_mm_s_9", "interrupt"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn stream_fifo_width() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        // AXI4 data interface, the stream width
+        let full = handoff.instance("axi_fifo_mm_s_0").unwrap();
+        assert_eq!(
+            crate::StreamFifoValue::try_from_ip(full),
+            Ok(crate::StreamFifoValue::U64)
+        );
+        // AXI4-Lite data interface, always 32 bits regardless of the stream width
+        let lite = handoff.instance("axi_fifo_mm_s_1").unwrap();
+        assert_eq!(
+            crate::StreamFifoValue::try_from_ip(lite),
+            Ok(crate::StreamFifoValue::U32)
+        );
+        // Unsupported and missing widths
+        let mut wide = full.clone();
+        wide.parameters
+            .insert("C_S_AXIS_TDATA_WIDTH".to_string(), "512".to_string());
+        assert_eq!(
+            crate::StreamFifoValue::try_from_ip(&wide),
+            Err(Error::Unsupported)
+        );
+        wide.parameters.remove("C_DATA_INTERFACE_TYPE");
+        assert!(matches!(
+            crate::StreamFifoValue::try_from_ip(&wide),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn from_xsa() {
+        let single = xsa(&[("design_1.hwh", HWH), ("design_1.bit", "")]);
+        let handoff = HardwareHandoff::from_xsa(single, None).unwrap();
+        assert_eq!(handoff.name(), "design_1");
+        // The handoff named after the bitstream is the design handoff
+        let entries = [
+            ("design_1_bd.hwh", "<EDKSYSTEM/>"),
+            ("hw/design_1.hwh", HWH),
+            ("hw/design_1.bit", ""),
+        ];
+        let handoff = HardwareHandoff::from_xsa(xsa(&entries), None).unwrap();
+        assert_eq!(handoff.instances().len(), 6);
+        let handoff = HardwareHandoff::from_xsa(xsa(&entries), Some("design_1_bd.hwh")).unwrap();
+        assert!(handoff.instances().is_empty());
+        let handoff = HardwareHandoff::from_xsa(xsa(&entries), Some("design_1.hwh")).unwrap();
+        assert_eq!(handoff.instances().len(), 6);
+        assert!(matches!(
+            HardwareHandoff::from_xsa(xsa(&entries), Some("other.hwh")),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(
+            HardwareHandoff::from_xsa(xsa(&entries[..2]), None),
+            Err(Error::Ambiguous(vec![
+                "design_1_bd.hwh".to_string(),
+                "hw/design_1.hwh".to_string()
+            ]))
+        );
+        assert!(matches!(
+            HardwareHandoff::from_xsa(xsa(&[("design_1.bit", "")]), None),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(
+            HardwareHandoff::from_xsa(Cursor::new(b"not an archive".to_vec()), None),
+            Err(Error::Io(std::io::ErrorKind::InvalidData))
+        );
+    }
+
+    #[test]
+    fn open() {
+        let directory = tempfile::tempdir().unwrap();
+        let hwh = directory.path().join("design_1.hwh");
+        std::fs::write(&hwh, HWH).unwrap();
+        assert_eq!(HardwareHandoff::open(&hwh).unwrap().name(), "design_1");
+        let path = directory.path().join("design_1.xsa");
+        std::fs::write(&path, xsa(&[("design_1.hwh", HWH)]).into_inner()).unwrap();
+        assert_eq!(HardwareHandoff::open(&path).unwrap().name(), "design_1");
+    }
+
+    #[test]
+    fn select() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let entries = names(&["a.hwh", "sub/b.hwh", "sub/b.bit"]);
+        assert_eq!(select_hwh(&entries), Ok(&entries[1]));
+        let entries = names(&["a.hwh", "b.hwh", "a.bit", "b.bit"]);
+        assert!(matches!(select_hwh(&entries), Err(Error::Ambiguous(_))));
+        let entries = names(&["a.bit", "sysdef.xml"]);
+        assert_eq!(select_hwh(&entries), Err(Error::NotFound(entries.clone())));
+    }
+}
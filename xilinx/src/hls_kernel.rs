@@ -0,0 +1,632 @@
+use crate::discovery::DeviceInfo;
+use crate::dma_buffer::DmaBuffer;
+use crate::error::Error;
+use crate::handoff::{AddressRange, IpInstance};
+use crate::interrupt::{Interrupt, wait_for};
+use crate::mmio::Mmio;
+use crate::{register_bits, register_map};
+use std::path::Path;
+use std::time::Duration;
+
+/// Offset of the first argument register, after the block level control registers
+const ARGUMENTS_OFFSET: u64 = 0x10;
+
+/// Name of the AXI4-Lite control interface of an HLS kernel
+const CONTROL_INTERFACE: &str = "s_axi_control";
+
+/// Argument register of an HLS kernel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HlsArgument {
+    /// Argument name, in lower case
+    pub name: String,
+    /// Offset of the data register
+    pub offset: usize,
+    /// Width in bits, 64-bit arguments span two registers
+    pub bits: u32,
+}
+
+impl HlsArgument {
+    /// Parses the argument registers from an HLS generated driver header, `x<kernel>_hw.h`.
+    ///
+    /// Uses the `..._ADDR_<NAME>_DATA` and `..._BITS_<NAME>_DATA` definitions of the interface
+    /// with the `..._ADDR_AP_CTRL` definition. The return value, `..._ADDR_AP_RETURN` and
+    /// `..._BITS_AP_RETURN`, is the argument `ap_return`.
+    pub fn parse_header(text: &str) -> Result<Vec<HlsArgument>, Error> {
+        let defines: Vec<(&str, &str)> = text
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some("#define"), Some(name), Some(value)) => Some((name, value)),
+                    _ => None,
+                }
+            })
+            .collect();
+        let address_prefix = defines
+            .iter()
+            .find_map(|(name, _)| name.strip_suffix("AP_CTRL"))
+            .filter(|prefix| prefix.ends_with("_ADDR_"))
+            .ok_or_else(|| {
+                log::warn!("no AP_CTRL register in header");
+                Error::NotFound(Vec::new())
+            })?;
+        let bits_prefix = format!(
+            "{}_BITS_",
+            address_prefix.strip_suffix("_ADDR_").unwrap_or_default()
+        );
+        let mut arguments = Vec::new();
+        let bits = |name: &str| {
+            defines
+                .iter()
+                .find(|(define, _)| *define == name)
+                .and_then(|(_, value)| value.parse::<u32>().ok())
+        };
+        for (name, value) in &defines {
+            let Some(register) = name.strip_prefix(address_prefix) else {
+                continue;
+            };
+            let (argument, width) = if let Some(argument) = register.strip_suffix("_DATA") {
+                let width = bits(&format!("{}{}_DATA", bits_prefix, argument));
+                (argument, width.unwrap_or(32))
+            } else if register == "AP_RETURN" {
+                (
+                    register,
+                    bits(&format!("{}AP_RETURN", bits_prefix)).unwrap_or(32),
+                )
+            } else if register == "AP_RETURN_0" {
+                // Wide return values are split in 32-bit registers
+                let low = bits(&format!("{}AP_RETURN_0", bits_prefix)).unwrap_or(32);
+                let high = bits(&format!("{}AP_RETURN_1", bits_prefix)).unwrap_or(0);
+                ("AP_RETURN", low + high)
+            } else {
+                continue;
+            };
+            let offset = parse_usize(value).ok_or(Error::InvalidArgument)?;
+            arguments.push(HlsArgument {
+                name: argument.to_lowercase(),
+                offset,
+                bits: width,
+            });
+        }
+        Ok(arguments)
+    }
+
+    /// Returns the argument registers of an HLS kernel in a hardware handoff.
+    ///
+    /// Only the registers of the control interface are used, see [`HlsKernel::try_from_ip`].
+    /// The handoff lists 64-bit arguments as the two halves `<name>_1` and `<name>_2`, these are
+    /// combined into one argument.
+    pub fn from_ip(ip: &IpInstance) -> Vec<HlsArgument> {
+        let interface = control_range(ip).map(|range| range.interface.as_str());
+        let registers: Vec<_> = ip
+            .registers()
+            .iter()
+            .filter(|r| interface.is_some_and(|i| r.interface.eq_ignore_ascii_case(i)))
+            .filter(|r| r.offset >= ARGUMENTS_OFFSET)
+            .collect();
+        let mut arguments = Vec::new();
+        for register in &registers {
+            let name = register.name.to_lowercase();
+            if let Some(base) = name.strip_suffix("_2")
+                && registers
+                    .iter()
+                    .any(|r| r.name.eq_ignore_ascii_case(&format!("{}_1", base)))
+            {
+                continue;
+            }
+            let (name, bits) = match name.strip_suffix("_1") {
+                Some(base)
+                    if registers.iter().any(|r| {
+                        r.name.eq_ignore_ascii_case(&format!("{}_2", base))
+                            && r.offset == register.offset + 4
+                    }) =>
+                {
+                    (base.to_string(), 64)
+                }
+                _ => (name, register.bits),
+            };
+            arguments.push(HlsArgument {
+                name,
+                offset: register.offset as usize,
+                bits,
+            });
+        }
+        arguments
+    }
+}
+
+/// Vitis HLS kernel with an `ap_ctrl_hs` or `ap_ctrl_chain` block level interface
+///
+/// Starts the kernel and waits for completion by polling the control register or on the
+/// `ap_done` interrupt, optionally restarting automatically. Arguments are set by name, with
+/// the register offsets from the HLS driver header or the hardware handoff.
+pub struct HlsKernel {
+    registers: Mmio,
+    interrupt: Option<Interrupt>,
+    arguments: Vec<HlsArgument>,
+    auto_restart: bool,
+}
+
+impl HlsKernel {
+    /// Creates a new `HlsKernel` instance from a UIO device.
+    ///
+    /// Arguments have to be set with [`HlsKernel::set_arguments`] or
+    /// [`HlsKernel::load_header`] before they can be accessed by name.
+    pub fn try_from(device: &DeviceInfo) -> Result<HlsKernel, Error> {
+        let registers = Mmio::try_from_device(device, 0)?;
+        let interrupt = Interrupt::try_from_device(device)?;
+        Ok(Self::from_mmio(registers, Some(interrupt)))
+    }
+
+    /// Creates a new `HlsKernel` instance from an IP instance of a hardware handoff, mapped
+    /// through `/dev/mem`, with the arguments of the handoff.
+    ///
+    /// The control interface is `s_axi_control`, or the first address range of the instance
+    /// when the interface has another name.
+    pub fn try_from_ip(ip: &IpInstance) -> Result<HlsKernel, Error> {
+        let range = control_range(ip).ok_or(Error::NoMemoryMap)?;
+        let registers = Mmio::try_from_region(&range.region())?;
+        let mut kernel = Self::from_mmio(registers, None);
+        kernel.set_arguments(HlsArgument::from_ip(ip));
+        Ok(kernel)
+    }
+
+    /// Creates a new `HlsKernel` instance from a memory mapped register region.
+    ///
+    /// Without an interrupt the control register is polled.
+    pub fn from_mmio(registers: Mmio, interrupt: Option<Interrupt>) -> HlsKernel {
+        HlsKernel {
+            registers,
+            interrupt,
+            arguments: Vec::new(),
+            auto_restart: false,
+        }
+    }
+
+    /// Sets the argument registers.
+    pub fn set_arguments(&mut self, arguments: Vec<HlsArgument>) {
+        self.arguments = arguments;
+    }
+
+    /// Reads the argument registers from an HLS generated driver header.
+    pub fn load_header<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let text = std::fs::read_to_string(path)?;
+        self.arguments = HlsArgument::parse_header(&text)?;
+        Ok(())
+    }
+
+    /// Returns the argument registers.
+    pub fn arguments(&self) -> &[HlsArgument] {
+        &self.arguments
+    }
+
+    /// Finds the argument register, the name is not case sensitive.
+    ///
+    /// Fails with [`Error::NotFound`] listing all arguments when nothing matches.
+    pub fn argument(&self, name: &str) -> Result<&HlsArgument, Error> {
+        self.arguments
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::NotFound(self.arguments.iter().map(|a| a.name.clone()).collect()))
+    }
+
+    /// Writes a scalar argument, truncated to the argument width.
+    pub fn set_argument(&mut self, name: &str, value: u64) -> Result<(), Error> {
+        let argument = self.argument(name)?.clone();
+        match argument.bits {
+            1..=32 => self
+                .registers
+                .write_u32(argument.offset, value as u32 & mask(argument.bits)),
+            33..=64 => {
+                self.registers.write_u32(argument.offset, value as u32)?;
+                self.registers.write_u32(
+                    argument.offset + 4,
+                    (value >> 32) as u32 & mask(argument.bits - 32),
+                )
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// Reads an argument, such as an output scalar or `ap_return`.
+    pub fn argument_value(&self, name: &str) -> Result<u64, Error> {
+        let argument = self.argument(name)?;
+        match argument.bits {
+            1..=32 => Ok(u64::from(
+                self.registers.read_u32(argument.offset)? & mask(argument.bits),
+            )),
+            33..=64 => {
+                let low = self.registers.read_u32(argument.offset)?;
+                let high = self.registers.read_u32(argument.offset + 4)?;
+                Ok(u64::from(high & mask(argument.bits - 32)) << 32 | u64::from(low))
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// Writes a pointer argument, the physical address of an `m_axi` buffer.
+    pub fn set_pointer(&mut self, name: &str, address: u64) -> Result<(), Error> {
+        let bits = self.argument(name)?.bits;
+        if bits < 64 && address >> bits != 0 {
+            return Err(Error::OutOfRange);
+        }
+        self.set_argument(name, address)
+    }
+
+    /// Writes a pointer argument with the physical address of a DMA buffer.
+    ///
+    /// Fails with [`Error::Unsupported`] when the physical address of the buffer is unknown.
+    pub fn set_buffer(&mut self, name: &str, buffer: &DmaBuffer) -> Result<(), Error> {
+        let address = buffer.address().ok_or(Error::Unsupported)?;
+        self.set_pointer(name, address)
+    }
+
+    /// Returns the control register.
+    ///
+    /// Reading clears `ap_done`, and `ap_ready` when not restarting automatically.
+    pub fn control(&self) -> Result<HlsControl, Error> {
+        self.registers.read(Registers::AP_CTRL)
+    }
+
+    /// Checks if the kernel is idle.
+    pub fn is_idle(&self) -> Result<bool, Error> {
+        Ok(self.control()?.idle())
+    }
+
+    fn restart_bits(&self) -> HlsControl {
+        if self.auto_restart {
+            HlsControl::AUTO_RESTART
+        } else {
+            HlsControl::empty()
+        }
+    }
+
+    /// Starts the kernel.
+    ///
+    /// Fails with [`Error::Busy`] when the kernel is neither idle nor ready for new inputs.
+    pub fn start(&mut self) -> Result<(), Error> {
+        let control = self.control()?;
+        if !control.idle() && !control.ready() {
+            return Err(Error::Busy);
+        }
+        self.registers
+            .write(Registers::AP_CTRL, HlsControl::START | self.restart_bits())
+    }
+
+    /// Sets `auto_restart`, the kernel starts again after each run until it is cleared.
+    ///
+    /// Enabling it also starts the kernel. After disabling, the current run completes.
+    pub fn set_auto_restart(&mut self, enable: bool) -> Result<(), Error> {
+        self.auto_restart = enable;
+        let start = if enable {
+            HlsControl::START
+        } else {
+            HlsControl::empty()
+        };
+        self.registers
+            .write(Registers::AP_CTRL, start | self.restart_bits())
+    }
+
+    /// Acknowledges a completed run of an `ap_ctrl_chain` kernel by setting `ap_continue`.
+    pub fn acknowledge(&mut self) -> Result<(), Error> {
+        self.registers.write(
+            Registers::AP_CTRL,
+            HlsControl::CONTINUE | self.restart_bits(),
+        )
+    }
+
+    /// Enables the interrupts, other interrupts are disabled. The global interrupt enable is
+    /// set when any interrupt is enabled.
+    pub fn enable_interrupts(&mut self, interrupts: HlsInterrupt) -> Result<(), Error> {
+        self.registers.write(Registers::IER, interrupts)?;
+        self.registers.write(
+            Registers::GIE,
+            u32::from(interrupts != HlsInterrupt::empty()),
+        )
+    }
+
+    /// Returns and acknowledges the pending interrupts.
+    pub fn interrupt_status(&mut self) -> Result<HlsInterrupt, Error> {
+        interrupt_status(&mut self.registers)
+    }
+
+    /// Waits until the current run is done.
+    ///
+    /// With an interrupt the `ap_done` interrupt is enabled for the wait, otherwise the control
+    /// register is polled. A run that completed before the interrupt was enabled is found
+    /// through the control register.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let Some(interrupt) = self.interrupt.as_ref() else {
+            let registers = &self.registers;
+            return wait_for(None, timeout, || {
+                let control = registers.read(Registers::AP_CTRL)?;
+                Ok(control.done().then_some(()))
+            });
+        };
+        self.registers.write(Registers::IER, HlsInterrupt::DONE)?;
+        self.registers.write(Registers::GIE, 1)?;
+        let registers = &mut self.registers;
+        let result = wait_for(Some(interrupt), timeout, || {
+            let done =
+                interrupt_status(registers)?.done() || registers.read(Registers::AP_CTRL)?.done();
+            Ok(done.then_some(()))
+        });
+        self.disable_interrupts()?;
+        result
+    }
+
+    fn disable_interrupts(&mut self) -> Result<(), Error> {
+        self.registers
+            .write(Registers::IER, HlsInterrupt::empty())?;
+        self.registers.write(Registers::GIE, 0)
+    }
+
+    /// Starts the kernel and waits until the run is done.
+    pub fn run(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        if self.interrupt.is_some() {
+            // Discard a completion left over from an earlier run and enable the interrupt
+            // before starting, a short run completes before the wait begins
+            self.interrupt_status()?;
+            self.registers.write(Registers::IER, HlsInterrupt::DONE)?;
+            self.registers.write(Registers::GIE, 1)?;
+            if let Err(error) = self.start() {
+                self.disable_interrupts()?;
+                return Err(error);
+            }
+        } else {
+            self.start()?;
+        }
+        self.wait(timeout)
+    }
+}
+
+/// Returns the address range of the control interface.
+fn control_range(ip: &IpInstance) -> Option<&AddressRange> {
+    ip.range(CONTROL_INTERFACE).or_else(|| ip.ranges().first())
+}
+
+fn interrupt_status(registers: &mut Mmio) -> Result<HlsInterrupt, Error> {
+    registers.acknowledge(Registers::ISR)
+}
+
+fn mask(bits: u32) -> u32 {
+    if bits >= 32 {
+        u32::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn parse_usize(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+register_map! {
+    /// HLS block level control registers
+    struct Registers {
+        /// Control
+        AP_CTRL: HlsControl, RW @ 0x00;
+        /// Global interrupt enable
+        GIE: u32, RW @ 0x04;
+        /// Interrupt enable
+        IER: HlsInterrupt, RW @ 0x08;
+        /// Interrupt status, toggle on write
+        ISR: HlsInterrupt, TOW @ 0x0c;
+    }
+}
+
+register_bits! {
+    /// HLS control register
+    pub struct HlsControl(u32) {
+        /// Start the kernel
+        START: start, set_start: 0;
+        /// The run is done, cleared when read
+        DONE: done, set_done: 1;
+        /// The kernel is idle
+        IDLE: idle, set_idle: 2;
+        /// The kernel is ready for new inputs
+        READY: ready, set_ready: 3;
+        /// Continue after done, `ap_ctrl_chain`
+        CONTINUE: continue_, set_continue: 4;
+        /// Restart automatically
+        AUTO_RESTART: auto_restart, set_auto_restart: 7;
+        /// An interrupt is pending
+        INTERRUPT: interrupt, set_interrupt: 9;
+    }
+}
+
+register_bits! {
+    /// HLS interrupts
+    pub struct HlsInterrupt(u32) {
+        /// The run is done, `ap_done`
+        DONE: done, set_done: 0;
+        /// Ready for new inputs, `ap_ready`
+        READY: ready, set_ready: 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handoff::HardwareHandoff;
+
+    /// Register definitions of `xvadd_hw.h`, generated by Vitis HLS
+    const HEADER: &str = "\
+// ==============================================================
+// Vitis HLS - High-Level Synthesis from C, C++ and OpenCL v2024.1 (64-bit)
+// ==============================================================
+// control
+// 0x00 : Control signals
+// 0x04 : Global Interrupt Enable Register
+// 0x08 : IP Interrupt Enable Register (Read/Write)
+// 0x0c : IP Interrupt Status Register (Read/TOW)
+// 0x10 : Data signal of ap_return
+//        bit 31~0 - ap_return[31:0] (Read)
+// 0x14 : Data signal of ap_return
+//        bit 31~0 - ap_return[63:32] (Read)
+// 0x1c : Data signal of in1
+//        bit 31~0 - in1[31:0] (Read/Write)
+// 0x20 : Data signal of in1
+//        bit 31~0 - in1[63:32] (Read/Write)
+// 0x24 : reserved
+// 0x28 : Data signal of size
+//        bit 31~0 - size[31:0] (Read/Write)
+// 0x2c : reserved
+// (SC = Self Clear, COR = Clear on Read, TOW = Toggle on Write, COH = Clear on Handshake)
+
+#define XVADD_CONTROL_ADDR_AP_CTRL        0x00
+#define XVADD_CONTROL_ADDR_GIE            0x04
+#define XVADD_CONTROL_ADDR_IER            0x08
+#define XVADD_CONTROL_ADDR_ISR            0x0c
+#define XVADD_CONTROL_ADDR_AP_RETURN_0    0x10
+#define XVADD_CONTROL_BITS_AP_RETURN_0    32
+#define XVADD_CONTROL_ADDR_AP_RETURN_1    0x14
+#define XVADD_CONTROL_BITS_AP_RETURN_1    32
+#define XVADD_CONTROL_ADDR_IN1_DATA       0x1c
+#define XVADD_CONTROL_BITS_IN1_DATA       64
+#define XVADD_CONTROL_ADDR_SIZE_DATA      0x28
+#define XVADD_CONTROL_BITS_SIZE_DATA      32
+";
+
+    /// Handoff of the `vadd` kernel, with a second AXI4-Lite interface
+    const HWH: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<EDKSYSTEM VIVADOVERSION="2024.1">
+  <SYSTEMINFO DEVICE="xczu3eg" NAME="design_1" PACKAGE="sbva484" SPEEDGRADE="-1"/>
+  <MODULES>
+    <MODULE INSTANCE="zynq_ultra_ps_e_0" IPTYPE="PROCESSOR" VLNV="xilinx.com:ip:zynq_ultra_ps_e:3.5">
+      <MEMORYMAP>
+        <MEMRANGE ADDRESSBLOCK="Reg" BASENAME="C_S_AXI_CONTROL_BASEADDR" BASEVALUE="0xA0000000" HIGHNAME="C_S_AXI_CONTROL_HIGHADDR" HIGHVALUE="0xA000FFFF" INSTANCE="vadd_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="s_axi_control"/>
+        <MEMRANGE ADDRESSBLOCK="Reg" BASENAME="C_S_AXI_STATS_BASEADDR" BASEVALUE="0xA0010000" HIGHNAME="C_S_AXI_STATS_HIGHADDR" HIGHVALUE="0xA001FFFF" INSTANCE="vadd_0" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="s_axi_stats"/>
+      </MEMORYMAP>
+    </MODULE>
+    <MODULE INSTANCE="vadd_0" IPTYPE="PERIPHERAL" VLNV="xilinx.com:hls:vadd:1.0">
+      <ADDRESSBLOCKS>
+        <ADDRESSBLOCK ACCESS="read-write" INTERFACE="S_AXI_CONTROL" NAME="REG" RANGE="64" USAGE="register">
+          <REGISTERS>
+            <REGISTER NAME="CTRL">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="0"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="read-write"/>
+            </REGISTER>
+            <REGISTER NAME="IP_ISR">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="12"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="read-write"/>
+            </REGISTER>
+            <REGISTER NAME="ap_return_1">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="16"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="read-only"/>
+            </REGISTER>
+            <REGISTER NAME="ap_return_2">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="20"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="read-only"/>
+            </REGISTER>
+            <REGISTER NAME="in1_1">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="28"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="write-only"/>
+              <PROPERTY NAME="DESCRIPTION" VALUE="Data signal of in1"/>
+            </REGISTER>
+            <REGISTER NAME="in1_2">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="32"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+              <PROPERTY NAME="ACCESS" VALUE="write-only"/>
+              <PROPERTY NAME="DESCRIPTION" VALUE="Data signal of in1"/>
+            </REGISTER>
+            <REGISTER NAME="size">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="40"/>
+              <PROPERTY NAME="SIZE" VALUE="16"/>
+              <PROPERTY NAME="ACCESS" VALUE="write-only"/>
+            </REGISTER>
+          </REGISTERS>
+        </ADDRESSBLOCK>
+        <ADDRESSBLOCK ACCESS="read-write" INTERFACE="S_AXI_STATS" NAME="REG" RANGE="64" USAGE="register">
+          <REGISTERS>
+            <REGISTER NAME="count">
+              <PROPERTY NAME="ADDRESS_OFFSET" VALUE="16"/>
+              <PROPERTY NAME="SIZE" VALUE="32"/>
+            </REGISTER>
+          </REGISTERS>
+        </ADDRESSBLOCK>
+      </ADDRESSBLOCKS>
+    </MODULE>
+  </MODULES>
+</EDKSYSTEM>
+"#;
+
+    fn argument(name: &str, offset: usize, bits: u32) -> HlsArgument {
+        HlsArgument {
+            name: name.to_string(),
+            offset,
+            bits,
+        }
+    }
+
+    #[test]
+    fn parse_header() {
+        assert_eq!(
+            HlsArgument::parse_header(HEADER),
+            Ok(vec![
+                argument("ap_return", 0x10, 64),
+                argument("in1", 0x1c, 64),
+                argument("size", 0x28, 32),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_header_narrow_return() {
+        let header = "\
+#define XADD_CONTROL_ADDR_AP_CTRL     0x00
+#define XADD_CONTROL_ADDR_AP_RETURN   0x10
+#define XADD_CONTROL_BITS_AP_RETURN   16
+#define XADD_CONTROL_ADDR_A_DATA      0x18
+";
+        assert_eq!(
+            HlsArgument::parse_header(header),
+            Ok(vec![
+                argument("ap_return", 0x10, 16),
+                argument("a", 0x18, 32),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_header_errors() {
+        let header = "#define XADD_CONTROL_ADDR_A_DATA 0x18\n";
+        assert_eq!(
+            HlsArgument::parse_header(header),
+            Err(Error::NotFound(Vec::new()))
+        );
+        let header = "\
+#define XADD_CONTROL_ADDR_AP_CTRL 0x00
+#define XADD_CONTROL_ADDR_A_DATA  0xz8
+";
+        assert_eq!(
+            HlsArgument::parse_header(header),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn from_ip() {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        let ip = handoff.instance("vadd_0").unwrap();
+        assert_eq!(control_range(ip).unwrap().base, 0xa000_0000);
+        // The halves of the return value and the pointer are combined, the control registers
+        // and other interfaces skipped
+        assert_eq!(
+            HlsArgument::from_ip(ip),
+            [
+                argument("ap_return", 0x10, 64),
+                argument("in1", 0x1c, 64),
+                argument("size", 0x28, 16),
+            ]
+        );
+    }
+}
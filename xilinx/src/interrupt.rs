@@ -0,0 +1,139 @@
+use crate::axi_intc::IntcSource;
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Interrupt of a UIO device, or of an [`AxiIntc`](crate::AxiIntc) source
+///
+/// The UIO driver masks the interrupt when it fires, it has to be enabled again before the next
+/// wait. The interrupt controller dispatcher masks sources the same way.
+pub struct Interrupt {
+    file: File,
+    source: Option<IntcSource>,
+}
+
+impl Interrupt {
+    /// Opens the interrupt of a UIO device node, `/dev/uioN`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Interrupt, Error> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        Ok(Interrupt { file, source: None })
+    }
+
+    /// Creates an interrupt of an interrupt controller source, signalled through an eventfd.
+    pub(crate) fn from_intc(file: File, source: IntcSource) -> Interrupt {
+        Interrupt {
+            file,
+            source: Some(source),
+        }
+    }
+
+    /// Opens the interrupt of a UIO device.
+    pub fn try_from_device(device: &DeviceInfo) -> Result<Interrupt, Error> {
+        Self::open(device.path())
+    }
+
+    /// Unmasks the interrupt.
+    pub fn enable(&self) -> Result<(), Error> {
+        if let Some(source) = &self.source {
+            return source.enable();
+        }
+        (&self.file).write_all(&1u32.to_ne_bytes())?;
+        Ok(())
+    }
+
+    /// Masks the interrupt.
+    pub fn disable(&self) -> Result<(), Error> {
+        if let Some(source) = &self.source {
+            return source.disable();
+        }
+        (&self.file).write_all(&0u32.to_ne_bytes())?;
+        Ok(())
+    }
+
+    /// Waits for the interrupt and returns the total interrupt count.
+    pub fn wait(&self) -> Result<u32, Error> {
+        if let Some(source) = &self.source {
+            // The eventfd counts the interrupts since the last read
+            let mut events = [0u8; 8];
+            (&self.file).read_exact(&mut events)?;
+            return Ok(source.count());
+        }
+        let mut count = [0u8; 4];
+        (&self.file).read_exact(&mut count)?;
+        Ok(u32::from_ne_bytes(count))
+    }
+
+    /// Waits for the interrupt at most `timeout` and returns the total interrupt count.
+    ///
+    /// Fails with [`Error::Timeout`] if no interrupt arrived in time.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<u32, Error> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let milliseconds = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+        let result = unsafe { libc::poll(&mut poll_fd, 1, milliseconds) };
+        match result {
+            0 => Err(Error::Timeout),
+            n if n < 0 => Err(std::io::Error::last_os_error().into()),
+            _ => self.wait(),
+        }
+    }
+}
+
+impl AsRawFd for Interrupt {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Waits until `poll` returns a value.
+///
+/// Between polls the thread sleeps on the interrupt when there is one, otherwise it yields. The
+/// interrupt is unmasked before each poll so that no interrupt is lost.
+pub(crate) fn wait_for<T, F>(
+    interrupt: Option<&Interrupt>,
+    timeout: Option<Duration>,
+    mut poll: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Result<Option<T>, Error>,
+{
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        if let Some(interrupt) = interrupt {
+            interrupt.enable()?;
+        }
+        if let Some(value) = poll()? {
+            return Ok(value);
+        }
+        let remaining = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::Timeout);
+                }
+                Some(deadline - now)
+            }
+            None => None,
+        };
+        match (interrupt, remaining) {
+            (Some(interrupt), Some(remaining)) => match interrupt.wait_timeout(remaining) {
+                Ok(_) | Err(Error::Timeout) => {}
+                Err(error) => return Err(error),
+            },
+            (Some(interrupt), None) => {
+                interrupt.wait()?;
+            }
+            (None, _) => std::thread::yield_now(),
+        }
+    }
+}
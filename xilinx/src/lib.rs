@@ -1,5 +1,76 @@
+mod axi_cdma;
+mod axi_dma;
+mod axi_gpio;
+mod axi_hwicap;
+mod axi_iic;
+mod axi_intc;
+mod axi_mailbox;
+mod axi_mutex;
+mod axi_perf_monitor;
+mod axi_quad_spi;
+mod axi_sysmon;
+mod axi_timer;
+mod axi_uartlite;
+mod axi_vdma;
+mod bitstream;
+mod clocking_wizard;
+pub mod devicetree;
+mod discovery;
+mod dma_buffer;
+mod dt_overlay;
 mod error;
+mod fpga_manager;
+mod handoff;
+mod hls_kernel;
+mod interrupt;
+mod mmio;
+mod overlay;
+pub mod register;
+mod spi_nor;
 mod stream_fifo;
+#[cfg(test)]
+mod test_util;
 
+pub use axi_cdma::{AxiCdma, CdmaDescriptorRing, CdmaStatus, MemoryCopy};
+pub use axi_dma::{
+    AxiDma, Coalesce, Completion, DESCRIPTOR_SIZE, DescriptorRing, DmaChannel, DmaStatus, Segment,
+};
+pub use axi_gpio::{AxiGpio, GpioChannel, GpioInterrupt};
+pub use axi_hwicap::{AxiHwicap, ConfigRegister, IcapFamily, IcapStatus};
+pub use axi_iic::{AxiIic, IicAddress, IicStatus};
+pub use axi_intc::{AxiIntc, IntcDispatcher};
+pub use axi_mailbox::{AxiMailbox, MailboxInterrupt, MailboxStatus};
+pub use axi_mutex::{AxiMutex, AxiMutexGuard};
+pub use axi_perf_monitor::{
+    APM_MAX_COUNTERS, ApmMetric, ApmSample, ApmSlotStatistics, AxiPerformanceMonitor,
+};
+pub use axi_quad_spi::{AxiQuadSpi, QuadSpiDevice, SpiMode, SpiStatus};
+pub use axi_sysmon::{
+    Averaging, AxiSysmon, SequencerMode, SysmonAlarm, SysmonChannel, SysmonFamily, SysmonInterrupt,
+};
+pub use axi_timer::{AxiTimer, TimerChannel};
+pub use axi_uartlite::{AxiUartLite, UART_LITE_FIFO_DEPTH, UartStatus};
+pub use axi_vdma::{AxiVdma, FrameFormat, Genlock, MAX_FRAME_STORES, VdmaStatus};
+pub use bitstream::Bitstream;
+pub use clocking_wizard::{
+    CLOCK_WIZARD_MAX_OUTPUTS, ClockPrimitive, ClockSettings, ClockingWizard,
+};
+pub use discovery::{DeviceInfo, Discovery, MapInfo, Selector};
+pub use dma_buffer::{DmaBuffer, DmaDirection};
+pub use dt_overlay::{DeviceTreeOverlay, DeviceTreeOverlays, OVERLAY_CONFIGFS_ROOT, OverlayStatus};
 pub use error::Error;
+pub use fpga_manager::{
+    FIRMWARE_ROOT, FPGA_FLAG_COMPRESSED, FPGA_FLAG_ENCRYPTED, FPGA_FLAG_EXTERNAL_CONFIG,
+    FPGA_FLAG_LSB_FIRST, FPGA_FLAG_PARTIAL, FPGA_MANAGER_CLASS_ROOT, FpgaManager, FpgaManagers,
+    FpgaState,
+};
+pub use handoff::{
+    AddressRange, HardwareHandoff, InterruptTarget, IpInstance, IpPort, IpRegister, PortConnection,
+    Vlnv,
+};
+pub use hls_kernel::{HlsArgument, HlsControl, HlsInterrupt, HlsKernel};
+pub use interrupt::Interrupt;
+pub use mmio::{Mmio, Region};
+pub use overlay::{DriverConstructor, DriverRegistry, Overlay};
+pub use spi_nor::{SPI_NOR_PAGE_SIZE, SPI_NOR_SECTOR_SIZE, SpiNor, SpiNorRead};
 pub use stream_fifo::{StreamFifo, StreamFifoValue};
@@ -0,0 +1,659 @@
+use crate::discovery::DeviceInfo;
+use crate::error::Error;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+
+/// Physical memory device
+pub const DEV_MEM: &str = "/dev/mem";
+
+/// Physical memory region
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Region {
+    /// Physical base address
+    pub address: u64,
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl Region {
+    /// Creates a region from base address and size.
+    pub fn new(address: u64, size: u64) -> Self {
+        Region { address, size }
+    }
+}
+
+/// Memory mapped IO region
+///
+/// Every read and write is performed as exactly one access of the requested width. Copying
+/// register contents through slices is not safe, `memcpy` in the aarch64 libc may read the same
+/// location several times, which pops extra words from FIFO data registers.
+/// <https://adaptivesupport.amd.com/s/question/0D54U00008Z19O5SAJ/why-are-my-uio-accesses-from-python-being-done-twice-in-the-logic-using-petalinuxvivado-20241?language=en_US>
+///
+/// On aarch64 the accesses are emitted with inline assembly, a 128-bit access is a single
+/// `ldp`/`stp` register pair. On other architectures volatile pointer accesses are used.
+pub struct Mmio {
+    base: *mut u8,
+    length: usize,
+    offset: usize,
+    size: usize,
+    address: u64,
+}
+
+// The mapping is owned and only accessed through volatile reads and writes.
+unsafe impl Send for Mmio {}
+
+impl Mmio {
+    /// Maps `size` bytes of physical memory starting at `address` through `/dev/mem`.
+    pub fn try_from_physical(address: u64, size: usize) -> Result<Mmio, Error> {
+        let page_size = page_size() as u64;
+        let page_address = address & !(page_size - 1);
+        Self::map(
+            Path::new(DEV_MEM),
+            address,
+            page_address,
+            (address - page_address) as usize,
+            size,
+        )
+    }
+
+    /// Maps a physical memory region through `/dev/mem`.
+    pub fn try_from_region(region: &Region) -> Result<Mmio, Error> {
+        Self::try_from_physical(region.address, region.size as usize)
+    }
+
+    /// Maps memory map `index` of a UIO device.
+    pub fn try_from_device(device: &DeviceInfo, index: usize) -> Result<Mmio, Error> {
+        let map = device.maps().get(index).ok_or(Error::NoMemoryMap)?;
+        Self::map(
+            &device.path(),
+            map.address,
+            (index * page_size()) as u64,
+            map.offset as usize,
+            map.size as usize,
+        )
+    }
+
+    fn map(
+        path: &Path,
+        address: u64,
+        page_offset: u64,
+        offset: usize,
+        size: usize,
+    ) -> Result<Mmio, Error> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_SYNC)
+            .open(path)?;
+        Self::map_fd(file.as_raw_fd(), address, page_offset, offset, size)
+    }
+
+    /// Maps `size` bytes of an open file descriptor at `page_offset` + `offset`.
+    ///
+    /// `page_offset` has to be page aligned, `address` is the physical address of the region.
+    pub(crate) fn map_fd(
+        fd: RawFd,
+        address: u64,
+        page_offset: u64,
+        offset: usize,
+        size: usize,
+    ) -> Result<Mmio, Error> {
+        let length = offset + size;
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                page_offset as libc::off_t,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Mmio {
+            base: base as *mut u8,
+            length,
+            offset,
+            size,
+            address,
+        })
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the physical address of the region.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    fn pointer<T>(&self, offset: usize) -> Result<*mut T, Error> {
+        let width = size_of::<T>();
+        if !offset.is_multiple_of(width) {
+            return Err(Error::Misaligned);
+        }
+        match offset.checked_add(width) {
+            Some(end) if end <= self.size => {}
+            _ => return Err(Error::OutOfRange),
+        }
+        Ok(unsafe { self.base.add(self.offset + offset) as *mut T })
+    }
+
+    /// Reads an 8-bit value at the offset.
+    pub fn read_u8(&self, offset: usize) -> Result<u8, Error> {
+        Ok(unsafe { access::read_u8(self.pointer(offset)?) })
+    }
+
+    /// Reads a 16-bit value at the offset.
+    pub fn read_u16(&self, offset: usize) -> Result<u16, Error> {
+        Ok(unsafe { access::read_u16(self.pointer(offset)?) })
+    }
+
+    /// Reads a 32-bit value at the offset.
+    pub fn read_u32(&self, offset: usize) -> Result<u32, Error> {
+        Ok(unsafe { access::read_u32(self.pointer(offset)?) })
+    }
+
+    /// Reads a 64-bit value at the offset.
+    pub fn read_u64(&self, offset: usize) -> Result<u64, Error> {
+        Ok(unsafe { access::read_u64(self.pointer(offset)?) })
+    }
+
+    /// Reads a 128-bit value at the offset.
+    pub fn read_u128(&self, offset: usize) -> Result<u128, Error> {
+        Ok(unsafe { access::read_u128(self.pointer(offset)?) })
+    }
+
+    /// Copies bytes starting at the offset into `data`.
+    ///
+    /// Naturally aligned 64-bit accesses are used where possible, byte accesses elsewhere, so
+    /// that no unaligned access reaches device memory.
+    pub fn read_bytes(&self, offset: usize, data: &mut [u8]) -> Result<(), Error> {
+        match offset.checked_add(data.len()) {
+            Some(end) if end <= self.size => {}
+            _ => return Err(Error::OutOfRange),
+        }
+        let mut n = 0;
+        while n < data.len() {
+            let position = offset + n;
+            if position.is_multiple_of(8) && data.len() - n >= 8 {
+                data[n..n + 8].copy_from_slice(&self.read_u64(position)?.to_ne_bytes());
+                n += 8;
+            } else {
+                data[n] = self.read_u8(position)?;
+                n += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `data` to the region starting at the offset.
+    ///
+    /// Naturally aligned 64-bit accesses are used where possible, byte accesses elsewhere.
+    pub fn write_bytes(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        match offset.checked_add(data.len()) {
+            Some(end) if end <= self.size => {}
+            _ => return Err(Error::OutOfRange),
+        }
+        let mut n = 0;
+        while n < data.len() {
+            let position = offset + n;
+            if position.is_multiple_of(8) && data.len() - n >= 8 {
+                let word = u64::from_ne_bytes(data[n..n + 8].try_into().unwrap());
+                self.write_u64(position, word)?;
+                n += 8;
+            } else {
+                self.write_u8(position, data[n])?;
+                n += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an 8-bit value at the offset.
+    pub fn write_u8(&mut self, offset: usize, value: u8) -> Result<(), Error> {
+        unsafe { access::write_u8(self.pointer(offset)?, value) };
+        Ok(())
+    }
+
+    /// Writes a 16-bit value at the offset.
+    pub fn write_u16(&mut self, offset: usize, value: u16) -> Result<(), Error> {
+        unsafe { access::write_u16(self.pointer(offset)?, value) };
+        Ok(())
+    }
+
+    /// Writes a 32-bit value at the offset.
+    pub fn write_u32(&mut self, offset: usize, value: u32) -> Result<(), Error> {
+        unsafe { access::write_u32(self.pointer(offset)?, value) };
+        Ok(())
+    }
+
+    /// Writes a 64-bit value at the offset.
+    pub fn write_u64(&mut self, offset: usize, value: u64) -> Result<(), Error> {
+        unsafe { access::write_u64(self.pointer(offset)?, value) };
+        Ok(())
+    }
+
+    /// Writes a 128-bit value at the offset.
+    pub fn write_u128(&mut self, offset: usize, value: u128) -> Result<(), Error> {
+        unsafe { access::write_u128(self.pointer(offset)?, value) };
+        Ok(())
+    }
+}
+
+impl Drop for Mmio {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.length);
+        }
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(not(test))]
+use hardware as access;
+
+#[cfg(target_arch = "aarch64")]
+mod hardware {
+    use std::arch::asm;
+
+    pub unsafe fn read_u8(pointer: *const u8) -> u8 {
+        let value: u32;
+        unsafe {
+            asm!("ldrb {v:w}, [{p}]", p = in(reg) pointer, v = out(reg) value,
+                options(nostack, preserves_flags));
+        }
+        value as u8
+    }
+
+    pub unsafe fn read_u16(pointer: *const u16) -> u16 {
+        let value: u32;
+        unsafe {
+            asm!("ldrh {v:w}, [{p}]", p = in(reg) pointer, v = out(reg) value,
+                options(nostack, preserves_flags));
+        }
+        value as u16
+    }
+
+    pub unsafe fn read_u32(pointer: *const u32) -> u32 {
+        let value: u32;
+        unsafe {
+            asm!("ldr {v:w}, [{p}]", p = in(reg) pointer, v = out(reg) value,
+                options(nostack, preserves_flags));
+        }
+        value
+    }
+
+    pub unsafe fn read_u64(pointer: *const u64) -> u64 {
+        let value: u64;
+        unsafe {
+            asm!("ldr {v:x}, [{p}]", p = in(reg) pointer, v = out(reg) value,
+                options(nostack, preserves_flags));
+        }
+        value
+    }
+
+    pub unsafe fn read_u128(pointer: *const u128) -> u128 {
+        let low: u64;
+        let high: u64;
+        unsafe {
+            asm!("ldp {l:x}, {h:x}, [{p}]", p = in(reg) pointer, l = out(reg) low,
+                h = out(reg) high, options(nostack, preserves_flags));
+        }
+        (u128::from(high) << 64) | u128::from(low)
+    }
+
+    pub unsafe fn write_u8(pointer: *mut u8, value: u8) {
+        unsafe {
+            asm!("strb {v:w}, [{p}]", p = in(reg) pointer, v = in(reg) u32::from(value),
+                options(nostack, preserves_flags));
+        }
+    }
+
+    pub unsafe fn write_u16(pointer: *mut u16, value: u16) {
+        unsafe {
+            asm!("strh {v:w}, [{p}]", p = in(reg) pointer, v = in(reg) u32::from(value),
+                options(nostack, preserves_flags));
+        }
+    }
+
+    pub unsafe fn write_u32(pointer: *mut u32, value: u32) {
+        unsafe {
+            asm!("str {v:w}, [{p}]", p = in(reg) pointer, v = in(reg) value,
+                options(nostack, preserves_flags));
+        }
+    }
+
+    pub unsafe fn write_u64(pointer: *mut u64, value: u64) {
+        unsafe {
+            asm!("str {v:x}, [{p}]", p = in(reg) pointer, v = in(reg) value,
+                options(nostack, preserves_flags));
+        }
+    }
+
+    pub unsafe fn write_u128(pointer: *mut u128, value: u128) {
+        unsafe {
+            asm!("stp {l:x}, {h:x}, [{p}]", p = in(reg) pointer, l = in(reg) value as u64,
+                h = in(reg) (value >> 64) as u64, options(nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+mod hardware {
+    pub unsafe fn read_u8(pointer: *const u8) -> u8 {
+        unsafe { pointer.read_volatile() }
+    }
+
+    pub unsafe fn read_u16(pointer: *const u16) -> u16 {
+        unsafe { pointer.read_volatile() }
+    }
+
+    pub unsafe fn read_u32(pointer: *const u32) -> u32 {
+        unsafe { pointer.read_volatile() }
+    }
+
+    pub unsafe fn read_u64(pointer: *const u64) -> u64 {
+        unsafe { pointer.read_volatile() }
+    }
+
+    pub unsafe fn read_u128(pointer: *const u128) -> u128 {
+        unsafe { pointer.read_volatile() }
+    }
+
+    pub unsafe fn write_u8(pointer: *mut u8, value: u8) {
+        unsafe { pointer.write_volatile(value) }
+    }
+
+    pub unsafe fn write_u16(pointer: *mut u16, value: u16) {
+        unsafe { pointer.write_volatile(value) }
+    }
+
+    pub unsafe fn write_u32(pointer: *mut u32, value: u32) {
+        unsafe { pointer.write_volatile(value) }
+    }
+
+    pub unsafe fn write_u64(pointer: *mut u64, value: u64) {
+        unsafe { pointer.write_volatile(value) }
+    }
+
+    pub unsafe fn write_u128(pointer: *mut u128, value: u128) {
+        unsafe { pointer.write_volatile(value) }
+    }
+}
+
+/// Accessors recording every access before passing it on to the hardware accessors, so the
+/// tests can check the emitted access pattern
+#[cfg(test)]
+mod access {
+    use super::hardware;
+    use std::cell::RefCell;
+
+    /// A single access to the mapping
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Access {
+        /// Write or read
+        pub write: bool,
+        /// Address of the access
+        pub address: usize,
+        /// Width in bytes
+        pub width: usize,
+    }
+
+    thread_local! {
+        static ACCESSES: RefCell<Vec<Access>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Returns and clears the accesses recorded on this thread.
+    pub fn take() -> Vec<Access> {
+        ACCESSES.with(|accesses| accesses.take())
+    }
+
+    fn record<T>(write: bool, pointer: *const T) {
+        let access = Access {
+            write,
+            address: pointer as usize,
+            width: size_of::<T>(),
+        };
+        ACCESSES.with(|accesses| accesses.borrow_mut().push(access));
+    }
+
+    macro_rules! recorded {
+        ($($read:ident, $write:ident, $t:ty;)*) => {
+            $(
+                pub unsafe fn $read(pointer: *const $t) -> $t {
+                    record(false, pointer);
+                    unsafe { hardware::$read(pointer) }
+                }
+
+                pub unsafe fn $write(pointer: *mut $t, value: $t) {
+                    record(true, pointer);
+                    unsafe { hardware::$write(pointer, value) }
+                }
+            )*
+        };
+    }
+
+    recorded! {
+        read_u8, write_u8, u8;
+        read_u16, write_u16, u16;
+        read_u32, write_u32, u32;
+        read_u64, write_u64, u64;
+        read_u128, write_u128, u128;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    /// Offset of the region into the mapped page, as for a UIO map with an offset
+    const REGION_OFFSET: usize = 0x10;
+    /// Size of the region
+    const REGION_SIZE: usize = 0x100;
+    /// Pattern filling the backing file, to detect bytes touched by an access
+    const FILL: u8 = 0xa5;
+
+    /// Maps a region of a temporary file filled with the pattern.
+    fn backed_region() -> (std::fs::File, Mmio) {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&vec![FILL; page_size()]).unwrap();
+        let mmio =
+            Mmio::map_fd(file.as_raw_fd(), 0x4000_0000, 0, REGION_OFFSET, REGION_SIZE).unwrap();
+        (file, mmio)
+    }
+
+    /// Returns the region contents as stored in the backing file.
+    fn backing(file: &mut std::fs::File) -> Vec<u8> {
+        let mut contents = vec![0; REGION_SIZE];
+        file.seek(SeekFrom::Start(REGION_OFFSET as u64)).unwrap();
+        file.read_exact(&mut contents).unwrap();
+        contents
+    }
+
+    /// Checks that exactly `width` bytes at `offset` hold `bytes` and nothing else changed.
+    fn assert_written(file: &mut std::fs::File, offset: usize, bytes: &[u8]) {
+        let contents = backing(file);
+        for (position, byte) in contents.iter().enumerate() {
+            if (offset..offset + bytes.len()).contains(&position) {
+                assert_eq!(*byte, bytes[position - offset], "byte {:#x}", position);
+            } else {
+                assert_eq!(*byte, FILL, "byte {:#x} touched", position);
+            }
+        }
+    }
+
+    #[test]
+    fn properties() {
+        let (_file, mmio) = backed_region();
+        assert_eq!(mmio.size(), REGION_SIZE);
+        assert_eq!(mmio.address(), 0x4000_0000);
+    }
+
+    #[test]
+    fn write_single_width() {
+        let (mut file, mut mmio) = backed_region();
+        mmio.write_u8(0x21, 0x12).unwrap();
+        assert_written(&mut file, 0x21, &[0x12]);
+        let (mut file, mut mmio) = backed_region();
+        mmio.write_u16(0x22, 0x1234).unwrap();
+        assert_written(&mut file, 0x22, &0x1234u16.to_ne_bytes());
+        let (mut file, mut mmio) = backed_region();
+        mmio.write_u32(0x24, 0x1234_5678).unwrap();
+        assert_written(&mut file, 0x24, &0x1234_5678u32.to_ne_bytes());
+        let (mut file, mut mmio) = backed_region();
+        mmio.write_u64(0x28, 0x0123_4567_89ab_cdef).unwrap();
+        assert_written(&mut file, 0x28, &0x0123_4567_89ab_cdefu64.to_ne_bytes());
+        let (mut file, mut mmio) = backed_region();
+        let value = 0x0011_2233_4455_6677_8899_aabb_ccdd_eeffu128;
+        mmio.write_u128(0x30, value).unwrap();
+        assert_written(&mut file, 0x30, &value.to_ne_bytes());
+    }
+
+    #[test]
+    fn read_single_width() {
+        let (mut file, mmio) = backed_region();
+        let pattern: Vec<u8> = (0..REGION_SIZE).map(|byte| byte as u8).collect();
+        file.seek(SeekFrom::Start(REGION_OFFSET as u64)).unwrap();
+        file.write_all(&pattern).unwrap();
+        let bytes = |offset: usize, width: usize| &pattern[offset..offset + width];
+        assert_eq!(mmio.read_u8(0x41).unwrap(), 0x41);
+        assert_eq!(
+            mmio.read_u16(0x42).unwrap(),
+            u16::from_ne_bytes(bytes(0x42, 2).try_into().unwrap())
+        );
+        assert_eq!(
+            mmio.read_u32(0x44).unwrap(),
+            u32::from_ne_bytes(bytes(0x44, 4).try_into().unwrap())
+        );
+        assert_eq!(
+            mmio.read_u64(0x48).unwrap(),
+            u64::from_ne_bytes(bytes(0x48, 8).try_into().unwrap())
+        );
+        assert_eq!(
+            mmio.read_u128(0x50).unwrap(),
+            u128::from_ne_bytes(bytes(0x50, 16).try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn misaligned() {
+        let (mut file, mut mmio) = backed_region();
+        assert_eq!(mmio.read_u16(0x01), Err(Error::Misaligned));
+        assert_eq!(mmio.read_u32(0x02), Err(Error::Misaligned));
+        assert_eq!(mmio.read_u64(0x04), Err(Error::Misaligned));
+        assert_eq!(mmio.read_u128(0x08), Err(Error::Misaligned));
+        assert_eq!(mmio.write_u32(0x06, 0), Err(Error::Misaligned));
+        assert_eq!(mmio.write_u128(0x18, 0), Err(Error::Misaligned));
+        assert_written(&mut file, 0, &[]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let (mut file, mut mmio) = backed_region();
+        // The last access that fits
+        assert!(mmio.read_u32(REGION_SIZE - 4).is_ok());
+        assert!(mmio.read_u128(REGION_SIZE - 16).is_ok());
+        assert_eq!(mmio.read_u32(REGION_SIZE), Err(Error::OutOfRange));
+        assert_eq!(mmio.read_u8(REGION_SIZE), Err(Error::OutOfRange));
+        assert_eq!(mmio.write_u64(REGION_SIZE, 0), Err(Error::OutOfRange));
+        // Offsets where the end of the access overflows
+        assert_eq!(mmio.read_u8(usize::MAX), Err(Error::OutOfRange));
+        assert_eq!(mmio.read_u32(usize::MAX - 3), Err(Error::OutOfRange));
+        assert_eq!(mmio.write_u128(usize::MAX - 15, 0), Err(Error::OutOfRange));
+        let mut data = [0u8; 4];
+        assert_eq!(
+            mmio.read_bytes(REGION_SIZE - 3, &mut data),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(
+            mmio.read_bytes(usize::MAX - 1, &mut data),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(
+            mmio.write_bytes(usize::MAX - 1, &data),
+            Err(Error::OutOfRange)
+        );
+        assert_written(&mut file, 0, &[]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let (mut file, mut mmio) = backed_region();
+        let data: Vec<u8> = (1..=21).collect();
+        mmio.write_bytes(0x63, &data).unwrap();
+        assert_written(&mut file, 0x63, &data);
+        let mut read = vec![0; data.len()];
+        mmio.read_bytes(0x63, &mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    /// Returns the recorded accesses as (write, offset into the region, width).
+    fn accesses(mmio: &Mmio) -> Vec<(bool, usize, usize)> {
+        let start = mmio.base as usize + mmio.offset;
+        access::take()
+            .into_iter()
+            .map(|access| (access.write, access.address - start, access.width))
+            .collect()
+    }
+
+    #[test]
+    fn single_access_per_call() {
+        let (_file, mut mmio) = backed_region();
+        access::take();
+        mmio.read_u8(0x01).unwrap();
+        assert_eq!(accesses(&mmio), [(false, 0x01, 1)]);
+        mmio.read_u16(0x02).unwrap();
+        assert_eq!(accesses(&mmio), [(false, 0x02, 2)]);
+        mmio.read_u32(0x04).unwrap();
+        assert_eq!(accesses(&mmio), [(false, 0x04, 4)]);
+        mmio.read_u64(0x08).unwrap();
+        assert_eq!(accesses(&mmio), [(false, 0x08, 8)]);
+        mmio.read_u128(0x10).unwrap();
+        assert_eq!(accesses(&mmio), [(false, 0x10, 16)]);
+        mmio.write_u8(0x21, 0).unwrap();
+        assert_eq!(accesses(&mmio), [(true, 0x21, 1)]);
+        mmio.write_u16(0x22, 0).unwrap();
+        assert_eq!(accesses(&mmio), [(true, 0x22, 2)]);
+        mmio.write_u32(0x24, 0).unwrap();
+        assert_eq!(accesses(&mmio), [(true, 0x24, 4)]);
+        mmio.write_u64(0x28, 0).unwrap();
+        assert_eq!(accesses(&mmio), [(true, 0x28, 8)]);
+        mmio.write_u128(0x30, 0).unwrap();
+        assert_eq!(accesses(&mmio), [(true, 0x30, 16)]);
+        // Rejected calls do not reach the mapping
+        assert!(mmio.read_u32(0x02).is_err());
+        assert!(mmio.write_u64(REGION_SIZE, 0).is_err());
+        assert_eq!(accesses(&mmio), []);
+    }
+
+    #[test]
+    fn bytes_access_pattern() {
+        let (_file, mut mmio) = backed_region();
+        access::take();
+        let mut data = [0u8; 21];
+        mmio.read_bytes(0x63, &mut data).unwrap();
+        let expected = [
+            (false, 0x63, 1),
+            (false, 0x64, 1),
+            (false, 0x65, 1),
+            (false, 0x66, 1),
+            (false, 0x67, 1),
+            (false, 0x68, 8),
+            (false, 0x70, 8),
+        ];
+        assert_eq!(accesses(&mmio), expected);
+        mmio.write_bytes(0x63, &data).unwrap();
+        let written: Vec<_> = expected.iter().map(|(_, o, w)| (true, *o, *w)).collect();
+        assert_eq!(accesses(&mmio), written);
+    }
+}
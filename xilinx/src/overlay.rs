@@ -0,0 +1,319 @@
+use crate::bitstream::Bitstream;
+use crate::error::Error;
+use crate::fpga_manager::{FpgaManager, FpgaManagers, firmware_data};
+use crate::handoff::{HardwareHandoff, IpInstance, Vlnv};
+use crate::hls_kernel::HlsKernel;
+use crate::stream_fifo::StreamFifo;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Creates a driver for an IP instance of a hardware handoff
+pub type DriverConstructor = fn(&IpInstance) -> Result<Box<dyn Any + Send>, Error>;
+
+/// Driver constructors by VLNV
+///
+/// Keys are either a full VLNV such as `xilinx.com:ip:axi_fifo_mm_s:4.3`, `vendor:library:name`
+/// matching every version, or `vendor:library` matching every core of the library. The most
+/// specific key takes precedence.
+#[derive(Clone, Debug)]
+pub struct DriverRegistry {
+    constructors: BTreeMap<String, DriverConstructor>,
+}
+
+impl Default for DriverRegistry {
+    /// Creates a registry with the drivers of this crate that can be built from a handoff.
+    fn default() -> Self {
+        let mut registry = DriverRegistry::empty();
+        registry.register("xilinx.com:ip:axi_fifo_mm_s", |ip| {
+            Ok(Box::new(StreamFifo::try_from_ip(ip)?))
+        });
+        registry.register("xilinx.com:hls", |ip| {
+            Ok(Box::new(HlsKernel::try_from_ip(ip)?))
+        });
+        registry
+    }
+}
+
+impl DriverRegistry {
+    /// Creates a registry without drivers.
+    pub fn empty() -> Self {
+        DriverRegistry {
+            constructors: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a driver constructor, replacing an earlier one for the same key.
+    pub fn register(&mut self, vlnv: &str, constructor: DriverConstructor) {
+        self.constructors.insert(vlnv.to_string(), constructor);
+    }
+
+    /// Returns the constructor for the VLNV.
+    pub fn constructor(&self, vlnv: &Vlnv) -> Option<DriverConstructor> {
+        let versionless = format!("{}:{}:{}", vlnv.vendor, vlnv.library, vlnv.name);
+        let library = format!("{}:{}", vlnv.vendor, vlnv.library);
+        self.constructors
+            .get(&vlnv.to_string())
+            .or_else(|| self.constructors.get(&versionless))
+            .or_else(|| self.constructors.get(&library))
+            .copied()
+    }
+
+    /// Creates the driver of an IP instance.
+    ///
+    /// Fails with [`Error::Unsupported`] when no driver is registered for its VLNV.
+    pub fn construct(&self, ip: &IpInstance) -> Result<Box<dyn Any + Send>, Error> {
+        let constructor = self.constructor(ip.vlnv()).ok_or_else(|| {
+            log::warn!("no driver for {}", ip);
+            Error::Unsupported
+        })?;
+        constructor(ip)
+    }
+}
+
+/// Loaded hardware design, a bitstream with its hardware handoff
+///
+/// Modelled after the PYNQ overlay: loading `design.bit` programs the FPGA and reads
+/// `design.hwh` from the same directory, drivers are then created by IP instance name.
+///
+/// ```no_run
+/// use plrs_xilinx::{Overlay, StreamFifo};
+///
+/// let overlay = Overlay::load("design.bit")?;
+/// let mut fifo = overlay.ip::<StreamFifo>("axi_fifo_mm_s_0")?;
+/// fifo.reset()?;
+/// # Ok::<(), plrs_xilinx::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    bitstream: Option<PathBuf>,
+    handoff: HardwareHandoff,
+    registry: DriverRegistry,
+}
+
+impl Overlay {
+    /// Programs the bitstream through the first FPGA manager and reads its `.hwh`.
+    pub fn load<P: AsRef<Path>>(bitstream: P) -> Result<Overlay, Error> {
+        Self::load_with(bitstream, &FpgaManagers::default().first()?)
+    }
+
+    /// Programs the bitstream through the FPGA manager and reads its `.hwh`.
+    ///
+    /// The handoff and the bitstream are read before programming, a missing or invalid file
+    /// leaves the FPGA untouched. The bitstream is installed as a `.bin` in the firmware search
+    /// directory.
+    ///
+    /// Fails with [`Error::Busy`] if a different file with that name is already in the firmware
+    /// search directory, an identical one is reused.
+    pub fn load_with<P: AsRef<Path>>(
+        bitstream: P,
+        manager: &FpgaManager,
+    ) -> Result<Overlay, Error> {
+        let path = bitstream.as_ref();
+        let handoff = HardwareHandoff::open(path.with_extension("hwh"))?;
+        let bitstream = Bitstream::open(path)?;
+        let name = path
+            .with_extension("bin")
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+            .ok_or(Error::InvalidArgument)?;
+        match std::fs::read(manager.firmware_root().join(&name)) {
+            Ok(existing) if existing != firmware_data(&bitstream) => {
+                log::warn!(
+                    "{} exists in the firmware directory, not replacing it",
+                    name
+                );
+                return Err(Error::Busy);
+            }
+            Ok(_) => log::debug!("{} already installed", name),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        log::debug!("load {} through {}", path.display(), manager.device());
+        manager.program_bitstream(&bitstream, &name, false)?;
+        let mut overlay = Self::from_handoff(handoff);
+        overlay.bitstream = Some(path.to_path_buf());
+        Ok(overlay)
+    }
+
+    /// Creates an overlay for a design that is already loaded.
+    pub fn from_handoff(handoff: HardwareHandoff) -> Overlay {
+        Overlay {
+            bitstream: None,
+            handoff,
+            registry: DriverRegistry::default(),
+        }
+    }
+
+    /// Returns the loaded bitstream, `None` when created from a handoff.
+    pub fn bitstream(&self) -> Option<&Path> {
+        self.bitstream.as_deref()
+    }
+
+    /// Returns the hardware handoff.
+    pub fn handoff(&self) -> &HardwareHandoff {
+        &self.handoff
+    }
+
+    /// Returns the driver registry.
+    pub fn registry(&self) -> &DriverRegistry {
+        &self.registry
+    }
+
+    /// Returns the driver registry to register additional drivers.
+    pub fn registry_mut(&mut self) -> &mut DriverRegistry {
+        &mut self.registry
+    }
+
+    /// Creates the driver of the IP instance.
+    ///
+    /// Fails with [`Error::NotFound`] when there is no instance with the name, with
+    /// [`Error::Unsupported`] when no driver is registered for it and with
+    /// [`Error::InvalidArgument`] when the registered driver is not a `T`.
+    pub fn ip<T: Any>(&self, name: &str) -> Result<T, Error> {
+        let ip = self.handoff.instance(name)?;
+        match self.registry.construct(ip)?.downcast::<T>() {
+            Ok(driver) => Ok(*driver),
+            Err(_) => {
+                log::warn!("{} is not a {}", ip, std::any::type_name::<T>());
+                Err(Error::InvalidArgument)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeRoot, fake_root};
+    use std::fs;
+
+    const HWH: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<EDKSYSTEM VIVADOVERSION="2024.1">
+  <SYSTEMINFO DEVICE="xczu3eg" NAME="design" PACKAGE="sbva484" SPEEDGRADE="-1"/>
+  <MODULES>
+    <MODULE INSTANCE="counter_0" IPTYPE="PERIPHERAL" VLNV="example.com:user:counter:1.2"/>
+    <MODULE INSTANCE="blinker_0" IPTYPE="PERIPHERAL" VLNV="example.com:other:blinker:1.0"/>
+  </MODULES>
+</EDKSYSTEM>
+"#;
+
+    /// Raw bitstream with a dummy word, the sync word and a NOOP
+    const BITSTREAM: [u8; 12] = [
+        0xff, 0xff, 0xff, 0xff, 0xaa, 0x99, 0x55, 0x66, 0x20, 0x00, 0x00, 0x00,
+    ];
+
+    fn constructed(registry: &DriverRegistry, vlnv: &str) -> Option<&'static str> {
+        let handoff = HardwareHandoff::parse(HWH).unwrap();
+        let ip = handoff.instance("counter_0").unwrap();
+        let constructor = registry.constructor(&Vlnv::parse(vlnv).unwrap())?;
+        Some(*constructor(ip).unwrap().downcast::<&str>().unwrap())
+    }
+
+    #[test]
+    fn constructor_precedence() {
+        let mut registry = DriverRegistry::empty();
+        assert_eq!(constructed(&registry, "example.com:user:counter:1.2"), None);
+        registry.register("example.com:user", |_| Ok(Box::new("library")));
+        assert_eq!(
+            constructed(&registry, "example.com:user:counter:1.2"),
+            Some("library")
+        );
+        registry.register("example.com:user:counter", |_| Ok(Box::new("versionless")));
+        registry.register("example.com:user:counter:1.2", |_| Ok(Box::new("full")));
+        assert_eq!(
+            constructed(&registry, "example.com:user:counter:1.2"),
+            Some("full")
+        );
+        assert_eq!(
+            constructed(&registry, "example.com:user:counter:1.3"),
+            Some("versionless")
+        );
+        assert_eq!(
+            constructed(&registry, "example.com:user:timer:1.2"),
+            Some("library")
+        );
+        assert_eq!(
+            constructed(&registry, "example.com:other:counter:1.2"),
+            None
+        );
+        // A later registration replaces the constructor of the same key
+        registry.register("example.com:user", |_| Ok(Box::new("replaced")));
+        assert_eq!(
+            constructed(&registry, "example.com:user:timer:1.2"),
+            Some("replaced")
+        );
+    }
+
+    #[test]
+    fn ip_downcast() {
+        let mut overlay = Overlay::from_handoff(HardwareHandoff::parse(HWH).unwrap());
+        assert_eq!(overlay.bitstream(), None);
+        overlay
+            .registry_mut()
+            .register("example.com:user:counter", |_| Ok(Box::new(42u32)));
+        assert_eq!(overlay.ip::<u32>("counter_0"), Ok(42));
+        assert_eq!(overlay.ip::<u64>("counter_0"), Err(Error::InvalidArgument));
+        assert_eq!(overlay.ip::<u32>("blinker_0"), Err(Error::Unsupported));
+        assert!(matches!(
+            overlay.ip::<u32>("timer_0"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    /// Returns a fake FPGA manager and the path of a design with its handoff.
+    fn fake_design() -> (FakeRoot, (FpgaManager, PathBuf)) {
+        fake_root(|root| {
+            root.write("fpga0/state", "operating\n");
+            root.write("fpga0/flags", "0\n");
+            root.write("fpga0/firmware", "");
+            root.create_dir("firmware");
+            root.write("designs/design.hwh", HWH);
+            let bitstream = root.write("designs/design.bit", BITSTREAM);
+            let manager = FpgaManager::new(root.join("fpga0"), root.join("firmware"));
+            (manager, bitstream)
+        })
+    }
+
+    #[test]
+    fn load_with() {
+        let (root, (manager, bitstream)) = fake_design();
+        let overlay = Overlay::load_with(&bitstream, &manager).unwrap();
+        assert_eq!(overlay.bitstream(), Some(bitstream.as_path()));
+        assert_eq!(overlay.handoff().name(), "design");
+        let installed = root.join("firmware/design.bin");
+        let data = fs::read(&installed).unwrap();
+        assert_eq!(data[4..8], [0x66, 0x55, 0x99, 0xaa]);
+        assert_eq!(
+            fs::read_to_string(root.join("fpga0/firmware")).unwrap(),
+            "design.bin"
+        );
+        // Loading the same design again reuses the installed file
+        Overlay::load_with(&bitstream, &manager).unwrap();
+        assert_eq!(fs::read(&installed).unwrap(), data);
+    }
+
+    #[test]
+    fn load_with_existing_firmware() {
+        let (root, (manager, bitstream)) = fake_design();
+        let installed = root.write("firmware/design.bin", [1, 2, 3, 4]);
+        assert_eq!(
+            Overlay::load_with(&bitstream, &manager).map(|o| o.handoff().name().to_string()),
+            Err(Error::Busy)
+        );
+        assert_eq!(fs::read(installed).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(fs::read_to_string(root.join("fpga0/firmware")).unwrap(), "");
+    }
+
+    #[test]
+    fn load_with_missing_handoff() {
+        let (root, (manager, bitstream)) = fake_design();
+        fs::remove_file(root.join("designs/design.hwh")).unwrap();
+        assert!(matches!(
+            Overlay::load_with(&bitstream, &manager),
+            Err(Error::Io(std::io::ErrorKind::NotFound))
+        ));
+        assert!(!root.join("firmware/design.bin").exists());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::error::Error;
+use crate::mmio::Mmio;
+use std::marker::PhantomData;
+
+/// Value stored in a 32-bit register
+pub trait RegisterValue: Copy {
+    /// Creates the value from raw register bits.
+    fn from_bits(bits: u32) -> Self;
+    /// Returns the raw register bits.
+    fn bits(self) -> u32;
+}
+
+impl RegisterValue for u32 {
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+
+    fn bits(self) -> u32 {
+        self
+    }
+}
+
+/// Access permission of a register
+pub trait Access {}
+/// Permission allowing register reads
+pub trait Readable: Access {}
+/// Permission allowing register writes
+pub trait Writable: Access {}
+/// Permission allowing read-modify-write of a register
+pub trait Modifiable: Readable + Writable {}
+
+/// Read-only register
+#[derive(Clone, Copy, Debug)]
+pub struct RO;
+/// Write-only register
+#[derive(Clone, Copy, Debug)]
+pub struct WO;
+/// Read-write register
+#[derive(Clone, Copy, Debug)]
+pub struct RW;
+/// Register where writing a one clears the bit
+///
+/// Read-modify-write is not allowed since writing back set bits clears them.
+#[derive(Clone, Copy, Debug)]
+pub struct W1C;
+/// Register where writing a one toggles the bit, such as the interrupt status registers of the
+/// AXI IP interrupt controller
+///
+/// Writing a one to a clear bit sets it, so only bits read as set may be written back. Use
+/// [`Mmio::acknowledge`] and [`Mmio::acknowledge_bits`] instead of plain writes.
+#[derive(Clone, Copy, Debug)]
+pub struct TOW;
+
+impl Access for RO {}
+impl Access for WO {}
+impl Access for RW {}
+impl Access for W1C {}
+impl Access for TOW {}
+impl Readable for RO {}
+impl Readable for RW {}
+impl Readable for W1C {}
+impl Readable for TOW {}
+impl Writable for WO {}
+impl Writable for RW {}
+impl Writable for W1C {}
+impl Modifiable for RW {}
+
+/// 32-bit register at an offset in a memory mapped region
+pub struct Register<T, A> {
+    offset: usize,
+    reset: u32,
+    _marker: PhantomData<fn() -> (T, A)>,
+}
+
+impl<T, A> Clone for Register<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, A> Copy for Register<T, A> {}
+
+impl<T: RegisterValue, A: Access> Register<T, A> {
+    /// Creates a register definition.
+    pub const fn new(offset: usize, reset: u32) -> Self {
+        Register {
+            offset,
+            reset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the register offset in bytes.
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the same register `delta` bytes further into the region.
+    ///
+    /// Used for register sets repeated at a fixed stride, such as channels.
+    pub const fn shifted(self, delta: usize) -> Self {
+        Register {
+            offset: self.offset + delta,
+            reset: self.reset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the reset value of the register.
+    pub fn reset(&self) -> T {
+        T::from_bits(self.reset)
+    }
+}
+
+impl Mmio {
+    /// Reads a register.
+    pub fn read<T: RegisterValue, A: Readable>(&self, register: Register<T, A>) -> Result<T, Error> {
+        Ok(T::from_bits(self.read_u32(register.offset)?))
+    }
+
+    /// Writes a register.
+    pub fn write<T: RegisterValue, A: Writable>(
+        &mut self,
+        register: Register<T, A>,
+        value: T,
+    ) -> Result<(), Error> {
+        self.write_u32(register.offset, value.bits())
+    }
+
+    /// Reads a register, lets `f` modify the value and writes it back.
+    pub fn modify<T: RegisterValue, A: Modifiable, F: FnOnce(&mut T)>(
+        &mut self,
+        register: Register<T, A>,
+        f: F,
+    ) -> Result<(), Error> {
+        let mut value = self.read(register)?;
+        f(&mut value);
+        self.write(register, value)
+    }
+
+    /// Reads a toggle-on-write register and clears the set bits by writing them back.
+    ///
+    /// Returns the bits that were set.
+    pub fn acknowledge<T: RegisterValue>(
+        &mut self,
+        register: Register<T, TOW>,
+    ) -> Result<T, Error> {
+        let pending = self.read(register)?;
+        self.acknowledge_bits(register, pending)?;
+        Ok(pending)
+    }
+
+    /// Clears `bits` of a toggle-on-write register.
+    ///
+    /// `bits` have to be set in the register, as read before, since writing a one to a clear
+    /// bit sets it. Nothing is written when no bit is given.
+    pub fn acknowledge_bits<T: RegisterValue>(
+        &mut self,
+        register: Register<T, TOW>,
+        bits: T,
+    ) -> Result<(), Error> {
+        if bits.bits() == 0 {
+            return Ok(());
+        }
+        self.write_u32(register.offset, bits.bits())
+    }
+
+    /// Writes the reset value of a register.
+    pub fn write_reset<T: RegisterValue, A: Writable>(
+        &mut self,
+        register: Register<T, A>,
+    ) -> Result<(), Error> {
+        self.write(register, register.reset())
+    }
+}
+
+/// Defines a register map as constants of type [`Register`].
+///
+/// Each register is declared as `NAME: Type, Access @ offset` with an optional `= reset` value,
+/// where `Access` is one of [`RO`], [`WO`], [`RW`], [`W1C`] or [`TOW`].
+///
+/// ```
+/// plrs_xilinx::register_map! {
+///     /// Example registers
+///     pub struct Registers {
+///         /// Control register
+///         CONTROL: u32, RW @ 0x00 = 0x0000_0001;
+///         /// Status register
+///         STATUS: u32, RO @ 0x04;
+///     }
+/// }
+/// assert_eq!(Registers::STATUS.offset(), 0x04);
+/// ```
+#[macro_export]
+macro_rules! register_map {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$register_meta:meta])*
+                $register:ident : $value:ty , $access:ident @ $offset:literal $(= $reset:expr)? ;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug)]
+        $vis struct $name;
+
+        #[allow(dead_code)]
+        impl $name {
+            $(
+                $(#[$register_meta])*
+                pub const $register: $crate::register::Register<$value, $crate::register::$access> =
+                    $crate::register::Register::new($offset, 0 $(| $reset)?);
+            )*
+        }
+    };
+}
+
+/// Defines a 32-bit register value with named bit fields.
+///
+/// Each field is declared as `MASK: getter, setter: bit` for a single bit flag, or
+/// `MASK: getter, setter: msb, lsb` for a multi-bit field. `MASK` is a constant of the value type
+/// with the bits of the field set.
+///
+/// ```
+/// plrs_xilinx::register_bits! {
+///     /// Example control bits
+///     pub struct Control(u32) {
+///         /// Enable
+///         ENABLE: enable, set_enable: 0;
+///         /// Mode
+///         MODE: mode, set_mode: 5, 4;
+///     }
+/// }
+/// let mut control = Control::ENABLE;
+/// control.set_mode(2);
+/// assert!(control.enable());
+/// assert_eq!(control.bits(), 0x21);
+/// ```
+#[macro_export]
+macro_rules! register_bits {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident(u32) {
+            $(
+                $(#[$field_meta:meta])*
+                $mask:ident : $get:ident , $set:ident : $msb:literal $(, $lsb:literal)? ;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        $vis struct $name(pub u32);
+
+        #[allow(dead_code)]
+        impl $name {
+            $(
+                $(#[$field_meta])*
+                pub const $mask: $name = $name($crate::register_bits!(@mask $msb $(, $lsb)?));
+            )*
+
+            /// Value with no bits set.
+            pub const fn empty() -> Self {
+                $name(0)
+            }
+
+            /// Returns the raw bits.
+            pub const fn bits(&self) -> u32 {
+                self.0
+            }
+
+            /// Checks if all bits of `other` are set.
+            pub const fn contains(&self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// Checks if any bit of `other` is set.
+            pub const fn intersects(&self, other: Self) -> bool {
+                self.0 & other.0 != 0
+            }
+
+            $(
+                $crate::register_bits!(@field $(#[$field_meta])* $get, $set, $msb $(, $lsb)?);
+            )*
+        }
+
+        impl $crate::register::RegisterValue for $name {
+            fn from_bits(bits: u32) -> Self {
+                $name(bits)
+            }
+
+            fn bits(self) -> u32 {
+                self.0
+            }
+        }
+
+        impl ::std::ops::BitOr for $name {
+            type Output = Self;
+            fn bitor(self, other: Self) -> Self {
+                $name(self.0 | other.0)
+            }
+        }
+
+        impl ::std::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+        }
+
+        impl ::std::ops::BitAnd for $name {
+            type Output = Self;
+            fn bitand(self, other: Self) -> Self {
+                $name(self.0 & other.0)
+            }
+        }
+
+        impl ::std::ops::BitAndAssign for $name {
+            fn bitand_assign(&mut self, other: Self) {
+                self.0 &= other.0;
+            }
+        }
+
+        impl ::std::ops::Not for $name {
+            type Output = Self;
+            fn not(self) -> Self {
+                $name(!self.0)
+            }
+        }
+    };
+    (@mask $bit:literal) => {
+        1u32 << $bit
+    };
+    (@mask $msb:literal, $lsb:literal) => {
+        (u32::MAX >> (31 - $msb)) & (u32::MAX << $lsb)
+    };
+    (@field $(#[$field_meta:meta])* $get:ident, $set:ident, $bit:literal) => {
+        $(#[$field_meta])*
+        pub const fn $get(&self) -> bool {
+            self.0 & (1u32 << $bit) != 0
+        }
+
+        $(#[$field_meta])*
+        pub const fn $set(&mut self, value: bool) {
+            if value {
+                self.0 |= 1u32 << $bit;
+            } else {
+                self.0 &= !(1u32 << $bit);
+            }
+        }
+    };
+    (@field $(#[$field_meta:meta])* $get:ident, $set:ident, $msb:literal, $lsb:literal) => {
+        $(#[$field_meta])*
+        pub const fn $get(&self) -> u32 {
+            (self.0 & $crate::register_bits!(@mask $msb, $lsb)) >> $lsb
+        }
+
+        $(#[$field_meta])*
+        pub const fn $set(&mut self, value: u32) {
+            let mask = $crate::register_bits!(@mask $msb, $lsb);
+            self.0 = (self.0 & !mask) | ((value << $lsb) & mask);
+        }
+    };
+}
@@ -0,0 +1,201 @@
+use crate::axi_quad_spi::SpiMode;
+use crate::error::Error;
+use crate::interrupt::wait_for;
+use embedded_hal::spi::{Operation, SpiDevice};
+use std::time::Duration;
+
+/// Page size of page program
+pub const SPI_NOR_PAGE_SIZE: usize = 256;
+/// Size of the smallest erase sector
+pub const SPI_NOR_SECTOR_SIZE: usize = 4096;
+
+const READ_ID: u8 = 0x9f;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const READ: u8 = 0x03;
+const READ_4B: u8 = 0x13;
+const DUAL_OUTPUT_READ: u8 = 0x3b;
+const DUAL_OUTPUT_READ_4B: u8 = 0x3c;
+const QUAD_OUTPUT_READ: u8 = 0x6b;
+const QUAD_OUTPUT_READ_4B: u8 = 0x6c;
+const PAGE_PROGRAM: u8 = 0x02;
+const PAGE_PROGRAM_4B: u8 = 0x12;
+const SECTOR_ERASE: u8 = 0x20;
+const SECTOR_ERASE_4B: u8 = 0x21;
+
+/// Write in progress bit of the status register
+const STATUS_BUSY: u8 = 0x01;
+
+/// Time to wait for a page program
+const PROGRAM_TIMEOUT: Duration = Duration::from_millis(10);
+/// Time to wait for a sector erase
+const ERASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Read command of [`SpiNor::read`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiNorRead {
+    /// Read on one data line
+    Standard,
+    /// Fast read with the data on two lines, eight dummy cycles
+    DualOutput,
+    /// Fast read with the data on four lines, eight dummy cycles
+    QuadOutput,
+}
+
+impl From<SpiMode> for SpiNorRead {
+    /// Returns the widest read supported by an AXI Quad SPI core in the mode.
+    fn from(mode: SpiMode) -> Self {
+        match mode {
+            SpiMode::Standard => SpiNorRead::Standard,
+            SpiMode::Dual => SpiNorRead::DualOutput,
+            SpiMode::Quad => SpiNorRead::QuadOutput,
+        }
+    }
+}
+
+/// SPI NOR flash commands common to most serial flash devices
+///
+/// Works on any [`SpiDevice`] using the crate error, such as
+/// [`QuadSpiDevice`](crate::QuadSpiDevice). Addresses are three bytes unless four byte
+/// addressing is selected with [`SpiNor::set_four_byte_address`].
+///
+/// Dual and quad reads need a controller that switches the data lines by command, such as an
+/// [`AxiQuadSpi`](crate::AxiQuadSpi) in dual or quad mode.
+pub struct SpiNor<D> {
+    device: D,
+    four_byte_address: bool,
+    read_mode: SpiNorRead,
+}
+
+impl<D: SpiDevice<Error = Error>> SpiNor<D> {
+    /// Creates a flash instance on the device.
+    pub fn new(device: D) -> Self {
+        SpiNor {
+            device,
+            four_byte_address: false,
+            read_mode: SpiNorRead::Standard,
+        }
+    }
+
+    /// Returns the device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Uses the four byte address commands when set.
+    pub fn set_four_byte_address(&mut self, four_byte_address: bool) {
+        self.four_byte_address = four_byte_address;
+    }
+
+    /// Sets the read command, the flash and the controller have to support it.
+    pub fn set_read_mode(&mut self, read_mode: SpiNorRead) {
+        self.read_mode = read_mode;
+    }
+
+    fn command(&self, command: u8, address: u32) -> Vec<u8> {
+        let address = address.to_be_bytes();
+        let mut bytes = vec![command];
+        if self.four_byte_address {
+            bytes.extend_from_slice(&address);
+        } else {
+            bytes.extend_from_slice(&address[1..]);
+        }
+        bytes
+    }
+
+    /// Reads the JEDEC manufacturer and device identification.
+    pub fn read_id(&mut self) -> Result<[u8; 3], Error> {
+        let mut id = [0; 3];
+        self.device
+            .transaction(&mut [Operation::Write(&[READ_ID]), Operation::Read(&mut id)])?;
+        Ok(id)
+    }
+
+    /// Reads status register 1.
+    pub fn read_status(&mut self) -> Result<u8, Error> {
+        let mut status = [0];
+        self.device.transaction(&mut [
+            Operation::Write(&[READ_STATUS]),
+            Operation::Read(&mut status),
+        ])?;
+        Ok(status[0])
+    }
+
+    /// Waits until the program or erase in progress completes.
+    pub fn wait_ready(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        wait_for(None, timeout, || {
+            Ok((self.read_status()? & STATUS_BUSY == 0).then_some(()))
+        })
+    }
+
+    fn write_enable(&mut self) -> Result<(), Error> {
+        self.device.write(&[WRITE_ENABLE])
+    }
+
+    /// Reads flash contents at `address`.
+    pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+        let command = match (self.read_mode, self.four_byte_address) {
+            (SpiNorRead::Standard, false) => READ,
+            (SpiNorRead::Standard, true) => READ_4B,
+            (SpiNorRead::DualOutput, false) => DUAL_OUTPUT_READ,
+            (SpiNorRead::DualOutput, true) => DUAL_OUTPUT_READ_4B,
+            (SpiNorRead::QuadOutput, false) => QUAD_OUTPUT_READ,
+            (SpiNorRead::QuadOutput, true) => QUAD_OUTPUT_READ_4B,
+        };
+        let mut command = self.command(command, address);
+        if self.read_mode != SpiNorRead::Standard {
+            // Eight dummy cycles on one line
+            command.push(0);
+        }
+        self.device
+            .transaction(&mut [Operation::Write(&command), Operation::Read(data)])
+    }
+
+    /// Programs at most a page at `address` and waits for completion.
+    ///
+    /// The data must not cross a page boundary.
+    pub fn page_program(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        let offset = address as usize % SPI_NOR_PAGE_SIZE;
+        if data.is_empty() || offset + data.len() > SPI_NOR_PAGE_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+        let command = if self.four_byte_address {
+            PAGE_PROGRAM_4B
+        } else {
+            PAGE_PROGRAM
+        };
+        let command = self.command(command, address);
+        self.write_enable()?;
+        self.device
+            .transaction(&mut [Operation::Write(&command), Operation::Write(data)])?;
+        self.wait_ready(Some(PROGRAM_TIMEOUT))
+    }
+
+    /// Programs `data` at `address`, split in pages.
+    ///
+    /// The range has to be erased.
+    pub fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let length = (SPI_NOR_PAGE_SIZE - address as usize % SPI_NOR_PAGE_SIZE).min(data.len());
+            self.page_program(address, &data[..length])?;
+            address += length as u32;
+            data = &data[length..];
+        }
+        Ok(())
+    }
+
+    /// Erases the sector containing `address` and waits for completion.
+    pub fn sector_erase(&mut self, address: u32) -> Result<(), Error> {
+        let command = if self.four_byte_address {
+            SECTOR_ERASE_4B
+        } else {
+            SECTOR_ERASE
+        };
+        let command = self.command(command, address);
+        self.write_enable()?;
+        self.device.write(&command)?;
+        self.wait_ready(Some(ERASE_TIMEOUT))
+    }
+}
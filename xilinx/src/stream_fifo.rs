@@ -1,6 +1,11 @@
-#[deny(missing_docs)]
 use crate::error::Error;
-use uio_rs;
+use crate::discovery::{DeviceInfo, Discovery, Selector};
+use crate::handoff::IpInstance;
+use crate::interrupt::{self, wait_for};
+use crate::mmio::{Mmio, Region};
+use crate::{register_bits, register_map};
+use std::path::Path;
+use std::time::Duration;
 
 /// Supported data widths for the AXI Stream FIFO
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -34,43 +39,138 @@ impl StreamFifoValue {
             _ => None,
         }
     }
+
+    /// Returns the data width of a FIFO in a hardware handoff.
+    ///
+    /// `C_S_AXIS_TDATA_WIDTH` with the AXI4 data interface (`C_DATA_INTERFACE_TYPE` 1), 32 bits
+    /// with the AXI4-Lite data interface. Fails with [`Error::Unsupported`] for widths the
+    /// driver cannot access, above 128 bits.
+    pub fn try_from_ip(ip: &IpInstance) -> Result<Self, Error> {
+        if ip.parameter_u64("C_DATA_INTERFACE_TYPE")? == 0 {
+            return Ok(Self::U32);
+        }
+        let bits = ip.parameter_u64("C_S_AXIS_TDATA_WIDTH")?;
+        match Self::try_from_bits(bits as usize) {
+            Some(Self::U256 | Self::U512) | None => Err(Error::Unsupported),
+            Some(width) => Ok(width),
+        }
+    }
 }
 
 /// Represents an AXI Stream FIFO device.
 pub struct StreamFifo {
     data_width: StreamFifoValue,
-    axi_lite: uio_rs::Map,
-    axi: Option<uio_rs::Map>,
+    axi_lite: Mmio,
+    axi: Option<Mmio>,
+    interrupt: Option<interrupt::Interrupt>,
 }
 
 impl StreamFifo {
-
     /// Creates a new `StreamFifo` instance from a UIO device.
+    ///
+    /// Fails with [`Error::Unsupported`] for data widths above 128 bits.
     pub fn try_from(
-        device: &uio_rs::Device,
+        device: &DeviceInfo,
         data_width: StreamFifoValue,
     ) -> Result<StreamFifo, Error> {
         let map_descriptions = device.maps();
         if map_descriptions.len() >= 2 {
-            let axi_lite = uio_rs::Map::try_from_device(device, 0)?;
-            let axi = uio_rs::Map::try_from_device(device, 1)?;
-            Ok(StreamFifo {
-                data_width,
-                axi_lite,
-                axi: Some(axi),
-            })
+            let axi_lite = Mmio::try_from_device(device, 0)?;
+            let axi = Mmio::try_from_device(device, 1)?;
+            Self::from_maps(axi_lite, Some(axi), data_width)
         } else if map_descriptions.len() == 1 {
-            let axi_lite = uio_rs::Map::try_from_device(device, 0)?;
-            Ok(StreamFifo {
-                data_width: StreamFifoValue::U32,
-                axi_lite,
-                axi: None,
-            })
+            let axi_lite = Mmio::try_from_device(device, 0)?;
+            Self::from_maps(axi_lite, None, data_width)
         } else {
             Err(Error::NoMemoryMap)
         }
     }
 
+    /// Creates a new `StreamFifo` instance from the UIO device number, the `N` in `/dev/uioN`.
+    #[deprecated(note = "find the device with `Discovery` and use `StreamFifo::try_from`")]
+    pub fn try_from_uio(number: u16, data_width: StreamFifoValue) -> Result<StreamFifo, Error> {
+        let device = Discovery::default().find(&Selector::Number(number))?;
+        Self::try_from(&device, data_width)
+    }
+
+    /// Creates a new `StreamFifo` instance from physical memory regions mapped through `/dev/mem`.
+    ///
+    /// The first region is the AXI4-Lite register interface, the optional second region is the
+    /// AXI4 data interface.
+    pub fn try_from_regions(
+        regions: &[Region],
+        data_width: StreamFifoValue,
+    ) -> Result<StreamFifo, Error> {
+        match regions {
+            [axi_lite, axi, ..] => {
+                let axi_lite = Mmio::try_from_region(axi_lite)?;
+                let axi = Mmio::try_from_region(axi)?;
+                Self::from_maps(axi_lite, Some(axi), data_width)
+            }
+            [axi_lite] => {
+                let axi_lite = Mmio::try_from_region(axi_lite)?;
+                Self::from_maps(axi_lite, None, data_width)
+            }
+            [] => Err(Error::NoMemoryMap),
+        }
+    }
+
+    /// Creates a new `StreamFifo` instance from the `reg` property of a device tree node.
+    pub fn try_from_device_tree(
+        node: &Path,
+        data_width: StreamFifoValue,
+    ) -> Result<StreamFifo, Error> {
+        Self::try_from_regions(&crate::devicetree::reg(node)?, data_width)
+    }
+
+    /// Creates a new `StreamFifo` instance from an IP instance of a hardware handoff, mapped
+    /// through `/dev/mem`.
+    ///
+    /// The data width and interfaces follow the IP configuration. To access the FIFO through
+    /// UIO instead, find the device by [`IpInstance::base_address`] and pass
+    /// [`StreamFifoValue::try_from_ip`] to [`StreamFifo::try_from`].
+    pub fn try_from_ip(ip: &IpInstance) -> Result<StreamFifo, Error> {
+        let data_width = StreamFifoValue::try_from_ip(ip)?;
+        let axi_lite = ip.range("S_AXI").ok_or(Error::NoMemoryMap)?;
+        let mut regions = vec![axi_lite.region()];
+        if ip.parameter_u64("C_DATA_INTERFACE_TYPE")? != 0 {
+            let axi = ip.range("S_AXI_FULL").ok_or(Error::NoMemoryMap)?;
+            regions.push(axi.region());
+        }
+        Self::try_from_regions(&regions, data_width)
+    }
+
+    /// Creates a new `StreamFifo` instance from memory mapped regions.
+    ///
+    /// Without an AXI4 data interface the data width is always 32 bits. Fails with
+    /// [`Error::Unsupported`] for data widths above 128 bits, which cannot be accessed as a
+    /// single word.
+    pub fn from_maps(
+        axi_lite: Mmio,
+        axi: Option<Mmio>,
+        data_width: StreamFifoValue,
+    ) -> Result<StreamFifo, Error> {
+        let data_width = match (&axi, data_width) {
+            (None, _) => StreamFifoValue::U32,
+            (Some(_), StreamFifoValue::U256 | StreamFifoValue::U512) => {
+                return Err(Error::Unsupported);
+            }
+            (Some(_), data_width) => data_width,
+        };
+        Ok(StreamFifo {
+            data_width,
+            axi_lite,
+            axi,
+            interrupt: None,
+        })
+    }
+
+    /// Sets the interrupt to sleep on while waiting, such as an [`AxiIntc`](crate::AxiIntc)
+    /// source. Without an interrupt the status register is polled.
+    pub fn set_interrupt(&mut self, interrupt: Option<interrupt::Interrupt>) {
+        self.interrupt = interrupt;
+    }
+
     /// Returns the data width of the FIFO.
     pub fn data_width(&self) -> StreamFifoValue {
         self.data_width
@@ -78,19 +178,15 @@ impl StreamFifo {
 
     /// Resets the AXI Stream FIFO.
     pub fn reset(&mut self) -> Result<(), Error> {
-        self.axi_lite
-            .write_u32(REG_AXI4_STREAM_RESET, RESET_MAGIC)?;
-        self.axi_lite.write_u32(REG_TX_RESET, RESET_MAGIC)?;
-        self.axi_lite.write_u32(REG_RX_RESET, RESET_MAGIC)?;
-        self.axi_lite.write_u32(
-            REG_INTERRUPT_ENABLE,
-            INTERRUPT_TX_COMPLETE
-                | INTERRUPT_RX_COMPLETE
-                | INTERRUPT_RX_UNDER_READ
-                | INTERRUPT_RX_OVER_READ
-                | INTERRUPT_RX_UNDER_RUN
-                | INTERRUPT_TX_OVER_RUN
-                | INTERRUPT_TX_LENGTH_MISMATCH,
+        self.axi_lite.write(Registers::SRR, RESET_MAGIC)?;
+        self.axi_lite.write(Registers::TDFR, RESET_MAGIC)?;
+        self.axi_lite.write(Registers::RDFR, RESET_MAGIC)?;
+        self.axi_lite.write(
+            Registers::IER,
+            Interrupt::TX_COMPLETE
+                | Interrupt::RX_COMPLETE
+                | Interrupt::RX_ERROR
+                | Interrupt::TX_ERROR,
         )?;
         self.interrupts_clear()?;
         Ok(())
@@ -98,43 +194,46 @@ impl StreamFifo {
 
     /// Clears all interrupts for the AXI Stream FIFO.
     pub fn interrupts_clear(&mut self) -> Result<(), Error> {
-        self.axi_lite
-            .write_u32(REG_INTERRUPT_STATUS, INTERRUPT_ALL)
-            .map_err(|e| e.into())
+        self.axi_lite.write(Registers::ISR, Interrupt::ALL)
     }
 
     /// Clears all RX interrupts for the AXI Stream FIFO.
     pub fn interrupts_clear_rx(&mut self) -> Result<(), Error> {
         self.axi_lite
-            .write_u32(
-                REG_INTERRUPT_STATUS,
-                INTERRUPT_RX_ERROR | INTERRUPT_RX_COMPLETE,
-            )
-            .map_err(|e| e.into())
+            .write(Registers::ISR, Interrupt::RX_ERROR | Interrupt::RX_COMPLETE)
     }
 
     /// Clears all TX interrupts for the AXI Stream FIFO.
     pub fn interrupts_clear_tx(&mut self) -> Result<(), Error> {
         self.axi_lite
-            .write_u32(
-                REG_INTERRUPT_STATUS,
-                INTERRUPT_TX_ERROR | INTERRUPT_TX_COMPLETE,
-            )
-            .map_err(|e| e.into())
+            .write(Registers::ISR, Interrupt::TX_ERROR | Interrupt::TX_COMPLETE)
+    }
+
+    /// Waits until the receive FIFO holds data.
+    ///
+    /// The receive complete interrupt is acknowledged, so a level interrupt does not fire again
+    /// for packets already in the FIFO.
+    pub fn wait_receive(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let axi_lite = &mut self.axi_lite;
+        wait_for(self.interrupt.as_ref(), timeout, || {
+            // Acknowledge before checking, a packet arriving afterwards raises it again
+            axi_lite.write(Registers::ISR, Interrupt::RX_COMPLETE)?;
+            Ok((axi_lite.read(Registers::RDFO)? != 0).then_some(()))
+        })
     }
 
     /// Reads bytes from the AXI Stream FIFO.
     pub fn read_bytes(&mut self, data: &mut [u8]) -> Result<(usize, u8), Error> {
-        let occupancy = self.axi_lite.read_u32(REG_RX_OCCUPANCY)?;
+        let occupancy = self.axi_lite.read(Registers::RDFO)?;
         if occupancy == 0 {
             return Err(Error::Empty);
         }
-        // REG_RX_DATA and REG_RX_LENGTH seems to fail
+        // RDFD and RLR seems to fail
         // with bus error if there has been no transfer.
         self.interrupts_clear_rx()?;
-        let packet_bytes = (self.axi_lite.read_u32(REG_RX_LENGTH)? & 0x003fffff) as usize;
+        let packet_bytes = self.axi_lite.read(Registers::RLR)?.length() as usize;
         let read_bytes = data.len().min(packet_bytes);
-        let destination = self.axi_lite.read_u32(REG_RX_DESTINATION)? as u8;
+        let destination = self.axi_lite.read(Registers::RDR)?.destination() as u8;
         log::debug!(
             "Occupancy {} Receive {} bytes {} bytes {} bytes expected ",
             occupancy,
@@ -143,51 +242,33 @@ impl StreamFifo {
             data.len()
         );
         let fifo_word_size = self.data_width.byte_count();
-        let read_count = (read_bytes + (fifo_word_size - 1)) / fifo_word_size;
-
-        // This access is hard to get right without getting double or more reads on the register for each call.
-        // The following reasons that this is because of the memcpy call in arm64 libc.
-        // https://adaptivesupport.amd.com/s/question/0D54U00008Z19O5SAJ/why-are-my-uio-accesses-from-python-being-done-twice-in-the-logic-using-petalinuxvivado-20241?language=en_US
-        // To convert the memory mapped byte slice to a u32 seems to work in this case...
+        let read_count = read_bytes.div_ceil(fifo_word_size);
 
+        // Each FIFO word must be read with exactly one access of the data width,
+        // any additional access pops another word from the FIFO. `Mmio` guarantees this.
         if let Some(ref axi) = self.axi {
             for n in 0..read_count {
                 let offset = n * fifo_word_size;
-                let fifo_chunk = axi.read_exact(FULL_REG_READ, fifo_word_size)?;
-                match self.data_width() {
-                    StreamFifoValue::U32 => {
-                        let v = u32::from_ne_bytes(fifo_chunk.try_into().unwrap());
-                        data[offset..offset + fifo_word_size].copy_from_slice(&v.to_ne_bytes());
-                    }
-                    StreamFifoValue::U64 => {
-                        let v = u64::from_ne_bytes(fifo_chunk.try_into().unwrap());
-                        data[offset..offset + fifo_word_size].copy_from_slice(&v.to_ne_bytes());
-                    }
-                    StreamFifoValue::U128 => {
-                        let v = u128::from_ne_bytes(fifo_chunk.try_into().unwrap());
-                        data[offset..offset + fifo_word_size].copy_from_slice(&v.to_ne_bytes());
-                    }
-                    StreamFifoValue::U256 | StreamFifoValue::U512 => {
-                        unimplemented!()
-                    }
-                }
+                read_word(
+                    axi,
+                    self.data_width,
+                    &mut data[offset..offset + fifo_word_size],
+                )?;
             }
         } else {
             for n in 0..read_count {
                 let offset = n * fifo_word_size;
-                let v = self.axi_lite.read_u32(REG_RX_DATA)?;
+                let v = self.axi_lite.read(Registers::RDFD)?;
                 data[offset..offset + fifo_word_size].copy_from_slice(&v.to_ne_bytes());
             }
         }
-        let interrupts = self.axi_lite.read_u32(REG_INTERRUPT_STATUS)?;
-        if (interrupts & INTERRUPT_RX_ERROR) != 0 {
-            log::warn!("Receive error, {:08x}", interrupts);
+        let interrupts = self.axi_lite.read(Registers::ISR)?;
+        if interrupts.intersects(Interrupt::RX_ERROR) {
+            log::warn!("Receive error, {:08x}", interrupts.bits());
             self.reset()?;
-            let error = if (interrupts & INTERRUPT_RX_OVER_READ) == INTERRUPT_RX_OVER_READ {
+            let error = if interrupts.rx_over_read() {
                 Error::OverRun
-            } else if (interrupts & INTERRUPT_RX_UNDER_READ) == INTERRUPT_RX_UNDER_READ
-                || (interrupts & INTERRUPT_RX_UNDER_RUN) == INTERRUPT_RX_UNDER_RUN
-            {
+            } else if interrupts.rx_under_read() || interrupts.rx_under_run() {
                 Error::UnderRun
             } else {
                 unreachable!();
@@ -200,12 +281,12 @@ impl StreamFifo {
     /// Writes bytes to the AXI Stream FIFO.
     pub fn write_bytes(&mut self, data: &[u8], destination: u8) -> Result<usize, Error> {
         let fifo_word_size = self.data_width.byte_count();
-        let word_count = (data.len() + (fifo_word_size - 1)) / fifo_word_size;
+        let word_count = data.len().div_ceil(fifo_word_size);
         let mut buffer = [0u8; 64];
 
         self.interrupts_clear_tx()?;
 
-        let vacancy = self.axi_lite.read_u32(REG_TX_VACANCY)? as usize;
+        let vacancy = self.axi_lite.read(Registers::TDFV)? as usize;
         if vacancy < word_count {
             log::warn!(
                 "Not enough vacant words, {} vacant, {} required",
@@ -215,8 +296,9 @@ impl StreamFifo {
             return Err(Error::Full);
         }
 
-        self.axi_lite
-            .write_u32(REG_TX_DESTINATION, u32::from(destination & 0x0f))?;
+        let mut tdr = Destination::empty();
+        tdr.set_destination(u32::from(destination));
+        self.axi_lite.write(Registers::TDR, tdr)?;
 
         let iter = data.chunks_exact(fifo_word_size);
         let remainder = iter.remainder();
@@ -231,105 +313,57 @@ impl StreamFifo {
         );
 
         let num_bytes = if let Some(ref mut axi) = self.axi {
-            // It seems like it is not possible to just copy slices of the same size to the FIFO data register.
-            // Following type shenanigans seems to work.
-
-            for chunk in iter.into_iter() {
-                match self.data_width {
-                    StreamFifoValue::U32 => {
-                        axi.write_u32(
-                            FULL_REG_WRITE,
-                            u32::from_ne_bytes(chunk.try_into().unwrap()),
-                        )?;
-                    }
-                    StreamFifoValue::U64 => {
-                        axi.write_u64(
-                            FULL_REG_WRITE,
-                            u64::from_ne_bytes(chunk.try_into().unwrap()),
-                        )?;
-                    }
-                    StreamFifoValue::U128 => {
-                        axi.write_u128(
-                            FULL_REG_WRITE,
-                            u128::from_ne_bytes(chunk.try_into().unwrap()),
-                        )?;
-                    }
-                    _ => {
-                        unimplemented!();
-                    }
-                }
+            for chunk in iter {
+                write_word(axi, self.data_width, chunk)?;
             }
-            if remainder.len() > 0 {
+            if !remainder.is_empty() {
                 buffer[..remainder.len()].copy_from_slice(remainder);
-                let part = &buffer[..fifo_word_size];
-                match self.data_width {
-                    StreamFifoValue::U32 => {
-                        axi.write_u32(
-                            FULL_REG_WRITE,
-                            u32::from_ne_bytes(part.try_into().unwrap()),
-                        )?;
-                    }
-                    StreamFifoValue::U64 => {
-                        axi.write_u64(
-                            FULL_REG_WRITE,
-                            u64::from_ne_bytes(part.try_into().unwrap()),
-                        )?;
-                    }
-                    StreamFifoValue::U128 => {
-                        axi.write_u128(
-                            FULL_REG_WRITE,
-                            u128::from_ne_bytes(part.try_into().unwrap()),
-                        )?;
-                    }
-                    _ => {
-                        unimplemented!();
-                    }
-                }
+                write_word(axi, self.data_width, &buffer[..fifo_word_size])?;
             }
             data.len()
         } else {
             for chunk in iter {
                 self.axi_lite
-                    .write_u32(REG_TX_DATA, u32::from_ne_bytes(chunk.try_into().unwrap()))?;
+                    .write(Registers::TDFD, u32::from_ne_bytes(chunk.try_into().unwrap()))?;
             }
-            if remainder.len() > 0 {
+            if !remainder.is_empty() {
                 buffer[..remainder.len()].copy_from_slice(remainder);
                 let part = &buffer[..fifo_word_size];
                 self.axi_lite
-                    .write_u32(REG_TX_DATA, u32::from_ne_bytes(part.try_into().unwrap()))?;
+                    .write(Registers::TDFD, u32::from_ne_bytes(part.try_into().unwrap()))?;
             }
             data.len()
         };
 
         log::debug!("Transmit {} bytes", num_bytes);
-        self.axi_lite.write_u32(REG_TX_LENGTH, num_bytes as u32)?;
-        loop {
-            let interrupts = self.axi_lite.read_u32(REG_INTERRUPT_STATUS)?;
-            if interrupts & INTERRUPT_TX_ERROR != 0 {
-                log::warn!("Transmit error, {:08x}", interrupts);
-                self.reset()?;
-                let error = if (interrupts & INTERRUPT_TX_OVER_RUN) == INTERRUPT_TX_OVER_RUN {
-                    Error::OverRun
-                } else if (interrupts & INTERRUPT_TX_LENGTH_MISMATCH)
-                    == INTERRUPT_TX_LENGTH_MISMATCH
-                {
-                    Error::LengthMismatch
-                } else {
-                    unreachable!();
-                };
-                return Err(error);
-            }
-            if interrupts & INTERRUPT_TX_COMPLETE != 0 {
-                break;
-            }
+        self.axi_lite.write(Registers::TLR, num_bytes as u32)?;
+        let axi_lite = &self.axi_lite;
+        let interrupts = wait_for(self.interrupt.as_ref(), None, || {
+            let interrupts = axi_lite.read(Registers::ISR)?;
+            Ok(interrupts
+                .intersects(Interrupt::TX_ERROR | Interrupt::TX_COMPLETE)
+                .then_some(interrupts))
+        })?;
+        if interrupts.intersects(Interrupt::TX_ERROR) {
+            log::warn!("Transmit error, {:08x}", interrupts.bits());
+            self.reset()?;
+            let error = if interrupts.tx_over_run() {
+                Error::OverRun
+            } else if interrupts.tx_length_mismatch() {
+                Error::LengthMismatch
+            } else {
+                unreachable!();
+            };
+            return Err(error);
         }
+        self.axi_lite.write(Registers::ISR, Interrupt::TX_COMPLETE)?;
         Ok(num_bytes)
     }
 
     /// Writes data to the AXI Stream FIFO.
     pub fn write(&mut self, data: &[u32], destination: u8) -> Result<usize, Error> {
         let bytes = {
-            let len = size_of::<u32>() * data.len();
+            let len = std::mem::size_of_val(data);
             let ptr = data.as_ptr() as *const u8;
             unsafe {
                 std::slice::from_raw_parts(ptr, len)
@@ -339,76 +373,133 @@ impl StreamFifo {
     }
 }
 
+/// Reads one FIFO word from the AXI4 data interface.
+///
+/// Fails with [`Error::Unsupported`] for data widths above 128 bits.
+fn read_word(axi: &Mmio, data_width: StreamFifoValue, word: &mut [u8]) -> Result<(), Error> {
+    match data_width {
+        StreamFifoValue::U32 => word.copy_from_slice(&axi.read_u32(FULL_REG_READ)?.to_ne_bytes()),
+        StreamFifoValue::U64 => word.copy_from_slice(&axi.read_u64(FULL_REG_READ)?.to_ne_bytes()),
+        StreamFifoValue::U128 => {
+            word.copy_from_slice(&axi.read_u128(FULL_REG_READ)?.to_ne_bytes())
+        }
+        StreamFifoValue::U256 | StreamFifoValue::U512 => return Err(Error::Unsupported),
+    }
+    Ok(())
+}
+
+/// Writes one FIFO word to the AXI4 data interface.
+///
+/// Fails with [`Error::Unsupported`] for data widths above 128 bits.
+fn write_word(axi: &mut Mmio, data_width: StreamFifoValue, word: &[u8]) -> Result<(), Error> {
+    match data_width {
+        StreamFifoValue::U32 => {
+            axi.write_u32(FULL_REG_WRITE, u32::from_ne_bytes(word.try_into().unwrap()))
+        }
+        StreamFifoValue::U64 => {
+            axi.write_u64(FULL_REG_WRITE, u64::from_ne_bytes(word.try_into().unwrap()))
+        }
+        StreamFifoValue::U128 => {
+            axi.write_u128(FULL_REG_WRITE, u128::from_ne_bytes(word.try_into().unwrap()))
+        }
+        StreamFifoValue::U256 | StreamFifoValue::U512 => Err(Error::Unsupported),
+    }
+}
+
 /// AXI Stream FIFO reset word
 const RESET_MAGIC: u32 = 0x000000A5;
 
-// AXI-lite registers
-const REG_INTERRUPT_STATUS: usize = 0x00;
-const REG_INTERRUPT_ENABLE: usize = 0x04;
-const REG_TX_RESET: usize = 0x08;
-const REG_TX_VACANCY: usize = 0x0c;
-const REG_TX_DATA: usize = 0x10;
-const REG_TX_LENGTH: usize = 0x14;
-/// Receiver reset
-const REG_RX_RESET: usize = 0x18;
-/// Receiver occupancy, number of location used for data storage
-const REG_RX_OCCUPANCY: usize = 0x1c;
-/// Data register, where the FIFO is read
-const REG_RX_DATA: usize = 0x20;
-/// Receive length register, number of bytes in the next "packet"
-const REG_RX_LENGTH: usize = 0x24;
-
-const REG_AXI4_STREAM_RESET: usize = 0x28;
-const REG_TX_DESTINATION: usize = 0x2c;
-const REG_RX_DESTINATION: usize = 0x30;
-
-// AXI4 registers
+register_map! {
+    /// AXI4-Lite registers
+    struct Registers {
+        /// Interrupt status register
+        ISR: Interrupt, W1C @ 0x00;
+        /// Interrupt enable register
+        IER: Interrupt, RW @ 0x04;
+        /// Transmit data FIFO reset
+        TDFR: u32, WO @ 0x08;
+        /// Transmit data FIFO vacancy, number of free locations
+        TDFV: u32, RO @ 0x0c;
+        /// Transmit data FIFO data, 32-bit data interface
+        TDFD: u32, WO @ 0x10;
+        /// Transmit length, number of bytes in the "packet", starts the transmission
+        TLR: u32, WO @ 0x14;
+        /// Receive data FIFO reset
+        RDFR: u32, WO @ 0x18;
+        /// Receive data FIFO occupancy, number of locations used for data storage
+        RDFO: u32, RO @ 0x1c;
+        /// Receive data FIFO data, where the FIFO is read on the 32-bit data interface
+        RDFD: u32, RO @ 0x20;
+        /// Receive length, number of bytes in the next "packet"
+        RLR: ReceiveLength, RO @ 0x24;
+        /// AXI4-Stream reset
+        SRR: u32, WO @ 0x28;
+        /// Transmit destination
+        TDR: Destination, WO @ 0x2c;
+        /// Receive destination
+        RDR: Destination, RO @ 0x30;
+    }
+}
+
+register_bits! {
+    /// Interrupt status and enable bits
+    struct Interrupt(u32) {
+        /// Receive under-read interrupt
+        RX_UNDER_READ: rx_under_read, set_rx_under_read: 31;
+        /// Receive over-read interrupt
+        RX_OVER_READ: rx_over_read, set_rx_over_read: 30;
+        /// Receive under run (empty) interrupt
+        RX_UNDER_RUN: rx_under_run, set_rx_under_run: 29;
+        /// Transmit overrun interrupt
+        TX_OVER_RUN: tx_over_run, set_tx_over_run: 28;
+        /// Transmit complete interrupt
+        TX_COMPLETE: tx_complete, set_tx_complete: 27;
+        /// Receive complete interrupt
+        RX_COMPLETE: rx_complete, set_rx_complete: 26;
+        /// Transmit length mismatch interrupt
+        TX_LENGTH_MISMATCH: tx_length_mismatch, set_tx_length_mismatch: 25;
+        /// Transmit reset complete interrupt
+        TX_RESET_COMPLETE: tx_reset_complete, set_tx_reset_complete: 24;
+        /// Receive reset complete interrupt
+        RX_RESET_COMPLETE: rx_reset_complete, set_rx_reset_complete: 23;
+        /// Tx FIFO Programmable Full interrupt
+        TX_PROGRAMMABLE_FULL: tx_programmable_full, set_tx_programmable_full: 22;
+        /// Tx FIFO Programmable Empty interrupt
+        TX_PROGRAMMABLE_EMPTY: tx_programmable_empty, set_tx_programmable_empty: 21;
+        /// Rx FIFO Programmable Full interrupt
+        RX_PROGRAMMABLE_FULL: rx_programmable_full, set_rx_programmable_full: 20;
+        /// Rx FIFO Programmable Empty interrupt
+        RX_PROGRAMMABLE_EMPTY: rx_programmable_empty, set_rx_programmable_empty: 19;
+        /// All interrupts
+        ALL: all, set_all: 31, 19;
+    }
+}
+
+impl Interrupt {
+    /// Receive Error status interrupts
+    const RX_ERROR: Interrupt = Interrupt(
+        Interrupt::RX_UNDER_RUN.0 | Interrupt::RX_OVER_READ.0 | Interrupt::RX_UNDER_READ.0,
+    );
+    /// Transmit Error status interrupts
+    const TX_ERROR: Interrupt = Interrupt(Interrupt::TX_OVER_RUN.0 | Interrupt::TX_LENGTH_MISMATCH.0);
+}
+
+register_bits! {
+    /// Receive length register
+    struct ReceiveLength(u32) {
+        /// Packet length in bytes
+        LENGTH: length, set_length: 21, 0;
+    }
+}
+
+register_bits! {
+    /// Transmit and receive destination registers
+    struct Destination(u32) {
+        /// AXI4-Stream TDEST
+        DESTINATION: destination, set_destination: 3, 0;
+    }
+}
+
+// AXI4 data interface, accessed with the width of the FIFO data
 const FULL_REG_WRITE: usize = 0x00000000;
 const FULL_REG_READ: usize = 0x00001000;
-
-// Interrupts
-/// Receive under-read interrupt
-const INTERRUPT_RX_UNDER_READ: u32 = 0x80000000;
-/// Receive over-read interrupt
-const INTERRUPT_RX_OVER_READ: u32 = 0x40000000;
-/// Receive under run (empty) interrupt
-const INTERRUPT_RX_UNDER_RUN: u32 = 0x20000000;
-/// Transmit overrun interrupt
-const INTERRUPT_TX_OVER_RUN: u32 = 0x10000000;
-/// Transmit complete interrupt
-const INTERRUPT_TX_COMPLETE: u32 = 0x08000000;
-/// Receive complete interrupt
-const INTERRUPT_RX_COMPLETE: u32 = 0x04000000;
-/// Transmit length mismatch interrupt
-const INTERRUPT_TX_LENGTH_MISMATCH: u32 = 0x02000000;
-/// Transmit reset complete interrupt
-const INTERRUPT_TX_RESET_COMPLETE: u32 = 0x01000000;
-/// Receive reset complete interrupt
-const INTERRUPT_RX_RESET_COMPLETE: u32 = 0x00800000;
-/// Tx FIFO Programmable Full interrupt
-const INTERRUPT_TX_PROGRAMMABLE_FULL: u32 = 0x00400000;
-/// Tx FIFO Programmable Empty interrupt
-const INTERRUPT_TX_PROGRAMMABLE_EMPTY: u32 = 0x00200000;
-/// Rx FIFO Programmable Full interrupt
-const INTERRUPT_RX_PROGRAMMABLE_FULL: u32 = 0x00100000;
-/// Rx FIFO Programmable Empty interrupt
-const INTERRUPT_RX_PROGRAMMABLE_EMPTY: u32 = 0x00080000;
-/// All interrupts
-const INTERRUPT_ALL: u32 = INTERRUPT_RX_PROGRAMMABLE_EMPTY
-    | INTERRUPT_RX_PROGRAMMABLE_FULL
-    | INTERRUPT_TX_PROGRAMMABLE_EMPTY
-    | INTERRUPT_TX_PROGRAMMABLE_FULL
-    | INTERRUPT_RX_RESET_COMPLETE
-    | INTERRUPT_TX_RESET_COMPLETE
-    | INTERRUPT_TX_LENGTH_MISMATCH
-    | INTERRUPT_RX_COMPLETE
-    | INTERRUPT_TX_COMPLETE
-    | INTERRUPT_TX_OVER_RUN
-    | INTERRUPT_RX_UNDER_RUN
-    | INTERRUPT_RX_OVER_READ
-    | INTERRUPT_RX_UNDER_READ;
-/// Receive Error status interrupts
-const INTERRUPT_RX_ERROR: u32 =
-    INTERRUPT_RX_UNDER_RUN | INTERRUPT_RX_OVER_READ | INTERRUPT_RX_UNDER_READ;
-/// Transmit Error status interrupts
-const INTERRUPT_TX_ERROR: u32 = INTERRUPT_TX_OVER_RUN | INTERRUPT_TX_LENGTH_MISMATCH;
@@ -0,0 +1,68 @@
+//! Fake sysfs and configfs trees for tests
+
+use crate::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Temporary directory standing in for a sysfs or configfs root, removed on drop
+pub(crate) struct FakeRoot {
+    dir: tempfile::TempDir,
+}
+
+impl FakeRoot {
+    /// Creates an empty root.
+    pub(crate) fn new() -> FakeRoot {
+        FakeRoot {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    /// Returns the path of the root.
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Returns `path` below the root.
+    pub(crate) fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    /// Creates a directory below the root with its parents and returns its path.
+    pub(crate) fn create_dir<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = self.join(path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Writes a file below the root, creating its directory, and returns its path.
+    pub(crate) fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> PathBuf {
+        let path = self.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Creates a symbolic link below the root pointing to `target`.
+    pub(crate) fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, target: P, link: Q) {
+        let link = self.join(link);
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(target, link).unwrap();
+    }
+}
+
+/// Creates a fake root, lets `setup` populate it and returns the root with the result.
+pub(crate) fn fake_root<T, F: FnOnce(&FakeRoot) -> T>(setup: F) -> (FakeRoot, T) {
+    let root = FakeRoot::new();
+    let value = setup(&root);
+    (root, value)
+}
+
+/// Checks that the enumerated entries have the expected names in the expected order.
+pub(crate) fn assert_enumerated<T, F: Fn(&T) -> String>(
+    entries: Result<Vec<T>, Error>,
+    name: F,
+    expected: &[&str],
+) {
+    let names: Vec<String> = entries.unwrap().iter().map(name).collect();
+    assert_eq!(names, expected);
+}